@@ -0,0 +1,8 @@
+#!/bin/bash
+
+set -eux
+
+cargo build --release
+c++ -std=c++17 -Wall -Wextra -I include -o cpp/example cpp/example.cpp \
+    target/release/libautomatic_clahe_ffi.a -lpthread -ldl -lm
+./cpp/example
@@ -0,0 +1,5 @@
+#!/bin/bash
+
+set -eux
+
+cbindgen --config cbindgen.toml --crate automatic-clahe-ffi --output include/automatic_clahe.h
@@ -0,0 +1,70 @@
+#pragma once
+
+#include <cstdint>
+#include <memory>
+#include <stdexcept>
+#include <string>
+#include <vector>
+
+#include "automatic_clahe.h"
+
+namespace automatic_clahe {
+
+class Error : public std::runtime_error {
+ public:
+  explicit Error(AutomaticClaheStatus status)
+      : std::runtime_error(message(status)), status_(status) {}
+
+  AutomaticClaheStatus status() const noexcept { return status_; }
+
+ private:
+  static std::string message(AutomaticClaheStatus status) {
+    switch (status) {
+      case AUTOMATIC_CLAHE_STATUS_OK:
+        return "ok";
+      case AUTOMATIC_CLAHE_STATUS_NULL_POINTER:
+        return "null pointer";
+      case AUTOMATIC_CLAHE_STATUS_INVALID_ARGUMENT:
+        return "invalid argument";
+      case AUTOMATIC_CLAHE_STATUS_PANIC:
+        return "internal error (panic)";
+    }
+    return "unknown status: " + std::to_string(static_cast<int>(status));
+  }
+
+  AutomaticClaheStatus status_;
+};
+
+// RAII owner of an `AutomaticClahe` handle.
+class Enhancer {
+ public:
+  Enhancer() : Enhancer(automatic_clahe_options_default()) {}
+
+  explicit Enhancer(const AutomaticClaheOptions& options)
+      : handle_(automatic_clahe_new(&options)) {
+    if (!handle_) {
+      throw Error(AUTOMATIC_CLAHE_STATUS_INVALID_ARGUMENT);
+    }
+  }
+
+  void enhance_rgba_image(std::uint8_t* pixels, std::size_t len, std::size_t width) const {
+    const AutomaticClaheStatus status =
+        automatic_clahe_enhance_rgba_image(handle_.get(), pixels, len, width);
+    if (status != AUTOMATIC_CLAHE_STATUS_OK) {
+      throw Error(status);
+    }
+  }
+
+  void enhance_rgba_image(std::vector<std::uint8_t>& pixels, std::size_t width) const {
+    enhance_rgba_image(pixels.data(), pixels.size(), width);
+  }
+
+ private:
+  struct Deleter {
+    void operator()(AutomaticClahe* handle) const noexcept { automatic_clahe_free(handle); }
+  };
+
+  std::unique_ptr<AutomaticClahe, Deleter> handle_;
+};
+
+}  // namespace automatic_clahe
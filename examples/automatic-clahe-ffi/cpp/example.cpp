@@ -0,0 +1,43 @@
+#include <cmath>
+#include <cstdint>
+#include <iostream>
+#include <vector>
+
+#include "automatic_clahe.hpp"
+
+int main() {
+  const std::size_t width = 320;
+  const std::size_t height = 240;
+
+  // A dim, low-contrast gradient.
+  std::vector<std::uint8_t> pixels(width * height * 4);
+  for (std::size_t y = 0; y < height; y++) {
+    for (std::size_t x = 0; x < width; x++) {
+      const double v = 40.0 + 20.0 * std::sin(x / 20.0) + 10.0 * std::cos(y / 15.0);
+      std::uint8_t* p = &pixels[(y * width + x) * 4];
+      p[0] = static_cast<std::uint8_t>(v);
+      p[1] = static_cast<std::uint8_t>(v * 0.8);
+      p[2] = static_cast<std::uint8_t>(v * 0.6);
+      p[3] = 255;
+    }
+  }
+
+  AutomaticClaheOptions options = automatic_clahe_options_default();
+  options.block_width = 64;
+  options.block_height = 64;
+
+  try {
+    const automatic_clahe::Enhancer enhancer(options);
+    enhancer.enhance_rgba_image(pixels, width);
+    std::cout << "Enhanced first pixel: " << static_cast<int>(pixels[0]) << ", "
+              << static_cast<int>(pixels[1]) << ", " << static_cast<int>(pixels[2]) << std::endl;
+
+    // Error codes are surfaced as exceptions.
+    enhancer.enhance_rgba_image(pixels.data(), pixels.size() - 1, width);
+  } catch (const automatic_clahe::Error& e) {
+    std::cout << "Error: " << e.what() << " (status=" << static_cast<int>(e.status()) << ")"
+              << std::endl;
+  }
+
+  return 0;
+}
@@ -0,0 +1,68 @@
+#ifndef AUTOMATIC_CLAHE_H
+#define AUTOMATIC_CLAHE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+// Status codes returned by the enhancement functions.
+typedef enum AutomaticClaheStatus {
+  AUTOMATIC_CLAHE_STATUS_OK = 0,
+  AUTOMATIC_CLAHE_STATUS_NULL_POINTER = 1,
+  AUTOMATIC_CLAHE_STATUS_INVALID_ARGUMENT = 2,
+  AUTOMATIC_CLAHE_STATUS_PANIC = 3,
+} AutomaticClaheStatus;
+
+// Opaque enhancer handle.
+typedef struct AutomaticClahe AutomaticClahe;
+
+// C-compatible mirror of `automatic_clahe::AutomaticClaheOptions`.
+typedef struct AutomaticClaheOptions {
+  size_t block_width;
+  size_t block_height;
+  float alpha;
+  float p;
+  uint8_t d_threshold;
+} AutomaticClaheOptions;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Returns the default options.
+struct AutomaticClaheOptions automatic_clahe_options_default(void);
+
+// Creates a new enhancer.
+//
+// `options` may be NULL, in which case the default options are used.
+// Returns NULL if the options are invalid.
+// The returned handle must be released with `automatic_clahe_free()`.
+//
+// # Safety
+//
+// `options` must be NULL or point to a valid `AutomaticClaheOptions`.
+struct AutomaticClahe *automatic_clahe_new(const struct AutomaticClaheOptions *options);
+
+// Releases an enhancer created by `automatic_clahe_new()`.
+//
+// # Safety
+//
+// `clahe` must be NULL or a handle returned by `automatic_clahe_new()` that has not been freed yet.
+void automatic_clahe_free(struct AutomaticClahe *clahe);
+
+// Enhances an RGBA image in place.
+//
+// `len` is the length of `pixels` in bytes and `width` is the image width in pixels.
+//
+// # Safety
+//
+// `clahe` must be a live handle and `pixels` must point to `len` writable bytes.
+enum AutomaticClaheStatus automatic_clahe_enhance_rgba_image(const struct AutomaticClahe *clahe,
+                                                             uint8_t *pixels,
+                                                             size_t len,
+                                                             size_t width);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* AUTOMATIC_CLAHE_H */
@@ -0,0 +1,118 @@
+/// Status codes returned by the enhancement functions.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutomaticClaheStatus {
+    Ok = 0,
+    NullPointer = 1,
+    InvalidArgument = 2,
+    Panic = 3,
+}
+
+/// C-compatible mirror of `automatic_clahe::AutomaticClaheOptions`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    pub block_width: usize,
+    pub block_height: usize,
+    pub alpha: f32,
+    pub p: f32,
+    pub d_threshold: u8,
+}
+
+impl From<automatic_clahe::AutomaticClaheOptions> for Options {
+    fn from(options: automatic_clahe::AutomaticClaheOptions) -> Self {
+        Self {
+            block_width: options.block_width,
+            block_height: options.block_height,
+            alpha: options.alpha,
+            p: options.p,
+            d_threshold: options.d_threshold,
+        }
+    }
+}
+
+impl From<Options> for automatic_clahe::AutomaticClaheOptions {
+    fn from(options: Options) -> Self {
+        Self {
+            block_width: options.block_width,
+            block_height: options.block_height,
+            alpha: options.alpha,
+            p: options.p,
+            d_threshold: options.d_threshold,
+            ..Default::default()
+        }
+    }
+}
+
+/// Opaque enhancer handle.
+pub struct AutomaticClahe(automatic_clahe::AutomaticClahe);
+
+/// Returns the default options.
+#[no_mangle]
+pub extern "C" fn automatic_clahe_options_default() -> Options {
+    automatic_clahe::AutomaticClaheOptions::default().into()
+}
+
+/// Creates a new enhancer.
+///
+/// `options` may be NULL, in which case the default options are used.
+/// Returns NULL if the options are invalid.
+/// The returned handle must be released with `automatic_clahe_free()`.
+///
+/// # Safety
+///
+/// `options` must be NULL or point to a valid `AutomaticClaheOptions`.
+#[no_mangle]
+pub unsafe extern "C" fn automatic_clahe_new(options: *const Options) -> *mut AutomaticClahe {
+    let options = if options.is_null() {
+        automatic_clahe_options_default()
+    } else {
+        *options
+    };
+    let options = automatic_clahe::AutomaticClaheOptions::from(options);
+    if options.validate().is_err() {
+        return std::ptr::null_mut();
+    }
+
+    Box::into_raw(Box::new(AutomaticClahe(
+        automatic_clahe::AutomaticClahe::with_options(options),
+    )))
+}
+
+/// Releases an enhancer created by `automatic_clahe_new()`.
+///
+/// # Safety
+///
+/// `clahe` must be NULL or a handle returned by `automatic_clahe_new()` that has not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn automatic_clahe_free(clahe: *mut AutomaticClahe) {
+    if !clahe.is_null() {
+        drop(Box::from_raw(clahe));
+    }
+}
+
+/// Enhances an RGBA image in place.
+///
+/// `len` is the length of `pixels` in bytes and `width` is the image width in pixels.
+///
+/// # Safety
+///
+/// `clahe` must be a live handle and `pixels` must point to `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn automatic_clahe_enhance_rgba_image(
+    clahe: *const AutomaticClahe,
+    pixels: *mut u8,
+    len: usize,
+    width: usize,
+) -> AutomaticClaheStatus {
+    if clahe.is_null() || pixels.is_null() {
+        return AutomaticClaheStatus::NullPointer;
+    }
+    let clahe = &*clahe;
+    let pixels = std::slice::from_raw_parts_mut(pixels, len);
+    match clahe.0.enhance_rgba_checked(pixels, width) {
+        Ok(()) => AutomaticClaheStatus::Ok,
+        Err(automatic_clahe::ClaheError::Internal) => AutomaticClaheStatus::Panic,
+        Err(_) => AutomaticClaheStatus::InvalidArgument,
+    }
+}
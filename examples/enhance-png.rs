@@ -8,20 +8,230 @@ struct Opt {
     #[structopt(long, default_value = "enhanced.png")]
     output_path: PathBuf,
 
-    #[structopt(long, default_value = "32")]
-    block_width: usize,
+    /// Base option set (subtle, balanced, aggressive, low-light or shadow-lift); the other flags
+    /// override it.
+    #[structopt(long)]
+    preset: Option<automatic_clahe::Preset>,
 
-    #[structopt(long, default_value = "32")]
-    block_height: usize,
+    /// Uses the options recommended by analyzing the image as the base option set
+    #[structopt(long)]
+    recommend: bool,
 
-    #[structopt(long, default_value = "100")]
-    alpha: f32,
+    /// Profile file (JSON or TOML) to use as the base option set; the other flags override it
+    #[cfg(feature = "profiles")]
+    #[structopt(long)]
+    profile: Option<PathBuf>,
 
-    #[structopt(long, default_value = "1.5")]
-    p: f32,
+    /// Saves the resulting options as a profile (JSON or TOML)
+    #[cfg(feature = "profiles")]
+    #[structopt(long)]
+    save_profile: Option<PathBuf>,
 
-    #[structopt(long, default_value = "50")]
-    d_threshold: u8,
+    /// Removes haze (dark channel prior) before the enhancement
+    #[cfg(feature = "dehaze")]
+    #[structopt(long)]
+    dehaze: bool,
+
+    /// Picks the block size from the image resolution (ignores --block-width/--block-height).
+    #[structopt(long)]
+    auto_block_size: bool,
+
+    /// Number of block columns (like OpenCV's tile grid; ignores --block-width) [default: 8]
+    #[structopt(long)]
+    tiles_x: Option<usize>,
+
+    /// Number of block rows (like OpenCV's tile grid; ignores --block-height) [default: 8]
+    #[structopt(long)]
+    tiles_y: Option<usize>,
+
+    /// [default: 32]
+    #[structopt(long)]
+    block_width: Option<usize>,
+
+    /// [default: 32]
+    #[structopt(long)]
+    block_height: Option<usize>,
+
+    /// Overlap of the histogram windows of neighboring blocks in [0.0, 1.0) [default: 0.0]
+    #[structopt(long)]
+    block_overlap: Option<f32>,
+
+    /// Comma-separated block size multipliers of a multi-scale enhancement (e.g. 1,2,4)
+    #[structopt(long, use_delimiter = true)]
+    block_scales: Vec<f32>,
+
+    /// Splits blocks whose luminance standard deviation exceeds this value (quadtree layout)
+    #[structopt(long)]
+    quadtree_sigma: Option<f32>,
+
+    /// Maximum number of quadtree splits of a block [default: 2]
+    #[structopt(long)]
+    quadtree_depth: Option<u32>,
+
+    /// [default: 100]
+    #[structopt(long)]
+    alpha: Option<f32>,
+
+    /// Picks alpha by maximizing the output entropy minus a noise penalty (overrides --alpha)
+    #[structopt(long)]
+    auto_alpha: bool,
+
+    /// [default: 1.5]
+    #[structopt(long)]
+    p: Option<f32>,
+
+    /// Builds the histograms from every n-th pixel of every n-th row [default: 1]
+    #[structopt(long)]
+    histogram_subsampling: Option<usize>,
+
+    /// Caps the number of pixels sampled into each block histogram
+    #[structopt(long)]
+    histogram_sample_budget: Option<usize>,
+
+    /// Clips noisy dark blocks harder in proportion to their noise-to-mean ratio [default: 0]
+    #[structopt(long)]
+    noise_clip_factor: Option<f32>,
+
+    /// Denoises the luminance before the enhancement, relative to its noise level [default: 0]
+    #[structopt(long)]
+    denoise_strength: Option<f32>,
+
+    /// Enhances the luminance in exposure stops (log2), e.g. for astrophotography
+    #[structopt(long)]
+    log_luminance: bool,
+
+    /// [default: 50]
+    #[structopt(long)]
+    d_threshold: Option<u8>,
+
+    /// Leaves blocks with a lower luminance standard deviation unenhanced [default: 0]
+    #[structopt(long)]
+    min_sigma: Option<f32>,
+
+    /// CDF point defining l_alpha of the enhancement weight factor [default: 0.75]
+    #[structopt(long)]
+    l_alpha_percentile: Option<f32>,
+
+    /// Disables the dual gamma correction
+    #[structopt(long)]
+    no_dual_gamma_correction: bool,
+
+    /// Fixed clip limit relative to the average bin height (overrides --alpha and --p)
+    #[structopt(long)]
+    clip_limit: Option<f32>,
+
+    /// Limits the LUT difference between adjacent blocks to this many levels (against halos)
+    #[structopt(long)]
+    max_block_divergence: Option<f32>,
+
+    /// Blend factor between the original and the enhanced image [default: 1.0]
+    #[structopt(long)]
+    strength: Option<f32>,
+
+    /// Spatial ramp of the strength (linear:x0,y0,x1,y1 or radial:x,y,inner,outer, relative
+    /// to the image size)
+    #[structopt(long)]
+    strength_gradient: Option<automatic_clahe::StrengthGradient>,
+
+    /// Comma-separated strengths fused by well-exposedness (e.g. 0.3,0.7,1.0)
+    #[structopt(long, use_delimiter = true)]
+    fusion_strengths: Vec<f32>,
+
+    /// Working color space (hsv, hsv-float, hsl, ycbcr709, ycbcr601, lab, oklab, bt709, bt601,
+    /// average or per-channel) [default: hsv]
+    #[structopt(long)]
+    color_space: Option<automatic_clahe::ColorSpace>,
+
+    /// Write-back of the enhanced luminance (color-space or ratio) [default: color-space]
+    #[structopt(long)]
+    write_back: Option<automatic_clahe::WriteBack>,
+
+    /// Multiplies the saturation of the enhanced pixels [default: 1.0]
+    #[structopt(long)]
+    saturation_factor: Option<f32>,
+
+    /// Desaturates boosted dark pixels to hide chroma noise (0 disables it) [default: 0.0]
+    #[structopt(long)]
+    chroma_noise_reduction: Option<f32>,
+
+    /// Luminance range to leave untouched, as MIN-MAX (may be given multiple times)
+    #[structopt(long, parse(try_from_str = parse_range))]
+    protect: Vec<std::ops::RangeInclusive<u8>>,
+
+    /// Upper limit of the luminance amplification of each pixel
+    #[structopt(long)]
+    max_gain: Option<f32>,
+
+    /// Only brightens luminances up to this value, fading out over --shadow-rolloff levels
+    #[structopt(long)]
+    shadow_threshold: Option<u8>,
+
+    /// Levels above --shadow-threshold over which the shadow lift fades out [default: 64]
+    #[structopt(long)]
+    shadow_rolloff: Option<u8>,
+
+    /// Protects point sources (stars) exceeding their surroundings by this luminance
+    #[structopt(long)]
+    point_source_prominence: Option<u8>,
+
+    /// Luminance above which brightening is compressed by a soft knee
+    #[structopt(long)]
+    highlight_knee: Option<u8>,
+
+    /// Window radius of the edge-aware gain smoothing [default: 8]
+    #[structopt(long)]
+    gain_smoothing_radius: Option<usize>,
+
+    /// Regularization of the edge-aware gain smoothing [default: 0.01]
+    #[structopt(long)]
+    gain_smoothing_epsilon: Option<f32>,
+
+    /// Lowest output luminance [default: 0]
+    #[structopt(long)]
+    output_black: Option<u8>,
+
+    /// Highest output luminance [default: 255]
+    #[structopt(long)]
+    output_white: Option<u8>,
+
+    /// Maps the full luminance range onto --output-black..--output-white instead of clipping
+    #[structopt(long)]
+    scale_output: bool,
+
+    /// Interpolation between blocks (nearest, bilinear, gaussian or sliding-window)
+    /// [default: bilinear]
+    #[structopt(long)]
+    interpolation: Option<automatic_clahe::Interpolation>,
+
+    /// Dithering of the enhanced luminance (none, ordered or floyd-steinberg) [default: none]
+    #[structopt(long)]
+    dither: Option<automatic_clahe::Dither>,
+
+    #[structopt(long)]
+    identity_luts: bool,
+
+    /// Blends u16 fixed-point block LUTs with integer arithmetic
+    #[structopt(long)]
+    fixed_point_luts: bool,
+
+    /// Transfer function of HDR input (pq or hlg)
+    #[structopt(long)]
+    transfer: Option<String>,
+
+    /// Peak luminance of PQ input in cd/m^2 [default: 1000]
+    #[structopt(long)]
+    pq_peak_nits: Option<f32>,
+
+    /// Reference PNG image whose luminance histogram the blocks are matched to
+    #[structopt(long)]
+    reference: Option<PathBuf>,
+}
+
+fn parse_range(s: &str) -> anyhow::Result<std::ops::RangeInclusive<u8>> {
+    let (min, max) = s
+        .split_once('-')
+        .ok_or_else(|| anyhow::anyhow!("expected MIN-MAX: {:?}", s))?;
+    Ok(min.parse()?..=max.parse()?)
 }
 
 fn main() -> anyhow::Result<()> {
@@ -38,14 +248,218 @@ fn main() -> anyhow::Result<()> {
     println!("Image color type: {:?}", info.color_type);
     assert_eq!(info.bit_depth, png::BitDepth::Eight);
 
-    let options = automatic_clahe::AutomaticClaheOptions {
-        block_width: opt.block_width,
-        block_height: opt.block_height,
-        alpha: opt.alpha,
-        p: opt.p,
-        d_threshold: opt.d_threshold,
-    };
-    let enhancer = automatic_clahe::AutomaticClahe::with_options(options);
+    let mut builder = automatic_clahe::AutomaticClaheOptions::builder();
+    if let Some(preset) = opt.preset {
+        builder = builder.preset(preset);
+    }
+    if opt.recommend {
+        let layout = match info.color_type {
+            png::ColorType::Rgba => automatic_clahe::ChannelLayout::RGBA,
+            _ => automatic_clahe::ChannelLayout::RGB,
+        };
+        let recommendation = automatic_clahe::AutomaticClahe::new().analyze(
+            &buf[..info.buffer_size()],
+            info.width as usize,
+            layout,
+        );
+        println!(
+            "Recommended: block={}x{}, alpha={:.1}, d_threshold={} (noise sigma: {:.2})",
+            recommendation.block_width,
+            recommendation.block_height,
+            recommendation.alpha,
+            recommendation.d_threshold,
+            recommendation.noise_sigma
+        );
+        builder = builder.options(recommendation.to_options());
+    }
+    #[cfg(feature = "profiles")]
+    if let Some(path) = &opt.profile {
+        builder = builder.options(automatic_clahe::Profile::load(path)?.options);
+    }
+    if opt.auto_block_size {
+        builder = builder.block_size(automatic_clahe::BlockSize::Auto);
+    }
+    if opt.tiles_x.is_some() || opt.tiles_y.is_some() {
+        builder = builder.block_size(automatic_clahe::BlockSize::Tiles {
+            x: opt.tiles_x.unwrap_or(8),
+            y: opt.tiles_y.unwrap_or(8),
+        });
+    }
+    if let Some(v) = opt.block_width {
+        builder = builder.block_width(v);
+    }
+    if let Some(v) = opt.block_height {
+        builder = builder.block_height(v);
+    }
+    if let Some(v) = opt.block_overlap {
+        builder = builder.block_overlap(v);
+    }
+    if !opt.block_scales.is_empty() {
+        builder = builder.block_scales(opt.block_scales.clone());
+    }
+    if opt.quadtree_sigma.is_some() || opt.quadtree_depth.is_some() {
+        let default = automatic_clahe::QuadtreeSplit::default();
+        builder = builder.quadtree(automatic_clahe::QuadtreeSplit {
+            sigma_threshold: opt.quadtree_sigma.unwrap_or(default.sigma_threshold),
+            max_depth: opt.quadtree_depth.unwrap_or(default.max_depth),
+        });
+    }
+    if let Some(v) = opt.alpha {
+        builder = builder.alpha(v);
+    }
+    if let Some(v) = opt.p {
+        builder = builder.p(v);
+    }
+    if let Some(v) = opt.histogram_subsampling {
+        builder = builder.histogram_subsampling(v);
+    }
+    if let Some(v) = opt.histogram_sample_budget {
+        builder = builder.histogram_sample_budget(v);
+    }
+    if let Some(v) = opt.noise_clip_factor {
+        builder = builder.noise_clip_factor(v);
+    }
+    if let Some(v) = opt.denoise_strength {
+        builder = builder.denoise_strength(v);
+    }
+    if let Some(v) = opt.d_threshold {
+        builder = builder.d_threshold(v);
+    }
+    if let Some(v) = opt.min_sigma {
+        builder = builder.min_sigma(v);
+    }
+    if let Some(v) = opt.l_alpha_percentile {
+        builder = builder.l_alpha_percentile(v);
+    }
+    if let Some(v) = opt.clip_limit {
+        builder = builder.clip_limit(v);
+    }
+    if let Some(v) = opt.max_block_divergence {
+        builder = builder.max_block_divergence(v);
+    }
+    if let Some(v) = opt.strength {
+        builder = builder.strength(v);
+    }
+    if let Some(v) = opt.strength_gradient {
+        builder = builder.strength_gradient(v);
+    }
+    if !opt.fusion_strengths.is_empty() {
+        builder = builder.fusion_strengths(opt.fusion_strengths.clone());
+    }
+    if let Some(v) = opt.color_space {
+        builder = builder.color_space(v);
+    }
+    if let Some(v) = opt.write_back {
+        builder = builder.write_back(v);
+    }
+    if let Some(v) = opt.saturation_factor {
+        builder = builder.saturation_factor(v);
+    }
+    if let Some(v) = opt.chroma_noise_reduction {
+        builder = builder.chroma_noise_reduction(v);
+    }
+    for range in opt.protect {
+        builder = builder.protected_range(range);
+    }
+    if let Some(v) = opt.max_gain {
+        builder = builder.max_gain(v);
+    }
+    if opt.shadow_threshold.is_some() || opt.shadow_rolloff.is_some() {
+        let default = automatic_clahe::ShadowLift::default();
+        builder = builder.shadow_lift(automatic_clahe::ShadowLift {
+            threshold: opt.shadow_threshold.unwrap_or(default.threshold),
+            rolloff: opt.shadow_rolloff.unwrap_or(default.rolloff),
+        });
+    }
+    if let Some(prominence) = opt.point_source_prominence {
+        builder = builder.point_source_protection(automatic_clahe::PointSourceProtection {
+            prominence,
+            ..Default::default()
+        });
+    }
+    if let Some(v) = opt.highlight_knee {
+        builder = builder.highlight_knee(v);
+    }
+    if opt.gain_smoothing_radius.is_some() || opt.gain_smoothing_epsilon.is_some() {
+        let default = automatic_clahe::GuidedFilter::default();
+        builder = builder.gain_smoothing(automatic_clahe::GuidedFilter {
+            radius: opt.gain_smoothing_radius.unwrap_or(default.radius),
+            epsilon: opt.gain_smoothing_epsilon.unwrap_or(default.epsilon),
+        });
+    }
+    if opt.output_black.is_some() || opt.output_white.is_some() {
+        builder = builder.output_range(
+            opt.output_black.unwrap_or(0),
+            opt.output_white.unwrap_or(255),
+        );
+    }
+    if let Some(v) = opt.interpolation {
+        builder = builder.interpolation(v);
+    }
+    if let Some(v) = opt.dither {
+        builder = builder.dither(v);
+    }
+    if opt.scale_output {
+        builder = builder.clamp_mode(automatic_clahe::ClampMode::Scale);
+    }
+    let mut options = builder
+        .dual_gamma_correction(!opt.no_dual_gamma_correction)
+        .identity_luts(opt.identity_luts)
+        .fixed_point_luts(opt.fixed_point_luts)
+        .log_luminance(opt.log_luminance)
+        .build()?;
+    if opt.auto_alpha {
+        let layout = match info.color_type {
+            png::ColorType::Rgba => automatic_clahe::ChannelLayout::RGBA,
+            _ => automatic_clahe::ChannelLayout::RGB,
+        };
+        options.alpha = automatic_clahe::AutomaticClahe::with_options(options.clone())
+            .select_alpha(
+                &buf[..info.buffer_size()],
+                info.width as usize,
+                layout,
+                &automatic_clahe::AlphaSearch::default(),
+            );
+        println!("Selected alpha: {:.1}", options.alpha);
+    }
+    #[cfg(feature = "profiles")]
+    if let Some(path) = &opt.save_profile {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        automatic_clahe::Profile::new(name, options.clone()).save(path)?;
+    }
+    let mut enhancer = automatic_clahe::AutomaticClahe::with_options(options);
+    match opt.transfer.as_deref() {
+        None => {}
+        Some("pq") => {
+            let peak_nits = opt.pq_peak_nits.unwrap_or(1000.0);
+            enhancer =
+                enhancer.with_transfer_function(automatic_clahe::TransferFunction::pq(peak_nits));
+        }
+        Some("hlg") => {
+            enhancer = enhancer.with_transfer_function(automatic_clahe::TransferFunction::hlg());
+        }
+        Some(s) => anyhow::bail!("unknown transfer function: {:?}", s),
+    }
+    #[cfg(feature = "dehaze")]
+    if opt.dehaze {
+        enhancer = enhancer.with_dehaze(Default::default());
+    }
+    if let Some(path) = &opt.reference {
+        let decoder = png::Decoder::new(std::io::BufReader::new(std::fs::File::open(path)?));
+        let mut reader = decoder.read_info()?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf)?;
+        let layout = match info.color_type {
+            png::ColorType::Rgb => automatic_clahe::ChannelLayout::RGB,
+            png::ColorType::Rgba => automatic_clahe::ChannelLayout::RGBA,
+            ty => anyhow::bail!("unsupported reference color type: {:?}", ty),
+        };
+        anyhow::ensure!(
+            info.bit_depth == png::BitDepth::Eight,
+            "unsupported reference bit depth"
+        );
+        enhancer = enhancer.with_reference_image(&buf[..info.buffer_size()], layout);
+    }
     let start = std::time::Instant::now();
     match reader.info().color_type {
         png::ColorType::Rgb => {
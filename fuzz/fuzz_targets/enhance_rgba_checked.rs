@@ -0,0 +1,24 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 12 {
+        return;
+    }
+    let (header, pixels) = data.split_at(12);
+    let options = automatic_clahe::AutomaticClaheOptions {
+        block_width: usize::from(header[0]),
+        block_height: usize::from(header[1]),
+        alpha: f32::from_le_bytes([header[2], header[3], header[4], header[5]]),
+        p: f32::from_le_bytes([header[6], header[7], header[8], header[9]]),
+        d_threshold: header[10],
+        ..Default::default()
+    };
+    let width = usize::from(header[11]);
+
+    let mut pixels = pixels.to_vec();
+    let result = automatic_clahe::AutomaticClahe::with_options(options)
+        .enhance_rgba_checked(&mut pixels, width);
+    assert_ne!(result, Err(automatic_clahe::ClaheError::Internal));
+});
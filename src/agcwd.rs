@@ -0,0 +1,91 @@
+use crate::{
+    Algorithm, AutomaticClahe, AutomaticClaheOptions, BlockSize, ChannelLayout, ClaheError,
+    ColorSpace, Enhancer,
+};
+
+/// Options of [`Agcwd`].
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct AgcwdOptions {
+    pub color_space: ColorSpace,
+}
+
+/// Global adaptive gamma correction with weighting distribution (AGCWD).
+///
+/// This is the gamma of the dual gamma correction applied to the whole image at once: lighter
+/// than CLAHE and free of tiling artifacts, for scenes that don't need local adaptation.
+#[derive(Debug)]
+pub struct Agcwd {
+    inner: AutomaticClahe,
+}
+
+impl Agcwd {
+    pub fn with_options(options: AgcwdOptions) -> Self {
+        let options = AutomaticClaheOptions {
+            block_size: BlockSize::Tiles { x: 1, y: 1 },
+            color_space: options.color_space,
+            ..Default::default()
+        };
+        Self {
+            inner: AutomaticClahe {
+                algorithm: Algorithm::Agcwd,
+                ..AutomaticClahe::with_options(options)
+            },
+        }
+    }
+
+    pub fn new() -> Self {
+        Self::with_options(Default::default())
+    }
+}
+
+impl Default for Agcwd {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Enhancer for Agcwd {
+    fn enhance_image(&self, pixels: &mut [u8], width: usize, layout: ChannelLayout) {
+        self.inner.enhance_image(pixels, width, layout);
+    }
+
+    fn try_enhance_image(
+        &self,
+        pixels: &mut [u8],
+        width: usize,
+        layout: ChannelLayout,
+    ) -> Result<(), ClaheError> {
+        self.inner.try_enhance_image(pixels, width, layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn agcwd_brightens_dark_images_monotonically() {
+        let width = 64;
+        let mut pixels = (0..width * 48)
+            .flat_map(|i| {
+                let l = (i % width + i / width) as u8;
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let original = pixels.clone();
+        Agcwd::new()
+            .try_enhance_rgb_image(&mut pixels, width)
+            .unwrap();
+
+        let mut lut = [None; 256];
+        for (p, o) in pixels.chunks(3).zip(original.chunks(3)) {
+            assert!(p[0] >= o[0]);
+            assert_eq!(*lut[usize::from(o[0])].get_or_insert(p[0]), p[0]);
+        }
+        let lut = lut.iter().flatten().collect::<Vec<_>>();
+        assert!(lut.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(**lut.last().unwrap(), 110);
+    }
+}
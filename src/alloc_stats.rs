@@ -0,0 +1,85 @@
+//! Allocation counting used to fill [`Report::allocations`](crate::Report::allocations).
+//!
+//! Install the allocator in the final binary to enable the counters:
+//!
+//! ```
+//! #[global_allocator]
+//! static ALLOCATOR: automatic_clahe::alloc_stats::CountingAllocator =
+//!     automatic_clahe::alloc_stats::CountingAllocator;
+//! # fn main() {}
+//! ```
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+// Lets the tests check the counters, e.g. that the scratch enhancement does not allocate.
+#[cfg(test)]
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+    static ALLOCATED_BYTES: Cell<u64> = const { Cell::new(0) };
+}
+
+/// A [`System`] wrapper that counts the allocations made by each thread.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CountingAllocator;
+
+impl CountingAllocator {
+    fn record(size: usize) {
+        INSTALLED.store(true, Ordering::Relaxed);
+        let _ = ALLOCATIONS.try_with(|c| c.set(c.get() + 1));
+        let _ = ALLOCATED_BYTES.try_with(|c| c.set(c.get() + size as u64));
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        Self::record(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        Self::record(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        Self::record(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Snapshot {
+    allocations: u64,
+    allocated_bytes: u64,
+}
+
+impl Snapshot {
+    pub(crate) fn take() -> Self {
+        Self {
+            allocations: ALLOCATIONS.with(|c| c.get()),
+            allocated_bytes: ALLOCATED_BYTES.with(|c| c.get()),
+        }
+    }
+
+    /// Returns `(allocations, allocated_bytes)` since this snapshot was taken.
+    pub(crate) fn elapsed(&self) -> Option<(u64, u64)> {
+        if !INSTALLED.load(Ordering::Relaxed) {
+            return None;
+        }
+        let now = Self::take();
+        Some((
+            now.allocations - self.allocations,
+            now.allocated_bytes - self.allocated_bytes,
+        ))
+    }
+}
@@ -0,0 +1,217 @@
+use crate::AutomaticClaheOptions;
+
+/// Result of [`AutomaticClahe::analyze_structure_scale()`](crate::AutomaticClahe::analyze_structure_scale).
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructureScale {
+    /// Approximate size (in pixels) of the dominant image structures.
+    pub scale: usize,
+
+    /// Detail energy of each pyramid level; level `i` corresponds to structures of about
+    /// `2 << i` pixels.
+    pub energies: Vec<f32>,
+}
+
+impl StructureScale {
+    pub(crate) fn estimate(luminances: &[u8], width: usize) -> Self {
+        let mut level = luminances
+            .iter()
+            .copied()
+            .map(f32::from)
+            .collect::<Vec<_>>();
+        let mut width = width;
+        let mut height = luminances.len() / width.max(1);
+
+        let mut energies = Vec::new();
+        while width >= 4 && height >= 4 {
+            let (next_width, next_height) = (width / 2, height / 2);
+            let mut next = vec![0.0; next_width * next_height];
+            for y in 0..next_height {
+                for x in 0..next_width {
+                    let i = 2 * y * width + 2 * x;
+                    next[y * next_width + x] =
+                        (level[i] + level[i + 1] + level[i + width] + level[i + width + 1]) / 4.0;
+                }
+            }
+
+            let mut energy = 0.0;
+            for y in 0..next_height * 2 {
+                for x in 0..next_width * 2 {
+                    let d = level[y * width + x] - next[y / 2 * next_width + x / 2];
+                    energy += d * d;
+                }
+            }
+            energies.push(energy / (next_width * next_height * 4) as f32);
+
+            level = next;
+            width = next_width;
+            height = next_height;
+        }
+
+        let dominant = energies
+            .iter()
+            .enumerate()
+            .fold(
+                (0, f32::NEG_INFINITY),
+                |acc, (i, &e)| {
+                    if e > acc.1 {
+                        (i, e)
+                    } else {
+                        acc
+                    }
+                },
+            )
+            .0;
+        Self {
+            scale: 2 << dominant,
+            energies,
+        }
+    }
+}
+
+/// Picks about 8x8 blocks per image, enlarged to cover the dominant image structures unless
+/// that would leave fewer than 4x4 blocks.
+pub(crate) fn auto_block_dimensions(luminances: &[u8], width: usize) -> (usize, usize) {
+    let height = luminances.len() / width.max(1);
+    let scale = StructureScale::estimate(luminances, width).scale;
+    let side = |len: usize| (len / 8).max(scale.min(len / 4)).max(1);
+    (side(width), side(height))
+}
+
+/// Result of [`AutomaticClahe::analyze()`](crate::AutomaticClahe::analyze).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recommendation {
+    pub width: usize,
+    pub height: usize,
+
+    /// Global luminance histogram.
+    pub histogram: [u64; 256],
+    pub l_min: u8,
+    pub l_max: u8,
+    pub mean: f32,
+
+    /// Estimated standard deviation of the image noise.
+    pub noise_sigma: f32,
+
+    pub block_width: usize,
+    pub block_height: usize,
+    pub alpha: f32,
+    pub d_threshold: u8,
+}
+
+impl Recommendation {
+    pub(crate) fn estimate(luminances: &[u8], width: usize) -> Self {
+        let height = luminances.len() / width.max(1);
+        let mut histogram = [0; 256];
+        for &l in luminances {
+            histogram[usize::from(l)] += 1;
+        }
+        let l_min = luminances.iter().copied().min().unwrap_or(0);
+        let l_max = luminances.iter().copied().max().unwrap_or(0);
+        let count = luminances.len().max(1) as f32;
+        let mean = luminances.iter().map(|&l| f32::from(l)).sum::<f32>() / count;
+        let noise_sigma = estimate_noise(luminances, width);
+        let (block_width, block_height) = auto_block_dimensions(luminances, width);
+
+        // Low-contrast images need a higher clip point to gain local contrast, while noisy ones
+        // need a lower one to avoid amplifying the noise.
+        let dynamic_range = f32::from(l_max - l_min).max(16.0);
+        let alpha = (100.0 * 128.0 / dynamic_range).clamp(40.0, 200.0) / (1.0 + noise_sigma / 5.0);
+        // The dual gamma correction should be enabled for blocks that carry more than noise.
+        let d_threshold = (dynamic_range / 4.0)
+            .max(noise_sigma * 3.0)
+            .clamp(10.0, 100.0) as u8;
+
+        Self {
+            width,
+            height,
+            histogram,
+            l_min,
+            l_max,
+            mean,
+            noise_sigma,
+            block_width,
+            block_height,
+            alpha,
+            d_threshold,
+        }
+    }
+
+    /// Returns the default options with the recommended parameters applied.
+    pub fn to_options(&self) -> AutomaticClaheOptions {
+        AutomaticClaheOptions {
+            block_width: self.block_width,
+            block_height: self.block_height,
+            alpha: self.alpha,
+            d_threshold: self.d_threshold,
+            ..Default::default()
+        }
+    }
+}
+
+// Immerkær's fast noise variance estimation.
+pub(crate) fn estimate_noise(luminances: &[u8], width: usize) -> f32 {
+    let height = luminances.len() / width.max(1);
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+
+    let l = |x: usize, y: usize| f32::from(luminances[y * width + x]);
+    let mut sum = 0.0;
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let v = l(x - 1, y - 1) + l(x + 1, y - 1) + l(x - 1, y + 1) + l(x + 1, y + 1)
+                - 2.0 * (l(x, y - 1) + l(x - 1, y) + l(x + 1, y) + l(x, y + 1))
+                + 4.0 * l(x, y);
+            sum += v.abs();
+        }
+    }
+    sum * (std::f32::consts::PI / 2.0).sqrt() / (6.0 * ((width - 2) * (height - 2)) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimate_finds_stripe_width() {
+        let width = 256;
+        for stripe in [2, 8, 32] {
+            let luminances = (0..width * 128)
+                .map(|i| {
+                    if (i % width) / stripe % 2 == 0 {
+                        50
+                    } else {
+                        200
+                    }
+                })
+                .collect::<Vec<_>>();
+            let scale = StructureScale::estimate(&luminances, width);
+            assert_eq!(scale.scale, stripe * 2, "{:?}", scale);
+        }
+    }
+
+    #[test]
+    fn recommendation_reflects_noise_and_contrast() {
+        let width = 128;
+        let mut seed = 1u32;
+        let mut noise = || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) % 21
+        };
+        let clean = (0..width * 96)
+            .map(|i| (100 + (i % width) / 8) as u8)
+            .collect::<Vec<_>>();
+        let noisy = clean
+            .iter()
+            .map(|&l| (u32::from(l) + noise()) as u8 - 10)
+            .collect::<Vec<_>>();
+
+        let clean = Recommendation::estimate(&clean, width);
+        let noisy = Recommendation::estimate(&noisy, width);
+        assert!(clean.noise_sigma < 1.0, "{}", clean.noise_sigma);
+        assert!(noisy.noise_sigma > 3.0, "{}", noisy.noise_sigma);
+        assert!(noisy.alpha < clean.alpha);
+        assert_eq!((clean.width, clean.height), (128, 96));
+        assert!(clean.to_options().validate().is_ok());
+    }
+}
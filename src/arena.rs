@@ -0,0 +1,387 @@
+use crate::{
+    blend_bilinear, dither::Ditherer, AutomaticClahe, AutomaticClaheOptions, Block, BlockRegions,
+    BlockSize, ChannelLayout, ClaheError, ColorSpace, Dither, GlobalStats, Histogram,
+    Interpolation, Point, Region, ALL_LEVELS,
+};
+
+const TABLE_BYTES: usize = 256 * std::mem::size_of::<f32>();
+
+impl AutomaticClahe {
+    /// The size of the scratch memory that
+    /// [`enhance_image_with_scratch()`](Self::enhance_image_with_scratch) needs for a
+    /// `width x height` image: the luminance plane, the LUT of every block and up to
+    /// `align_of::<f32>() - 1` bytes of alignment padding.
+    pub fn required_scratch_bytes(
+        width: usize,
+        height: usize,
+        options: &AutomaticClaheOptions,
+    ) -> Result<usize, ClaheError> {
+        if let Some(option) = allocating_option(options) {
+            return Err(ClaheError::UnsupportedOption(option));
+        }
+        let (block_width, block_height) = block_dimensions(width, height, options);
+        let blocks = (width / block_width) * (height / block_height);
+        Ok(width * height + std::mem::align_of::<f32>() - 1 + blocks * TABLE_BYTES)
+    }
+
+    /// Enhances an interleaved image like [`try_enhance_image()`](Self::try_enhance_image)
+    /// without any heap allocation, keeping all intermediate data in `scratch` (see
+    /// [`required_scratch_bytes()`](Self::required_scratch_bytes)).
+    ///
+    /// Only the stages that work on single pixels and single blocks are supported: the fixed or
+    /// tiled block sizes with bilinear interpolation of one block grid, no quadtree, block
+    /// scales, noise-adaptive clipping, denoising, log luminance, divergence limit, gain
+    /// smoothing, point-source protection, Floyd-Steinberg dithering, fixed-point LUTs, dehaze
+    /// or per-channel color space. The output is identical to that of
+    /// [`enhance_image()`](Self::enhance_image).
+    pub fn enhance_image_with_scratch(
+        &self,
+        pixels: &mut [u8],
+        width: usize,
+        layout: ChannelLayout,
+        scratch: &mut [u8],
+    ) -> Result<(), ClaheError> {
+        self.validate(pixels.len(), layout.channels(), width)?;
+        #[cfg(feature = "dehaze")]
+        if self.dehaze.is_some() {
+            return Err(ClaheError::UnsupportedOption("dehaze"));
+        }
+        let height = pixels.len() / layout.channels() / width;
+        let required = Self::required_scratch_bytes(width, height, &self.options)?;
+        if scratch.len() < required {
+            return Err(ClaheError::ScratchTooSmall {
+                required,
+                len: scratch.len(),
+            });
+        }
+
+        let (luminances, rest) = scratch.split_at_mut(width * height);
+        // SAFETY: every bit pattern is a valid `f32`.
+        let (_, tables, _) = unsafe { rest.align_to_mut::<f32>() };
+        let color_space = self.options.color_space;
+        for (l, p) in luminances
+            .iter_mut()
+            .zip(pixels.chunks_exact(layout.channels()))
+        {
+            let (r, g, b) = self.decode(layout.rgb(p));
+            *l = color_space.luminance(r, g, b);
+        }
+
+        let block_size = block_dimensions(width, height, &self.options);
+        let histogram = |window: Region, step: usize| {
+            Histogram::new(
+                window
+                    .sampled_indices(width, step)
+                    .map(|i| (luminances[i], 1)),
+            )
+        };
+        let whole = Region {
+            start: Point::new(0, 0),
+            end: Point::new(width, height),
+        };
+        let stats = GlobalStats::new(
+            &histogram(whole, self.options.histogram_subsampling),
+            self.options.l_alpha_percentile,
+        );
+        let regions = BlockRegions::new(width, height, block_size.0, block_size.1)
+            .with_overlap(self.options.block_overlap);
+        for ((region, window), table) in regions.zip(tables.chunks_exact_mut(256)) {
+            let histogram = histogram(window, self.histogram_step(window));
+            table.copy_from_slice(&self.block_table(&histogram, &stats, region));
+        }
+
+        let grid = LutGrid::new(tables, (width, height), block_size);
+        let mut ditherer = Ditherer::new(
+            self.options.dither,
+            width,
+            self.options.output_black,
+            self.options.output_white,
+        );
+        for (y, row) in luminances.chunks_exact_mut(width).enumerate() {
+            grid.enhance_row(self, y, row, &mut ditherer);
+        }
+        self.write_back_luminances(pixels, layout, luminances);
+        Ok(())
+    }
+
+    // The LUT of a block of the histogram-only paths (without noise estimation).
+    pub(crate) fn block_table(
+        &self,
+        histogram: &Histogram,
+        stats: &GlobalStats,
+        region: Region,
+    ) -> [f32; 256] {
+        if self.options.identity_luts {
+            Block::identity(region).table
+        } else {
+            Block::from_histogram(histogram, stats, self, region, 0.0, ALL_LEVELS).table
+        }
+    }
+}
+
+// The first option that makes the enhancement allocate.
+fn allocating_option(options: &AutomaticClaheOptions) -> Option<&'static str> {
+    non_local_option(options)
+        .or_else(|| (options.dither == Dither::FloydSteinberg).then_some("dither"))
+}
+
+// The first option whose stage needs more than the block histograms and the pixel itself (or
+// that has no per-pixel variant).
+pub(crate) fn non_local_option(options: &AutomaticClaheOptions) -> Option<&'static str> {
+    let is_set = [
+        (options.block_size == BlockSize::Auto, "block_size"),
+        (!options.block_scales.is_empty(), "block_scales"),
+        (options.quadtree.is_some(), "quadtree"),
+        (options.noise_clip_factor > 0.0, "noise_clip_factor"),
+        (options.denoise_strength > 0.0, "denoise_strength"),
+        (options.log_luminance, "log_luminance"),
+        (
+            options.max_block_divergence.is_some(),
+            "max_block_divergence",
+        ),
+        (options.gain_smoothing.is_some(), "gain_smoothing"),
+        (
+            options.point_source_protection.is_some(),
+            "point_source_protection",
+        ),
+        (
+            options.interpolation != Interpolation::Bilinear,
+            "interpolation",
+        ),
+        (options.color_space == ColorSpace::PerChannel, "color_space"),
+        (options.fixed_point_luts, "fixed_point_luts"),
+    ];
+    is_set
+        .iter()
+        .find(|&&(is_set, _)| is_set)
+        .map(|&(_, option)| option)
+}
+
+// The block dimensions of `AutomaticClahe::block_layouts()` for the supported block sizes.
+pub(crate) fn block_dimensions(
+    width: usize,
+    height: usize,
+    options: &AutomaticClaheOptions,
+) -> (usize, usize) {
+    let (block_width, block_height) = match options.block_size {
+        BlockSize::Tiles { x, y } => ((width / x).max(1), (height / y).max(1)),
+        _ => (options.block_width, options.block_height),
+    };
+    (block_width.clamp(1, width), block_height.clamp(1, height))
+}
+
+// A `BlockGrid` of the row-major block LUTs `tables`, whose block geometry is computed instead of
+// stored.
+pub(crate) struct LutGrid<'a> {
+    tables: &'a [f32],
+    width: usize,
+    height: usize,
+    block_width: usize,
+    block_height: usize,
+    line_blocks: usize,
+    aligned_width: usize,
+    aligned_height: usize,
+}
+
+impl<'a> LutGrid<'a> {
+    pub(crate) fn new(
+        tables: &'a [f32],
+        (width, height): (usize, usize),
+        (block_width, block_height): (usize, usize),
+    ) -> Self {
+        let line_blocks = width / block_width;
+        Self {
+            tables,
+            width,
+            height,
+            block_width,
+            block_height,
+            line_blocks,
+            aligned_width: line_blocks * block_width,
+            aligned_height: height / block_height * block_height,
+        }
+    }
+
+    // Enhances the luminances of row `y` in place like `AutomaticClahe::enhance_luminances()`.
+    pub(crate) fn enhance_row(
+        &self,
+        clahe: &AutomaticClahe,
+        y: usize,
+        row: &mut [u8],
+        ditherer: &mut Ditherer,
+    ) {
+        for (x, l) in row.iter_mut().enumerate() {
+            let l0 = *l;
+            *l = if clahe.options.is_protected(l0) {
+                l0
+            } else {
+                let enhanced = blend_bilinear(self.bilinear_terms(y, x, l0));
+                let enhanced =
+                    clahe.graded_output_value((x, y), (self.width, self.height), l0, enhanced);
+                ditherer.quantize(x, y, enhanced)
+            };
+        }
+        ditherer.next_row();
+    }
+
+    // `BlockGrid::bilinear_terms()`.
+    fn bilinear_terms(&self, y: usize, x: usize, l0: u8) -> [f32; 6] {
+        let (half_width, half_height) = (self.block_width / 2, self.block_height / 2);
+        let y0 = std::cmp::min(y, self.aligned_height - 1);
+        let x0 = std::cmp::min(x, self.aligned_width - 1);
+        let top = (y0 >= half_height).then(|| (y0 - half_height) / self.block_height);
+        let bottom = (y0 + half_height < self.aligned_height)
+            .then(|| (y0 + half_height) / self.block_height);
+        let left = (x0 >= half_width).then(|| (x0 - half_width) / self.block_width);
+        let right =
+            (x0 + half_width < self.aligned_width).then(|| (x0 + half_width) / self.block_width);
+        let block = |row: Option<usize>, column: Option<usize>| Some((row?, column?));
+        let (a, b) = (block(top, left), block(top, right));
+        let (c, d) = (block(bottom, left), block(bottom, right));
+
+        let m = match (a, c) {
+            (Some((top, _)), Some((bottom, _))) if self.center_y(top) != self.center_y(bottom) => {
+                let (a, c) = (self.center_y(top), self.center_y(bottom));
+                (c - y) as f32 / (c - a) as f32
+            }
+            _ if a.is_some() || b.is_some() => 1.0,
+            _ => 0.0,
+        };
+        let n = match (a, b) {
+            (Some((_, left)), Some((_, right))) if self.center_x(left) != self.center_x(right) => {
+                let (a, b) = (self.center_x(left), self.center_x(right));
+                (b - x) as f32 / (b - a) as f32
+            }
+            _ if a.is_some() || c.is_some() => 1.0,
+            _ => 0.0,
+        };
+        let value = |block: Option<(usize, usize)>| {
+            block.map_or(0.0, |(row, column)| {
+                self.tables[(row * self.line_blocks + column) * 256 + usize::from(l0)]
+            })
+        };
+        [m, n, value(a), value(b), value(c), value(d)]
+    }
+
+    // The centers of the blocks of `BlockRegions`, the last of which extend to the image edge.
+    fn center_x(&self, column: usize) -> usize {
+        let start = column * self.block_width;
+        let end = if column + 1 == self.line_blocks {
+            self.width
+        } else {
+            start + self.block_width
+        };
+        (end - start) / 2 + start
+    }
+
+    fn center_y(&self, row: usize) -> usize {
+        let rows = self.height / self.block_height;
+        let start = row * self.block_height;
+        let end = if row + 1 == rows {
+            self.height
+        } else {
+            start + self.block_height
+        };
+        (end - start) / 2 + start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StrengthGradient;
+
+    #[test]
+    fn scratch_enhancement_matches_the_enhancement() {
+        for (width, height, options) in [
+            (64, 48, AutomaticClaheOptions::default()),
+            (
+                61,
+                37,
+                AutomaticClaheOptions {
+                    block_width: 7,
+                    block_height: 5,
+                    histogram_subsampling: 2,
+                    block_overlap: 0.25,
+                    dither: Dither::Ordered,
+                    strength_gradient: Some(StrengthGradient::Linear {
+                        start: (0.0, 0.0),
+                        end: (1.0, 1.0),
+                    }),
+                    ..Default::default()
+                },
+            ),
+            (
+                50,
+                33,
+                AutomaticClaheOptions {
+                    block_size: BlockSize::Tiles { x: 3, y: 2 },
+                    color_space: ColorSpace::Oklab,
+                    ..Default::default()
+                },
+            ),
+            (
+                20,
+                20,
+                AutomaticClaheOptions {
+                    block_width: 20,
+                    block_height: 20,
+                    ..Default::default()
+                },
+            ),
+        ] {
+            let original = (0..width * height)
+                .flat_map(|i| {
+                    let (x, y) = (i % width, i / width);
+                    [(x * 3) as u8, (y * 5) as u8, ((x + y) * 2) as u8, 255]
+                })
+                .collect::<Vec<_>>();
+            let clahe = AutomaticClahe::with_options(options.clone());
+            let mut expected = original.clone();
+            clahe.enhance_rgba_image(&mut expected, width);
+
+            let required = AutomaticClahe::required_scratch_bytes(width, height, &options).unwrap();
+            let mut scratch = vec![0; required];
+            let mut pixels = original.clone();
+            assert_eq!(
+                clahe.enhance_image_with_scratch(
+                    &mut pixels,
+                    width,
+                    ChannelLayout::RGBA,
+                    &mut scratch[..required - 4]
+                ),
+                Err(ClaheError::ScratchTooSmall {
+                    required,
+                    len: required - 4
+                })
+            );
+            // Any alignment of the scratch memory fits.
+            for offset in 0..4 {
+                let mut scratch = vec![0; required + offset];
+                let mut pixels = original.clone();
+                #[cfg(feature = "alloc-stats")]
+                let snapshot = crate::alloc_stats::Snapshot::take();
+                clahe
+                    .enhance_image_with_scratch(
+                        &mut pixels,
+                        width,
+                        ChannelLayout::RGBA,
+                        &mut scratch[offset..],
+                    )
+                    .unwrap();
+                #[cfg(feature = "alloc-stats")]
+                assert_eq!(snapshot.elapsed(), Some((0, 0)), "{:?}", options);
+                assert_eq!(pixels, expected);
+            }
+        }
+
+        let options = AutomaticClaheOptions {
+            log_luminance: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            AutomaticClahe::required_scratch_bytes(64, 48, &options),
+            Err(ClaheError::UnsupportedOption("log_luminance"))
+        );
+    }
+}
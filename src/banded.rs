@@ -0,0 +1,318 @@
+use crate::arena::{block_dimensions, non_local_option, LutGrid};
+use crate::{
+    dither::Ditherer, AutomaticClahe, BlockRegions, ChannelLayout, ClaheError, GlobalStats,
+    Histogram, Region,
+};
+
+/// The first pass of the banded enhancement, which accumulates the block histograms of an
+/// image fed as horizontal bands of rows, see [`AutomaticClahe::analyze_bands()`].
+///
+/// ```
+/// use automatic_clahe::{AutomaticClahe, ChannelLayout};
+///
+/// let (width, height) = (64, 48);
+/// let mut pixels = vec![128; width * height * 3];
+/// let clahe = AutomaticClahe::new();
+/// let band_len = 16 * width * 3;
+///
+/// let mut analysis = clahe.analyze_bands(width, height, ChannelLayout::RGB)?;
+/// for band in pixels.chunks(band_len) {
+///     analysis.analyze_band(band)?;
+/// }
+/// let mut enhancement = analysis.finish()?;
+/// for band in pixels.chunks_mut(band_len) {
+///     enhancement.enhance_band(band)?;
+/// }
+/// # Ok::<(), automatic_clahe::ClaheError>(())
+/// ```
+#[derive(Debug)]
+pub struct BandAnalysis<'a> {
+    bands: Bands<'a>,
+    histogram: Histogram,
+    // The regions, histogram windows, subsampling steps and histograms of the blocks in
+    // row-major order.
+    blocks: Vec<(Region, Region, usize, Histogram)>,
+}
+
+/// The second pass of the banded enhancement, which enhances the bands in place from top to
+/// bottom, see [`BandAnalysis::finish()`].
+#[derive(Debug)]
+pub struct BandEnhancement<'a> {
+    bands: Bands<'a>,
+    // The row-major LUTs of the blocks.
+    tables: Vec<f32>,
+    ditherer: Ditherer,
+}
+
+// The image geometry and the position of the next band.
+#[derive(Debug)]
+struct Bands<'a> {
+    clahe: &'a AutomaticClahe,
+    width: usize,
+    height: usize,
+    layout: ChannelLayout,
+    block_size: (usize, usize),
+    row: usize,
+    // The luminances of the current band.
+    luminances: Vec<u8>,
+}
+
+impl AutomaticClahe {
+    /// Starts the banded enhancement of a `width x height` image, for images too large to hold
+    /// a full luminance plane: the first pass computes the block statistics from the bands of
+    /// rows, and the second pass enhances the same bands again. Besides the pixels, the peak
+    /// memory is one band of luminances and the histogram and LUT of every block.
+    ///
+    /// The restrictions of
+    /// [`enhance_image_with_scratch()`](Self::enhance_image_with_scratch) apply, except that
+    /// Floyd-Steinberg dithering is supported. The output is identical to that of
+    /// [`enhance_image()`](Self::enhance_image).
+    pub fn analyze_bands(
+        &self,
+        width: usize,
+        height: usize,
+        layout: ChannelLayout,
+    ) -> Result<BandAnalysis<'_>, ClaheError> {
+        let len = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(layout.channels()))
+            .ok_or(ClaheError::ImageTooLarge { width, height })?;
+        self.validate(len, layout.channels(), width)?;
+        #[cfg(feature = "dehaze")]
+        if self.dehaze.is_some() {
+            return Err(ClaheError::UnsupportedOption("dehaze"));
+        }
+        if let Some(option) = non_local_option(&self.options) {
+            return Err(ClaheError::UnsupportedOption(option));
+        }
+
+        let block_size = block_dimensions(width, height, &self.options);
+        let blocks = BlockRegions::new(width, height, block_size.0, block_size.1)
+            .with_overlap(self.options.block_overlap)
+            .map(|(region, window)| {
+                let step = self.histogram_step(window);
+                (region, window, step, Histogram([0; 256]))
+            })
+            .collect();
+        Ok(BandAnalysis {
+            bands: Bands {
+                clahe: self,
+                width,
+                height,
+                layout,
+                block_size,
+                row: 0,
+                luminances: Vec::new(),
+            },
+            histogram: Histogram([0; 256]),
+            blocks,
+        })
+    }
+}
+
+impl<'a> BandAnalysis<'a> {
+    /// Adds the next band of whole rows to the statistics.
+    pub fn analyze_band(&mut self, pixels: &[u8]) -> Result<(), ClaheError> {
+        let rows = self.bands.next_band(pixels)?;
+        let (width, step) = (
+            self.bands.width,
+            self.bands.clahe.options.histogram_subsampling,
+        );
+        let line_blocks = width / self.bands.block_size.0;
+        for y in rows.clone() {
+            let row = &self.bands.luminances[(y - rows.start) * width..][..width];
+            if y % step == 0 {
+                for &l in row.iter().step_by(step) {
+                    self.histogram.0[usize::from(l)] += 1;
+                }
+            }
+            for blocks in self.blocks.chunks_mut(line_blocks) {
+                let rows = blocks[0].1.start.y..blocks[0].1.end.y;
+                if !rows.contains(&y) {
+                    continue;
+                }
+                for (_, window, step, histogram) in blocks {
+                    let step = *step;
+                    if y % step != 0 {
+                        continue;
+                    }
+                    // A step wider than the block can skip all of its columns.
+                    let start = window.start.x.next_multiple_of(step).min(window.end.x);
+                    for &l in row[start..window.end.x].iter().step_by(step) {
+                        histogram.0[usize::from(l)] += 1;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Computes the block LUTs once all rows have been analyzed.
+    pub fn finish(self) -> Result<BandEnhancement<'a>, ClaheError> {
+        let Self {
+            mut bands,
+            histogram,
+            blocks,
+        } = self;
+        bands.check_complete()?;
+        let clahe = bands.clahe;
+        let stats = GlobalStats::new(&histogram, clahe.options.l_alpha_percentile);
+        let tables = blocks
+            .iter()
+            .flat_map(|(region, _, _, histogram)| clahe.block_table(histogram, &stats, *region))
+            .collect();
+        let ditherer = Ditherer::new(
+            clahe.options.dither,
+            bands.width,
+            clahe.options.output_black,
+            clahe.options.output_white,
+        );
+        bands.row = 0;
+        Ok(BandEnhancement {
+            bands,
+            tables,
+            ditherer,
+        })
+    }
+}
+
+impl BandEnhancement<'_> {
+    /// Enhances the next band of whole rows in place; the bands must be those of the analysis.
+    pub fn enhance_band(&mut self, pixels: &mut [u8]) -> Result<(), ClaheError> {
+        let rows = self.bands.next_band(pixels)?;
+        let bands = &mut self.bands;
+        let grid = LutGrid::new(&self.tables, (bands.width, bands.height), bands.block_size);
+        for (y, row) in rows.zip(bands.luminances.chunks_exact_mut(bands.width)) {
+            grid.enhance_row(bands.clahe, y, row, &mut self.ditherer);
+        }
+        bands
+            .clahe
+            .write_back_luminances(pixels, bands.layout, &bands.luminances);
+        Ok(())
+    }
+
+    /// Checks that all rows have been enhanced.
+    pub fn finish(self) -> Result<(), ClaheError> {
+        self.bands.check_complete()
+    }
+}
+
+impl Bands<'_> {
+    // Extracts the luminances of the next band and returns its rows.
+    fn next_band(&mut self, pixels: &[u8]) -> Result<std::ops::Range<usize>, ClaheError> {
+        let channels = self.layout.channels();
+        if !pixels.len().is_multiple_of(channels * self.width) {
+            return Err(ClaheError::BufferSizeMismatch {
+                len: pixels.len(),
+                channels,
+                width: self.width,
+            });
+        }
+        let rows = self.row..self.row + pixels.len() / channels / self.width;
+        if rows.end > self.height {
+            return Err(ClaheError::PlaneSizeMismatch {
+                expected: self.width * self.height,
+                actual: self.width * rows.end,
+            });
+        }
+        self.row = rows.end;
+        self.luminances.clear();
+        self.clahe
+            .extend_luminances(pixels, self.layout, &mut self.luminances);
+        Ok(rows)
+    }
+
+    fn check_complete(&self) -> Result<(), ClaheError> {
+        if self.row == self.height {
+            Ok(())
+        } else {
+            Err(ClaheError::PlaneSizeMismatch {
+                expected: self.width * self.height,
+                actual: self.width * self.row,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AutomaticClaheOptions, BlockSize, Dither};
+
+    #[test]
+    fn bands_match_the_enhancement() {
+        for (width, height, options) in [
+            (64, 48, AutomaticClaheOptions::default()),
+            (
+                61,
+                37,
+                AutomaticClaheOptions {
+                    block_width: 7,
+                    block_height: 5,
+                    histogram_subsampling: 3,
+                    block_overlap: 0.4,
+                    histogram_sample_budget: Some(6),
+                    dither: Dither::FloydSteinberg,
+                    ..Default::default()
+                },
+            ),
+            (
+                50,
+                33,
+                AutomaticClaheOptions {
+                    block_size: BlockSize::Tiles { x: 3, y: 2 },
+                    dither: Dither::Ordered,
+                    ..Default::default()
+                },
+            ),
+            (
+                64,
+                64,
+                AutomaticClaheOptions {
+                    block_width: 4,
+                    block_height: 4,
+                    histogram_subsampling: 12,
+                    ..Default::default()
+                },
+            ),
+        ] {
+            let layout = ChannelLayout::RGBA;
+            let original = (0..width * height)
+                .flat_map(|i| {
+                    let (x, y) = (i % width, i / width);
+                    [(x * 3) as u8, (y * 5) as u8, ((x * y) % 251) as u8, 255]
+                })
+                .collect::<Vec<_>>();
+            let clahe = AutomaticClahe::with_options(options);
+            let mut expected = original.clone();
+            clahe.enhance_image(&mut expected, width, layout);
+
+            for band_rows in [1, 7, height] {
+                let band_len = band_rows * width * layout.channels();
+                let mut pixels = original.clone();
+                let mut analysis = clahe.analyze_bands(width, height, layout).unwrap();
+                for band in pixels.chunks(band_len) {
+                    analysis.analyze_band(band).unwrap();
+                }
+                let mut enhancement = analysis.finish().unwrap();
+                for band in pixels.chunks_mut(band_len) {
+                    enhancement.enhance_band(band).unwrap();
+                }
+                enhancement.finish().unwrap();
+                assert_eq!(pixels, expected, "{} rows", band_rows);
+            }
+        }
+
+        let clahe = AutomaticClahe::new();
+        let pixels = vec![0; 64 * 48 * 3];
+        let mut analysis = clahe.analyze_bands(64, 48, ChannelLayout::RGB).unwrap();
+        analysis.analyze_band(&pixels[..64 * 3 * 10]).unwrap();
+        assert!(analysis.analyze_band(&pixels[5..]).is_err());
+        assert!(analysis.analyze_band(&pixels).is_err());
+        assert!(analysis.finish().is_err());
+        assert!(matches!(
+            clahe.analyze_bands(usize::MAX / 2, 3, ChannelLayout::RGB),
+            Err(ClaheError::ImageTooLarge { .. })
+        ));
+    }
+}
@@ -0,0 +1,26 @@
+/// Position and luminance statistics of a block, passed to the callback of
+/// [`AutomaticClahe::with_block_override()`](crate::AutomaticClahe::with_block_override).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockInfo {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub l_min: u8,
+    pub l_max: u8,
+    pub mean: f32,
+    pub sigma: f32,
+}
+
+/// Adjustments of a single block's parameters.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct BlockOverride {
+    /// Replaces the clip point (as a fraction of the block's pixels per bin).
+    pub clip_point: Option<f32>,
+
+    /// Forces the dual gamma correction on or off, regardless of `d_threshold`.
+    pub dual_gamma_correction: Option<bool>,
+
+    /// Leaves the block unenhanced.
+    pub identity: bool,
+}
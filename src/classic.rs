@@ -0,0 +1,120 @@
+use crate::{
+    Algorithm, AutomaticClahe, AutomaticClaheOptions, ChannelLayout, ClaheError, ColorSpace,
+    Enhancer,
+};
+
+/// Options of [`ClassicClahe`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ClassicClaheOptions {
+    pub block_width: usize,
+    pub block_height: usize,
+
+    /// Clip limit as a multiple of the average bin count of a block (the same for all blocks).
+    pub clip_limit: f32,
+
+    pub color_space: ColorSpace,
+}
+
+impl Default for ClassicClaheOptions {
+    fn default() -> Self {
+        Self {
+            block_width: 32,
+            block_height: 32,
+            clip_limit: 2.0,
+            color_space: ColorSpace::HsvInteger,
+        }
+    }
+}
+
+/// Zuiderveld's classic CLAHE (uniform clip limit, plain CDF LUTs and bilinear interpolation),
+/// as a baseline for [`AutomaticClahe`].
+#[derive(Debug)]
+pub struct ClassicClahe {
+    inner: AutomaticClahe,
+}
+
+impl ClassicClahe {
+    pub fn with_options(options: ClassicClaheOptions) -> Self {
+        let options = AutomaticClaheOptions {
+            block_width: options.block_width,
+            block_height: options.block_height,
+            clip_limit: Some(options.clip_limit),
+            color_space: options.color_space,
+            ..Default::default()
+        };
+        Self {
+            inner: AutomaticClahe {
+                algorithm: Algorithm::Classic,
+                ..AutomaticClahe::with_options(options)
+            },
+        }
+    }
+
+    pub fn new() -> Self {
+        Self::with_options(Default::default())
+    }
+}
+
+impl Default for ClassicClahe {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Enhancer for ClassicClahe {
+    fn enhance_image(&self, pixels: &mut [u8], width: usize, layout: ChannelLayout) {
+        self.inner.enhance_image(pixels, width, layout);
+    }
+
+    fn try_enhance_image(
+        &self,
+        pixels: &mut [u8],
+        width: usize,
+        layout: ChannelLayout,
+    ) -> Result<(), ClaheError> {
+        self.inner.try_enhance_image(pixels, width, layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classic_clahe_equalizes_blocks() {
+        // A single block without effective clipping is plain histogram equalization.
+        let mut pixels = (0..32 * 32)
+            .flat_map(|i| {
+                let l = (i % 128 + 64) as u8;
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let original = pixels.clone();
+        let clahe = ClassicClahe::with_options(ClassicClaheOptions {
+            clip_limit: 256.0,
+            ..Default::default()
+        });
+        clahe.enhance_rgb_image(&mut pixels, 32);
+        for (p, o) in pixels.chunks(3).zip(original.chunks(3)) {
+            let expected = 255.0 * f32::from(o[0] - 63) / 128.0;
+            assert!((f32::from(p[0]) - expected).abs() <= 1.0);
+        }
+
+        let enhancers: [Box<dyn Enhancer>; 2] = [
+            Box::new(ClassicClahe::new()),
+            Box::new(AutomaticClahe::new()),
+        ];
+        let outputs = enhancers
+            .iter()
+            .map(|e| {
+                let mut pixels = original.clone();
+                e.try_enhance_image(&mut pixels, 32, ChannelLayout::RGB)
+                    .unwrap();
+                pixels
+            })
+            .collect::<Vec<_>>();
+        assert_ne!(outputs[0], outputs[1]);
+    }
+}
@@ -1,3 +1,10 @@
+//! Conversions between 8-bit sRGB and the color spaces used by the enhancer.
+//!
+//! The `u8` HSV conversions are the integer ones of the paper (lossy by up to 6 levels per
+//! channel on saturated colors, as the hue is quantized to 256 steps); the other conversions use
+//! `f32` components and round trip exactly.
+
+/// Converts RGB to HSV with all components scaled to `[0, 255]`.
 pub fn rgb_to_hsv(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
     let r = usize::from(r);
     let g = usize::from(g);
@@ -6,7 +13,7 @@ pub fn rgb_to_hsv(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
     let min = std::cmp::min(r, std::cmp::min(g, b));
     let n = max - min;
 
-    let s = if max == 0 { 0 } else { n * 255 / max };
+    let s = (n * 255).checked_div(max).unwrap_or(0);
     let v = max;
     let h = if n == 0 {
         0
@@ -25,6 +32,7 @@ pub fn rgb_to_hsv(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
     (h as u8, s as u8, v as u8)
 }
 
+/// Converts the output of `rgb_to_hsv()` back to RGB.
 pub fn hsv_to_rgb(h: u8, s: u8, v: u8) -> (u8, u8, u8) {
     if s == 0 {
         return (v, v, v);
@@ -68,6 +76,283 @@ pub fn hsv_to_rgb(h: u8, s: u8, v: u8) -> (u8, u8, u8) {
     (r as u8, g as u8, b as u8)
 }
 
+/// Returns the hue in `[0, 6)`, the saturation in `[0, 1]` and the value in `[0, 255]`.
+pub fn rgb_to_hsv_f32(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
+    let (r, g, b) = (f32::from(r), f32::from(g), f32::from(b));
+    let max = r.max(g).max(b);
+    let n = max - r.min(g).min(b);
+    if n == 0.0 {
+        return (0.0, 0.0, max);
+    }
+
+    let h = if max == r {
+        ((g - b) / n).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / n + 2.0
+    } else {
+        (r - g) / n + 4.0
+    };
+    (h, n / max, max)
+}
+
+/// Converts the output of `rgb_to_hsv_f32()` back to RGB.
+pub fn hsv_f32_to_rgb(h: f32, s: f32, v: f32) -> (u8, u8, u8) {
+    let c = v * s;
+    let x = c * (1.0 - ((h % 2.0) - 1.0).abs());
+    let m = v - c;
+    let (r, g, b) = match h as u8 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let to_u8 = |x: f32| (x + m).round().clamp(0.0, 255.0) as u8;
+    (to_u8(r), to_u8(g), to_u8(b))
+}
+
+/// Returns the hue in `[0, 6)`, the saturation in `[0, 1]` and the lightness in `[0, 255]`.
+pub fn rgb_to_hsl(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
+    let (h, _, max) = rgb_to_hsv_f32(r, g, b);
+    let min = f32::from(r.min(g).min(b));
+    let l = (max + min) / 2.0;
+    let n = max - min;
+    let s = if n == 0.0 {
+        0.0
+    } else {
+        n / (255.0 - (2.0 * l - 255.0).abs())
+    };
+    (h, s, l)
+}
+
+/// Converts the output of `rgb_to_hsl()` back to RGB.
+pub fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (u8, u8, u8) {
+    let c = (255.0 - (2.0 * l - 255.0).abs()) * s;
+    let v = l + c / 2.0;
+    let s = if v == 0.0 { 0.0 } else { c / v };
+    hsv_f32_to_rgb(h, s, v)
+}
+
+fn srgb_to_linear(x: u8) -> f32 {
+    let x = f32::from(x) / 255.0;
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(x: f32) -> u8 {
+    let x = if x <= 0.003_130_8 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    };
+    (x * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+/// Converts sRGB to CIELAB (D65 white point); `L*` is in `[0, 100]`.
+pub fn rgb_to_lab(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
+    let (r, g, b) = (srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b));
+    let x = (0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b) / 0.950_47;
+    let y = 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b;
+    let z = (0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b) / 1.088_83;
+
+    const DELTA: f32 = 6.0 / 29.0;
+    let f = |t: f32| {
+        if t > DELTA.powi(3) {
+            t.cbrt()
+        } else {
+            t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    (116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
+
+/// Converts CIELAB back to sRGB, clamping out-of-gamut colors.
+pub fn lab_to_rgb(l: f32, a: f32, b: f32) -> (u8, u8, u8) {
+    const DELTA: f32 = 6.0 / 29.0;
+    let f_inv = |t: f32| {
+        if t > DELTA {
+            t.powi(3)
+        } else {
+            3.0 * DELTA * DELTA * (t - 4.0 / 29.0)
+        }
+    };
+    let fy = (l + 16.0) / 116.0;
+    let x = 0.950_47 * f_inv(fy + a / 500.0);
+    let y = f_inv(fy);
+    let z = 1.088_83 * f_inv(fy - b / 200.0);
+
+    let r = 3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z;
+    let g = -0.969_266 * x + 1.876_010_8 * y + 0.041_556 * z;
+    let b = 0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z;
+    (linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b))
+}
+
+/// Returns the sRGB level of the neutral color with the given `L*`.
+pub fn lab_to_gray(l: f32) -> u8 {
+    const DELTA: f32 = 6.0 / 29.0;
+    let fy = (l + 16.0) / 116.0;
+    let y = if fy > DELTA {
+        fy.powi(3)
+    } else {
+        3.0 * DELTA * DELTA * (fy - 4.0 / 29.0)
+    };
+    linear_to_srgb(y)
+}
+
+/// Converts sRGB to Oklab; `L` is in `[0, 1]`.
+pub fn rgb_to_oklab(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
+    let (r, g, b) = (srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b));
+    let l = (0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+    let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+    let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+    (
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    )
+}
+
+/// Converts Oklab back to sRGB, clamping out-of-gamut colors.
+pub fn oklab_to_rgb(l: f32, a: f32, b: f32) -> (u8, u8, u8) {
+    let lc = (l + 0.396_337_78 * a + 0.215_803_76 * b).powi(3);
+    let mc = (l - 0.105_561_35 * a - 0.063_854_17 * b).powi(3);
+    let sc = (l - 0.089_484_18 * a - 1.291_485_5 * b).powi(3);
+    (
+        linear_to_srgb(4.076_741_7 * lc - 3.307_711_6 * mc + 0.230_969_94 * sc),
+        linear_to_srgb(-1.268_438 * lc + 2.609_757_4 * mc - 0.341_319_4 * sc),
+        linear_to_srgb(-0.004_196_086_3 * lc - 0.703_418_6 * mc + 1.707_614_7 * sc),
+    )
+}
+
+/// Returns the sRGB level of the neutral color with the given Oklab `L`.
+pub fn oklab_to_gray(l: f32) -> u8 {
+    linear_to_srgb(l.powi(3))
+}
+
+/// The BT.709 luma weights of R, G and B.
+pub const BT709: (f32, f32, f32) = (0.2126, 0.7152, 0.0722);
+
+/// The BT.601 luma weights of R, G and B.
+pub const BT601: (f32, f32, f32) = (0.299, 0.587, 0.114);
+
+/// Returns the weighted sum of the channels (the weights should add up to 1).
+pub fn rgb_to_luma(r: u8, g: u8, b: u8, (kr, kg, kb): (f32, f32, f32)) -> u8 {
+    let luma = kr * f32::from(r) + kg * f32::from(g) + kb * f32::from(b);
+    luma.round().min(255.0) as u8
+}
+
+/// Converts RGB to full-range YCbCr with the given luma weights; Y is in `[0, 255]` and Cb/Cr
+/// are in `[-127.5, 127.5]`.
+pub fn rgb_to_ycbcr(r: u8, g: u8, b: u8, (kr, kg, kb): (f32, f32, f32)) -> (f32, f32, f32) {
+    let (r, g, b) = (f32::from(r), f32::from(g), f32::from(b));
+    let y = kr * r + kg * g + kb * b;
+    (
+        y,
+        (b - y) / (2.0 * (1.0 - kb)),
+        (r - y) / (2.0 * (1.0 - kr)),
+    )
+}
+
+/// Converts the output of `rgb_to_ycbcr()` back to RGB, clamping out-of-gamut colors.
+pub fn ycbcr_to_rgb(y: f32, cb: f32, cr: f32, (kr, kg, kb): (f32, f32, f32)) -> (u8, u8, u8) {
+    let r = y + 2.0 * (1.0 - kr) * cr;
+    let b = y + 2.0 * (1.0 - kb) * cb;
+    let g = (y - kr * r - kb * b) / kg;
+    let to_u8 = |x: f32| x.round().clamp(0.0, 255.0) as u8;
+    (to_u8(r), to_u8(g), to_u8(b))
+}
+
+/// Changes the (rounded) luma of the pixel to `l` while keeping its Cb/Cr chroma, which is
+/// multiplied by `chroma_factor`.
+pub fn ycbcr_with_luma(
+    r: u8,
+    g: u8,
+    b: u8,
+    (kr, kg, kb): (f32, f32, f32),
+    l: u8,
+    chroma_factor: f32,
+) -> (u8, u8, u8) {
+    let y = kr * f32::from(r) + kg * f32::from(g) + kb * f32::from(b);
+    // The offset is relative to the rounded luma so that unchanged values keep the exact color.
+    let shift = f32::from(l) - y.round().min(255.0);
+    let y2 = y + shift;
+    let mix = |x: u8| {
+        (y2 + (f32::from(x) - y) * chroma_factor)
+            .round()
+            .clamp(0.0, 255.0) as u8
+    };
+    (mix(r), mix(g), mix(b))
+}
+
+/// Scales the channels so that a luminance of `from` becomes `to` (clamping at 255).
+pub fn scale_rgb(r: u8, g: u8, b: u8, from: u8, to: u8) -> (u8, u8, u8) {
+    if from == 0 {
+        return (to, to, to);
+    }
+    let factor = f32::from(to) / f32::from(from);
+    let scale = |x: u8| (f32::from(x) * factor).round().min(255.0) as u8;
+    (scale(r), scale(g), scale(b))
+}
+
+/// Like `scale_rgb()`, but channels that would exceed 255 are moved towards the gray of `to`
+/// (keeping the hue and the luma) instead of being clamped.
+pub fn scale_rgb_desaturating(r: u8, g: u8, b: u8, from: u8, to: u8) -> (u8, u8, u8) {
+    if from == 0 {
+        return (to, to, to);
+    }
+    let factor = f32::from(to) / f32::from(from);
+    let (r, g, b) = (
+        f32::from(r) * factor,
+        f32::from(g) * factor,
+        f32::from(b) * factor,
+    );
+    let max = r.max(g).max(b);
+    let to = f32::from(to);
+    let k = if max > 255.0 {
+        (255.0 - to) / (max - to)
+    } else {
+        1.0
+    };
+    let mix = |x: f32| (to + (x - to) * k).round().clamp(0.0, 255.0) as u8;
+    (mix(r), mix(g), mix(b))
+}
+
+/// Moves the channels away from (`factor > 1`) or towards (`factor < 1`) the gray of luminance `l`.
+pub fn scale_saturation(r: u8, g: u8, b: u8, l: u8, factor: f32) -> (u8, u8, u8) {
+    let l = f32::from(l);
+    let mix = |x: u8| (l + (f32::from(x) - l) * factor).round().clamp(0.0, 255.0) as u8;
+    (mix(r), mix(g), mix(b))
+}
+
+/// Returns the brightness of a CMYK color, i.e. the HSV value of its RGB equivalent.
+pub fn cmyk_to_value(c: u8, m: u8, y: u8, k: u8) -> u8 {
+    let white = 255 - usize::from(std::cmp::min(c, std::cmp::min(m, y)));
+    ((255 - usize::from(k)) * white / 255) as u8
+}
+
+/// Returns the inks for which `cmyk_to_value()` yields `v`.
+///
+/// The black ink is changed first. If removing all black is not enough, the C/M/Y inks are
+/// scaled down by the same factor so that their ratios are kept.
+pub fn cmyk_with_value(c: u8, m: u8, y: u8, v: u8) -> (u8, u8, u8, u8) {
+    let min = usize::from(std::cmp::min(c, std::cmp::min(m, y)));
+    let white = 255 - min;
+    let v = usize::from(v);
+    if v <= white && white != 0 {
+        let k = 255 - (v * 255 + white / 2) / white;
+        return (c, m, y, k as u8);
+    }
+
+    let new_min = 255 - v;
+    let scale = |x: u8| (usize::from(x) * new_min / min) as u8;
+    (scale(c), scale(m), scale(y), 0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -87,4 +372,148 @@ mod tests {
             assert!((i32::from(b) - i32::from(i.2)).abs() <= 2);
         }
     }
+
+    // Calls `f` with a dense, evenly strided sample of the RGB cube.
+    fn for_each_rgb(mut f: impl FnMut(u8, u8, u8)) {
+        for r in (0..=255).step_by(5) {
+            for g in (0..=255).step_by(15) {
+                for b in (0..=255).step_by(17) {
+                    f(r, g, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn integer_hsv_round_trip_is_close() {
+        for_each_rgb(|r, g, b| {
+            let (h, s, v) = rgb_to_hsv(r, g, b);
+            let (r2, g2, b2) = hsv_to_rgb(h, s, v);
+            // The quantized hue costs up to 6 levels on saturated colors.
+            assert!(r2.abs_diff(r) <= 6 && g2.abs_diff(g) <= 6 && b2.abs_diff(b) <= 6);
+        });
+    }
+
+    #[test]
+    fn conversions_stay_in_range() {
+        for_each_rgb(|r, g, b| {
+            for (h, s, v) in [rgb_to_hsv_f32(r, g, b), rgb_to_hsl(r, g, b)] {
+                assert!((0.0..6.0).contains(&h));
+                assert!((0.0..=1.0).contains(&s));
+                assert!((0.0..=255.0).contains(&v));
+            }
+            let (y, cb, cr) = rgb_to_ycbcr(r, g, b, BT709);
+            assert!((-0.01..=255.01).contains(&y));
+            assert!(cb.abs() <= 127.51 && cr.abs() <= 127.51);
+            assert!((-0.01..=100.01).contains(&rgb_to_lab(r, g, b).0));
+            assert!((-0.001..=1.001).contains(&rgb_to_oklab(r, g, b).0));
+        });
+    }
+
+    #[test]
+    fn rgb_to_ycbcr_round_trips_exactly() {
+        for weights in [BT709, BT601] {
+            for_each_rgb(|r, g, b| {
+                let (y, cb, cr) = rgb_to_ycbcr(r, g, b, weights);
+                assert_eq!(ycbcr_to_rgb(y, cb, cr, weights), (r, g, b));
+            });
+        }
+    }
+
+    #[test]
+    fn rgb_to_hsv_f32_round_trips_exactly() {
+        for_each_rgb(|r, g, b| {
+            let (h, s, v) = rgb_to_hsv_f32(r, g, b);
+            assert_eq!(hsv_f32_to_rgb(h, s, v), (r, g, b));
+        });
+    }
+
+    #[test]
+    fn rgb_to_hsl_round_trips_exactly() {
+        for_each_rgb(|r, g, b| {
+            let (h, s, l) = rgb_to_hsl(r, g, b);
+            assert_eq!(hsl_to_rgb(h, s, l), (r, g, b));
+        });
+    }
+
+    #[test]
+    fn rgb_to_lab_round_trips() {
+        assert!((rgb_to_lab(255, 255, 255).0 - 100.0).abs() < 0.01);
+        for_each_rgb(|r, g, b| {
+            let (l, a, b2) = rgb_to_lab(r, g, b);
+            assert_eq!(lab_to_rgb(l, a, b2), (r, g, b));
+        });
+    }
+
+    #[test]
+    fn rgb_to_oklab_round_trips() {
+        assert!((rgb_to_oklab(255, 255, 255).0 - 1.0).abs() < 0.001);
+        for_each_rgb(|r, g, b| {
+            let (l, a, b2) = rgb_to_oklab(r, g, b);
+            assert_eq!(oklab_to_rgb(l, a, b2), (r, g, b));
+        });
+    }
+
+    #[test]
+    fn ycbcr_with_luma_keeps_chroma() {
+        for (r, g, b) in [(10, 30, 200), (100, 100, 100), (200, 40, 90)] {
+            let y = rgb_to_luma(r, g, b, BT601);
+            assert_eq!(ycbcr_with_luma(r, g, b, BT601, y, 1.0), (r, g, b));
+            let (r2, g2, b2) = ycbcr_with_luma(r, g, b, BT601, y + 20, 1.0);
+            assert_eq!(rgb_to_luma(r2, g2, b2, BT601), y + 20);
+            assert_eq!(i32::from(r2) - i32::from(g2), i32::from(r) - i32::from(g));
+            assert_eq!(i32::from(b2) - i32::from(g2), i32::from(b) - i32::from(g));
+        }
+    }
+
+    #[test]
+    fn cmyk_with_value_works() {
+        let inputs = [
+            (0, 0, 0, 0),
+            (10, 200, 30, 100),
+            (255, 255, 255, 0),
+            (40, 50, 60, 255),
+        ];
+        for i in inputs {
+            for v in [0, 1, 64, 128, 200, 255] {
+                let (c, m, y, k) = cmyk_with_value(i.0, i.1, i.2, v);
+                let actual = cmyk_to_value(c, m, y, k);
+                assert!(
+                    (i32::from(actual) - i32::from(v)).abs() <= 1,
+                    "{:?}, {}",
+                    i,
+                    v
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn scale_rgb_changes_luma() {
+        let bt709 = (0.2126, 0.7152, 0.0722);
+        for (r, g, b) in [(10, 30, 200), (100, 100, 100), (200, 40, 90)] {
+            let from = rgb_to_luma(r, g, b, bt709);
+            for to in [0, 20, 35] {
+                let (r, g, b) = scale_rgb(r, g, b, from, to);
+                let actual = rgb_to_luma(r, g, b, bt709);
+                assert!((i32::from(actual) - i32::from(to)).abs() <= 1);
+            }
+        }
+        assert_eq!(scale_rgb(0, 0, 0, 0, 30), (30, 30, 30));
+        assert_eq!(scale_rgb(100, 200, 50, 100, 200), (200, 255, 100));
+    }
+
+    #[test]
+    fn scale_rgb_desaturating_keeps_hue_and_luma() {
+        let bt709 = (0.2126, 0.7152, 0.0722);
+        assert_eq!(scale_rgb_desaturating(40, 80, 20, 80, 160), (80, 160, 40));
+        assert_eq!(scale_rgb_desaturating(77, 77, 77, 77, 200), (200, 200, 200));
+
+        let (r, g, b) = (100, 200, 50);
+        let from = rgb_to_luma(r, g, b, bt709);
+        let (r2, g2, b2) = scale_rgb_desaturating(r, g, b, from, 230);
+        assert_eq!(g2, 255);
+        assert!(r2 > b2 && r2 < g2);
+        assert!((i32::from(rgb_to_luma(r2, g2, b2, bt709)) - 230).abs() <= 1);
+    }
 }
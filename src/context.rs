@@ -0,0 +1,134 @@
+use crate::{AutomaticClahe, BilinearSweep, Block, BlockGrid, ChannelLayout, ClaheError};
+
+/// Buffers that are reused across calls to enhance a stream of same-sized images (e.g. video
+/// frames) without reallocating the luminance planes, blocks and interpolation tables.
+///
+/// ```
+/// use automatic_clahe::{AutomaticClahe, ClaheContext};
+///
+/// let clahe = AutomaticClahe::new();
+/// let mut context = ClaheContext::new();
+/// # let frames = vec![vec![128; 64 * 48 * 3]; 2];
+/// for mut frame in frames {
+///     context.enhance(&clahe, &mut frame, 64);
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct ClaheContext {
+    planes: [Vec<u8>; 3],
+    scratch: Scratch,
+}
+
+impl ClaheContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enhances an interleaved RGB image like [`AutomaticClahe::enhance_rgb_image()`].
+    pub fn enhance(&mut self, clahe: &AutomaticClahe, pixels: &mut [u8], width: usize) {
+        self.enhance_image(clahe, pixels, width, ChannelLayout::RGB);
+    }
+
+    /// Enhances an interleaved image like [`AutomaticClahe::enhance_image()`].
+    pub fn enhance_image(
+        &mut self,
+        clahe: &AutomaticClahe,
+        pixels: &mut [u8],
+        width: usize,
+        layout: ChannelLayout,
+    ) {
+        if let Some(dehazed) = clahe.dehazed(pixels, width, layout) {
+            pixels.copy_from_slice(&dehazed);
+        }
+        let buffers = std::mem::take(&mut self.planes);
+        let mut planes = clahe.extract_planes_into(pixels, width, layout, None, buffers);
+        clahe.enhance_planes_with(&mut planes, None, None, Some(&mut self.scratch));
+        clahe.write_back_planes(pixels, layout, &planes);
+        self.planes = planes.into_buffers();
+    }
+
+    /// Validating variant of [`enhance_image()`](Self::enhance_image).
+    pub fn try_enhance_image(
+        &mut self,
+        clahe: &AutomaticClahe,
+        pixels: &mut [u8],
+        width: usize,
+        layout: ChannelLayout,
+    ) -> Result<(), ClaheError> {
+        clahe.validate(pixels.len(), layout.channels(), width)?;
+        self.enhance_image(clahe, pixels, width, layout);
+        Ok(())
+    }
+}
+
+// The buffers of the enhancement of a single plane.
+#[derive(Debug, Default)]
+pub(crate) struct Scratch {
+    // The unquantized output luminances.
+    pub(crate) enhanced: Vec<f32>,
+    // The blocks of the first block grid (their contents are stale).
+    pub(crate) blocks: Vec<Block>,
+    // The bilinear sweep and the image and block dimensions it was built for.
+    sweep: Option<([usize; 4], BilinearSweep)>,
+}
+
+impl Scratch {
+    // The bilinear sweep of `grid`, rebuilt only if the dimensions changed.
+    pub(crate) fn sweep(
+        &mut self,
+        grid: &BlockGrid,
+        width: usize,
+        height: usize,
+    ) -> &BilinearSweep {
+        let key = [width, height, grid.block_width, grid.block_height];
+        if !matches!(&self.sweep, Some((k, _)) if *k == key) {
+            self.sweep = Some((key, grid.bilinear_sweep(width, height)));
+        }
+        &self.sweep.as_ref().expect("built above").1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AutomaticClaheOptions, ColorSpace};
+
+    #[test]
+    fn reused_contexts_match_the_enhancement() {
+        let frame = |width: usize, height: usize, offset: usize| {
+            (0..width * height)
+                .flat_map(|i| {
+                    let (x, y) = (i % width, i / width);
+                    [(x * 3 + offset) as u8, (y * 5) as u8, ((x + y) * 2) as u8]
+                })
+                .collect::<Vec<_>>()
+        };
+        for color_space in [ColorSpace::HsvInteger, ColorSpace::PerChannel] {
+            let clahe = AutomaticClahe::with_options(AutomaticClaheOptions {
+                color_space,
+                ..Default::default()
+            });
+            let mut context = ClaheContext::new();
+            for (width, height, offset) in [(64, 48, 0), (64, 48, 40), (50, 33, 7), (64, 48, 9)] {
+                let original = frame(width, height, offset);
+                let mut expected = original.clone();
+                clahe.enhance_rgb_image(&mut expected, width);
+                let mut pixels = original.clone();
+                context.enhance(&clahe, &mut pixels, width);
+                assert_eq!(pixels, expected);
+            }
+
+            // Frames of the same size reuse the allocations.
+            let pointers = |context: &ClaheContext| {
+                (
+                    context.planes[0].as_ptr(),
+                    context.scratch.enhanced.as_ptr(),
+                    context.scratch.blocks.as_ptr(),
+                )
+            };
+            let before = pointers(&context);
+            context.enhance(&clahe, &mut frame(64, 48, 1), 64);
+            assert_eq!(pointers(&context), before);
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{ChannelLayout, GuidedFilter, OptionsError};
+
+/// Parameters of the dark channel prior dehazing of He et al.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct DehazeOptions {
+    /// Radius of the patches whose darkest channel value estimates the haze.
+    pub patch_radius: usize,
+
+    /// Fraction of the haze that is removed (`1.0` removes all of it, which tends to look
+    /// unnatural for distant objects).
+    pub omega: f32,
+
+    /// Lower limit of the transmission, which keeps dense haze from amplifying noise.
+    pub min_transmission: f32,
+}
+
+impl Default for DehazeOptions {
+    fn default() -> Self {
+        Self {
+            patch_radius: 7,
+            omega: 0.95,
+            min_transmission: 0.1,
+        }
+    }
+}
+
+impl DehazeOptions {
+    pub fn validate(&self) -> Result<(), OptionsError> {
+        if !(self.omega > 0.0 && self.omega <= 1.0) {
+            return Err(OptionsError::InvalidDehazeOmega(self.omega));
+        }
+        if !(self.min_transmission > 0.0 && self.min_transmission <= 1.0) {
+            return Err(OptionsError::InvalidDehazeTransmission(
+                self.min_transmission,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Removes the haze of an interleaved image in place.
+    pub(crate) fn apply(&self, pixels: &mut [u8], width: usize, layout: ChannelLayout) {
+        let channels = layout.channels();
+        let rgb = pixels
+            .chunks_exact(channels)
+            .map(|p| {
+                let (r, g, b) = layout.rgb(p);
+                [f32::from(r), f32::from(g), f32::from(b)]
+            })
+            .collect::<Vec<_>>();
+        if rgb.is_empty() {
+            return;
+        }
+
+        // The atmospheric light is the brightest pixel among the 0.1% haziest ones.
+        let dark = self.min_filter(
+            &rgb.iter()
+                .map(|p| p[0].min(p[1]).min(p[2]))
+                .collect::<Vec<_>>(),
+            width,
+        );
+        let mut haziest = (0..rgb.len()).collect::<Vec<_>>();
+        haziest.sort_by(|&a, &b| dark[b].total_cmp(&dark[a]));
+        let airlight = haziest[..std::cmp::max(rgb.len() / 1000, 1)]
+            .iter()
+            .map(|&i| rgb[i])
+            .max_by(|a, b| a.iter().sum::<f32>().total_cmp(&b.iter().sum::<f32>()))
+            .expect("non-empty")
+            .map(|a| a.max(1.0));
+
+        let normalized = rgb
+            .iter()
+            .map(|p| {
+                (0..3)
+                    .map(|c| p[c] / airlight[c])
+                    .fold(f32::INFINITY, f32::min)
+            })
+            .collect::<Vec<_>>();
+        let transmission = self
+            .min_filter(&normalized, width)
+            .into_iter()
+            .map(|d| 1.0 - self.omega * d)
+            .collect::<Vec<_>>();
+        // Refines the blocky transmission along the edges of the image.
+        let guide = rgb
+            .iter()
+            .map(|p| p.iter().sum::<f32>() / (3.0 * 255.0))
+            .collect::<Vec<_>>();
+        let filter = GuidedFilter {
+            radius: 4 * self.patch_radius,
+            epsilon: 1e-3,
+        };
+        let transmission = filter.smooth(&guide, &transmission, width);
+
+        for ((p, rgb), t) in pixels.chunks_exact_mut(channels).zip(rgb).zip(transmission) {
+            let t = t.max(self.min_transmission);
+            let [r, g, b] = std::array::from_fn(|c| {
+                ((rgb[c] - airlight[c]) / t + airlight[c])
+                    .clamp(0.0, 255.0)
+                    .round() as u8
+            });
+            layout.set_rgb(p, (r, g, b));
+        }
+    }
+
+    // The minimum of the `(2 * patch_radius + 1)^2` patch around each pixel.
+    fn min_filter(&self, values: &[f32], width: usize) -> Vec<f32> {
+        let height = values.len() / width;
+        let radius = self.patch_radius;
+        let mut rows = vec![0.0; values.len()];
+        for y in 0..height {
+            for x in 0..width {
+                let (x0, x1) = (
+                    x.saturating_sub(radius),
+                    std::cmp::min(x + radius + 1, width),
+                );
+                rows[y * width + x] = values[y * width + x0..y * width + x1]
+                    .iter()
+                    .fold(f32::INFINITY, |m, &v| m.min(v));
+            }
+        }
+        let mut mins = vec![0.0; values.len()];
+        for y in 0..height {
+            let (y0, y1) = (
+                y.saturating_sub(radius),
+                std::cmp::min(y + radius + 1, height),
+            );
+            for x in 0..width {
+                mins[y * width + x] = (y0..y1)
+                    .map(|y| rows[y * width + x])
+                    .fold(f32::INFINITY, f32::min);
+            }
+        }
+        mins
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dehazing_restores_contrast() {
+        let width = 64;
+        // A textured scene behind a uniform gray haze with 40% transmission.
+        let scene = |i: usize| [(i % width * 3) as f32, 60.0, ((i / width) * 4) as f32];
+        let hazy = (0..width * 48)
+            .flat_map(|i| scene(i).map(|c| (0.4 * c + 0.6 * 220.0) as u8))
+            .collect::<Vec<_>>();
+        let mut pixels = hazy.clone();
+        DehazeOptions::default().apply(&mut pixels, width, ChannelLayout::RGB);
+
+        let spread = |pixels: &[u8]| {
+            let red = pixels.chunks(3).map(|p| p[0]);
+            red.clone().max().unwrap() - red.min().unwrap()
+        };
+        assert!(spread(&pixels) > spread(&hazy) * 2);
+        assert!(DehazeOptions {
+            omega: 0.0,
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+}
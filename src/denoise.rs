@@ -0,0 +1,79 @@
+use crate::{Image, Point, Region};
+
+// Radius and spatial standard deviation of the bilateral filter window.
+const RADIUS: usize = 2;
+const SPATIAL_SIGMA: f32 = 1.5;
+
+impl Image {
+    /// A copy smoothed by a bilateral filter whose range sigma is `strength` times the noise
+    /// sigma of the image, so that edges well above the noise level are kept.
+    pub(crate) fn denoised(&self, strength: f32) -> Self {
+        let range_sigma = strength
+            * self.noise_sigma(Region {
+                start: Point::new(0, 0),
+                end: Point::new(self.width, self.height),
+            });
+        if range_sigma <= 0.0 {
+            return Self::with_weights(self.luminances.clone(), self.weights.clone(), self.width);
+        }
+
+        let spatial = |dx: usize, dy: usize| {
+            (-((dx * dx + dy * dy) as f32) / (2.0 * SPATIAL_SIGMA * SPATIAL_SIGMA)).exp()
+        };
+        let range = (0..256)
+            .map(|d| (-((d * d) as f32) / (2.0 * range_sigma * range_sigma)).exp())
+            .collect::<Vec<_>>();
+        let mut luminances = Vec::with_capacity(self.luminances.len());
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let l0 = self.luminances[y * self.width + x];
+                let (mut sum, mut weights) = (0.0, 0.0);
+                for ny in y.saturating_sub(RADIUS)..std::cmp::min(y + RADIUS + 1, self.height) {
+                    for nx in x.saturating_sub(RADIUS)..std::cmp::min(x + RADIUS + 1, self.width) {
+                        let l = self.luminances[ny * self.width + nx];
+                        let w = spatial(nx.abs_diff(x), ny.abs_diff(y))
+                            * range[usize::from(l.abs_diff(l0))];
+                        sum += w * f32::from(l);
+                        weights += w;
+                    }
+                }
+                luminances.push((sum / weights).round() as u8);
+            }
+        }
+        Self::with_weights(luminances, self.weights.clone(), self.width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn denoising_keeps_edges() {
+        let width = 64;
+        // A dark and a bright half with a +-6 checkerboard noise.
+        let luminances = (0..width * 32)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                let base = if x < 32 { 30 } else { 180 };
+                if (x + y) % 2 == 0 {
+                    base + 6
+                } else {
+                    base - 6
+                }
+            })
+            .collect::<Vec<_>>();
+        let image = Image::new(luminances, width);
+        let denoised = image.denoised(2.0);
+
+        for y in 0..32 {
+            for x in [0, 10, 29] {
+                assert!(denoised.luminances[y * width + x].abs_diff(30) <= 2);
+            }
+            for x in [34, 50, 63] {
+                assert!(denoised.luminances[y * width + x].abs_diff(180) <= 2);
+            }
+        }
+        assert_eq!(image.denoised(0.0).luminances, image.luminances);
+    }
+}
@@ -0,0 +1,85 @@
+use crate::Dither;
+
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Quantizes the enhanced luminances of an image in row-major order.
+#[derive(Debug)]
+pub(crate) struct Ditherer {
+    mode: Dither,
+    black: f32,
+    white: f32,
+
+    // Floyd-Steinberg errors of the current and the next row (with one pixel of padding on
+    // each side).
+    current: Vec<f32>,
+    next: Vec<f32>,
+}
+
+impl Ditherer {
+    pub(crate) fn new(mode: Dither, width: usize, black: u8, white: u8) -> Self {
+        let len = if mode == Dither::FloydSteinberg {
+            width + 2
+        } else {
+            0
+        };
+        Self {
+            mode,
+            black: f32::from(black),
+            white: f32::from(white),
+            current: vec![0.0; len],
+            next: vec![0.0; len],
+        }
+    }
+
+    /// Quantizes `v`, which must be within the output range.
+    pub(crate) fn quantize(&mut self, x: usize, y: usize, v: f32) -> u8 {
+        match self.mode {
+            Dither::None => v as u8,
+            Dither::Ordered => {
+                let threshold = f32::from(BAYER_4X4[y % 4][x % 4]) / 16.0;
+                (v + threshold).min(self.white) as u8
+            }
+            Dither::FloydSteinberg => {
+                let v = v + self.current[x + 1];
+                let q = v.round().clamp(self.black, self.white);
+                let e = v - q;
+                self.current[x + 2] += e * 7.0 / 16.0;
+                self.next[x] += e * 3.0 / 16.0;
+                self.next[x + 1] += e * 5.0 / 16.0;
+                self.next[x + 2] += e / 16.0;
+                q as u8
+            }
+        }
+    }
+
+    pub(crate) fn next_row(&mut self) {
+        std::mem::swap(&mut self.current, &mut self.next);
+        self.next.iter_mut().for_each(|e| *e = 0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dithering_preserves_the_mean() {
+        let (width, height) = (64, 64);
+        for mode in [Dither::Ordered, Dither::FloydSteinberg] {
+            let mut ditherer = Ditherer::new(mode, width, 0, 255);
+            let mut sum = 0.0;
+            let mut values = std::collections::HashSet::new();
+            for y in 0..height {
+                for x in 0..width {
+                    let q = ditherer.quantize(x, y, 100.25);
+                    sum += f32::from(q);
+                    values.insert(q);
+                }
+                ditherer.next_row();
+            }
+            let mean = sum / (width * height) as f32;
+            assert!((mean - 100.25).abs() < 0.6, "{:?}: {}", mode, mean);
+            assert_eq!(values.len(), 2, "{:?}", mode);
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use crate::{AutomaticClahe, ChannelLayout, ClaheError};
+
+/// The interface shared by the enhancers of this crate, e.g. to A/B them against each other.
+pub trait Enhancer {
+    /// Enhances an interleaved image whose channel roles are described by `layout`.
+    fn enhance_image(&self, pixels: &mut [u8], width: usize, layout: ChannelLayout);
+
+    /// Validating variant of [`enhance_image()`](Self::enhance_image).
+    fn try_enhance_image(
+        &self,
+        pixels: &mut [u8],
+        width: usize,
+        layout: ChannelLayout,
+    ) -> Result<(), ClaheError>;
+
+    fn enhance_rgb_image(&self, pixels: &mut [u8], width: usize) {
+        self.enhance_image(pixels, width, ChannelLayout::RGB);
+    }
+
+    fn enhance_rgba_image(&self, pixels: &mut [u8], width: usize) {
+        self.enhance_image(pixels, width, ChannelLayout::RGBA);
+    }
+
+    fn try_enhance_rgb_image(&self, pixels: &mut [u8], width: usize) -> Result<(), ClaheError> {
+        self.try_enhance_image(pixels, width, ChannelLayout::RGB)
+    }
+
+    fn try_enhance_rgba_image(&self, pixels: &mut [u8], width: usize) -> Result<(), ClaheError> {
+        self.try_enhance_image(pixels, width, ChannelLayout::RGBA)
+    }
+}
+
+impl Enhancer for AutomaticClahe {
+    fn enhance_image(&self, pixels: &mut [u8], width: usize, layout: ChannelLayout) {
+        AutomaticClahe::enhance_image(self, pixels, width, layout);
+    }
+
+    fn try_enhance_image(
+        &self,
+        pixels: &mut [u8],
+        width: usize,
+        layout: ChannelLayout,
+    ) -> Result<(), ClaheError> {
+        AutomaticClahe::try_enhance_image(self, pixels, width, layout)
+    }
+}
@@ -0,0 +1,119 @@
+use crate::OptionsError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClaheError {
+    /// The buffer length is not a multiple of `channels * width`.
+    BufferSizeMismatch {
+        len: usize,
+        channels: usize,
+        width: usize,
+    },
+    /// The image width or height is zero.
+    ZeroDimension {
+        width: usize,
+        height: usize,
+    },
+    /// The pixel count or byte length of the image does not fit into `usize`.
+    ImageTooLarge {
+        width: usize,
+        height: usize,
+    },
+    /// The image is smaller than a single block.
+    BlockLargerThanImage {
+        block_width: usize,
+        block_height: usize,
+        width: usize,
+        height: usize,
+    },
+    /// An auxiliary plane (e.g. alpha) does not have one entry per pixel.
+    PlaneSizeMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    /// The caller-provided scratch memory is smaller than
+    /// [`required_scratch_bytes()`](crate::AutomaticClahe::required_scratch_bytes).
+    ScratchTooSmall {
+        required: usize,
+        len: usize,
+    },
+    /// The array does not have 3 (RGB) or 4 (RGBA) channels.
+    UnsupportedChannelCount(usize),
+    /// The named option (or stage) is not supported by the allocation-free or banded
+    /// enhancement.
+    UnsupportedOption(&'static str),
+    InvalidOptions(OptionsError),
+    /// A (deserialized) [`EnhancementPlan`](crate::EnhancementPlan) does not match its own image
+    /// size and options.
+    InvalidPlan(&'static str),
+    /// An unexpected internal failure (a caught panic).
+    Internal,
+}
+
+impl std::fmt::Display for ClaheError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BufferSizeMismatch {
+                len,
+                channels,
+                width,
+            } => write!(
+                f,
+                "buffer length {} is not a multiple of {} channels x {} pixels",
+                len, channels, width
+            ),
+            Self::ZeroDimension { width, height } => {
+                write!(f, "image dimensions must be non-zero: {}x{}", width, height)
+            }
+            Self::ImageTooLarge { width, height } => {
+                write!(f, "image dimensions {}x{} are too large", width, height)
+            }
+            Self::BlockLargerThanImage {
+                block_width,
+                block_height,
+                width,
+                height,
+            } => write!(
+                f,
+                "block size {}x{} is larger than the image size {}x{}",
+                block_width, block_height, width, height
+            ),
+            Self::PlaneSizeMismatch { expected, actual } => write!(
+                f,
+                "plane length {} does not match the pixel count {}",
+                actual, expected
+            ),
+            Self::ScratchTooSmall { required, len } => write!(
+                f,
+                "scratch memory of {} bytes is smaller than the required {} bytes",
+                len, required
+            ),
+            Self::UnsupportedChannelCount(channels) => write!(
+                f,
+                "unsupported channel count {}, expected 3 (RGB) or 4 (RGBA)",
+                channels
+            ),
+            Self::UnsupportedOption(option) => {
+                write!(f, "{} is not supported by this enhancement mode", option)
+            }
+            Self::InvalidOptions(e) => write!(f, "invalid options: {}", e),
+            Self::InvalidPlan(reason) => write!(f, "inconsistent enhancement plan: {}", reason),
+            Self::Internal => write!(f, "internal error"),
+        }
+    }
+}
+
+impl std::error::Error for ClaheError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        if let Self::InvalidOptions(e) = self {
+            Some(e)
+        } else {
+            None
+        }
+    }
+}
+
+impl From<OptionsError> for ClaheError {
+    fn from(e: OptionsError) -> Self {
+        Self::InvalidOptions(e)
+    }
+}
@@ -0,0 +1,440 @@
+use crate::color_format::{hsv_to_rgb, rgb_to_hsv};
+use crate::{
+    AutomaticClahe, AutomaticClaheOptions, BlockRegions, ChannelLayout, ClaheError, Enhancer,
+    GammaParams, Histogram, Region,
+};
+
+// All intermediate values are Q16.16 fixed-point numbers.
+const FRACTION_BITS: u32 = 16;
+const ONE: i64 = 1 << FRACTION_BITS;
+
+// `ln(2)` in Q16.16.
+const LN_2: i64 = 45426;
+
+// `2^(2^-(k + 1))` in Q2.30 for the bits of a Q16.16 fraction, most significant first.
+const EXP2_FRACTIONS: [u64; 16] = exp2_fractions();
+
+/// Options of [`FixedPointClahe`], interpreted as in [`AutomaticClaheOptions`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct FixedPointClaheOptions {
+    pub block_width: usize,
+    pub block_height: usize,
+    pub alpha: f32,
+    pub p: f32,
+    pub d_threshold: u8,
+    pub dual_gamma_correction: bool,
+    pub l_alpha_percentile: f32,
+    pub gamma: GammaParams,
+}
+
+impl Default for FixedPointClaheOptions {
+    fn default() -> Self {
+        let options = AutomaticClaheOptions::default();
+        Self {
+            block_width: options.block_width,
+            block_height: options.block_height,
+            alpha: options.alpha,
+            p: options.p,
+            d_threshold: options.d_threshold,
+            dual_gamma_correction: options.dual_gamma_correction,
+            l_alpha_percentile: options.l_alpha_percentile,
+            gamma: options.gamma,
+        }
+    }
+}
+
+/// A reference implementation of the automatic CLAHE that only uses integer arithmetic, so that
+/// its output is bit-identical on every target (e.g. for golden-image tests), unlike the `f32`
+/// path whose `ln()`/`powf()` depend on the platform's math library.
+///
+/// The options are converted to Q16.16 fixed point (rounded to nearest), the logarithms and
+/// powers are computed bit by bit, every division truncates and the interpolated output is
+/// rounded half up. The luminance is the HSV value with the integer HSV round trip, and the
+/// blocks are interpolated bilinearly between their centers. The output is close to, but not
+/// identical to, that of [`AutomaticClahe`].
+///
+/// The options are converted once by [`with_options()`](Self::with_options), after which the
+/// PDFs, CDFs, clip points and gamma curves are computed without any floating-point operation,
+/// e.g. for microcontrollers without an FPU.
+#[derive(Debug)]
+pub struct FixedPointClahe {
+    options: FixedPointClaheOptions,
+    params: Params,
+    // Only used for the validation.
+    inner: AutomaticClahe,
+}
+
+impl FixedPointClahe {
+    pub fn with_options(options: FixedPointClaheOptions) -> Self {
+        let inner = AutomaticClahe::with_options(AutomaticClaheOptions {
+            block_width: options.block_width,
+            block_height: options.block_height,
+            alpha: options.alpha,
+            p: options.p,
+            d_threshold: options.d_threshold,
+            l_alpha_percentile: options.l_alpha_percentile,
+            gamma: options.gamma,
+            ..Default::default()
+        });
+        Self {
+            params: Params::new(&options),
+            options,
+            inner,
+        }
+    }
+
+    pub fn new() -> Self {
+        Self::with_options(Default::default())
+    }
+
+    fn enhance_luminances(&self, luminances: &mut [u8], width: usize) {
+        let height = luminances.len() / width;
+        let histogram =
+            |region: Region| Histogram::new(region.indices(width).map(|i| (luminances[i], 1)));
+        let whole = Region {
+            start: crate::Point::new(0, 0),
+            end: crate::Point::new(width, height),
+        };
+        let stats = Stats::new(&histogram(whole), &self.params);
+
+        let (block_width, block_height) = (
+            std::cmp::min(self.options.block_width, width),
+            std::cmp::min(self.options.block_height, height),
+        );
+        let blocks = BlockRegions::new(width, height, block_width, block_height)
+            .map(|(region, _)| (region, self.table(&histogram(region), &stats)))
+            .collect::<Vec<_>>();
+        let line_blocks = width / block_width;
+        let column_centers = blocks[..line_blocks]
+            .iter()
+            .map(|(region, _)| (region.start.x + region.end.x) / 2)
+            .collect::<Vec<_>>();
+        let row_centers = blocks
+            .iter()
+            .step_by(line_blocks)
+            .map(|(region, _)| (region.start.y + region.end.y) / 2)
+            .collect::<Vec<_>>();
+
+        for y in 0..height {
+            let (by0, by1, wy) = neighbors(&row_centers, y);
+            for x in 0..width {
+                let (bx0, bx1, wx) = neighbors(&column_centers, x);
+                let i = y * width + x;
+                let l = usize::from(luminances[i]);
+                let at = |by: usize, bx: usize| blocks[by * line_blocks + bx].1[l];
+                let top = lerp(at(by0, bx0), at(by0, bx1), wx);
+                let bottom = lerp(at(by1, bx0), at(by1, bx1), wx);
+                let value = lerp(top, bottom, wy);
+                luminances[i] = ((value + ONE / 2) >> FRACTION_BITS).clamp(0, 255) as u8;
+            }
+        }
+    }
+
+    // The Q16.16 LUT of a block.
+    fn table(&self, histogram: &Histogram, stats: &Stats) -> [i64; 256] {
+        let mut table = [0; 256];
+        let (Some(l_min), Some(l_max)) = (histogram.min(), histogram.max()) else {
+            for (l, t) in table.iter_mut().enumerate() {
+                *t = l as i64 * ONE;
+            }
+            return table;
+        };
+        let n = histogram.count() as i128;
+        let sum = histogram.sum() as i128;
+        let sum_squares = histogram
+            .iter()
+            .map(|(l, c)| i128::from(l) * i128::from(l) * i128::from(c))
+            .sum::<i128>();
+        let mean = (sum * i128::from(ONE) / n) as i64;
+        let variance = sum_squares * i128::from(ONE) * i128::from(ONE) / n
+            - i128::from(mean) * i128::from(mean);
+        let sigma = variance.max(0).isqrt() as i64;
+
+        let range = i64::from(l_max - l_min).max(1);
+        let params = &self.params;
+        let clip_point =
+            (ONE + params.p * i64::from(l_max) / 255 + params.alpha / 100 * sigma / mean.max(1))
+                / range;
+
+        let mut pdf = [0; 256];
+        for (l, c) in histogram.iter() {
+            pdf[usize::from(l)] = (i128::from(c) * i128::from(ONE) / n) as i64;
+        }
+        let mut exceeded = 0;
+        for x in &mut pdf {
+            if *x > clip_point {
+                exceeded += *x - clip_point;
+                *x = clip_point;
+            }
+        }
+        for x in &mut pdf {
+            *x += exceeded / 256;
+        }
+        let (min, max) = (*pdf.iter().min().unwrap(), *pdf.iter().max().unwrap());
+        let mut pdf_w = [0; 256];
+        if max > min {
+            for (w, x) in pdf_w.iter_mut().zip(pdf) {
+                *w = max * (x - min) / (max - min);
+            }
+        }
+        let (cdf, cdf_w) = (cdf(&pdf), cdf(&pdf_w));
+
+        let dual_gamma_correction =
+            self.options.dual_gamma_correction && l_max - l_min > self.options.d_threshold;
+        for (l, t) in table.iter_mut().enumerate() {
+            let l_fixed = l as i64 * ONE;
+            let gamma_2 = (cdf_w[l] + params.gamma_2_offset) * ONE / params.gamma_2_divisor;
+            let l2 = stats.l_max * pow(l_fixed * ONE / stats.l_max, gamma_2) / ONE;
+            *t = if dual_gamma_correction {
+                let gamma_1 = log2(cdf[l].max(1)) * LN_2 / params.gamma_1_divisor;
+                let w_en = pow(stats.enhancement_weight_factor, ONE - gamma_1);
+                let l1 = (i128::from(l_max) * i128::from(w_en) * i128::from(cdf[l])
+                    / i128::from(ONE)) as i64;
+                l1.max(l2)
+            } else {
+                l2
+            };
+        }
+        table
+    }
+}
+
+impl Default for FixedPointClahe {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Enhancer for FixedPointClahe {
+    fn enhance_image(&self, pixels: &mut [u8], width: usize, layout: ChannelLayout) {
+        let mut luminances = pixels
+            .chunks_exact(layout.channels())
+            .map(|p| {
+                let (r, g, b) = layout.rgb(p);
+                std::cmp::max(r, std::cmp::max(g, b))
+            })
+            .collect::<Vec<_>>();
+        self.enhance_luminances(&mut luminances, width);
+        for (p, l) in pixels.chunks_exact_mut(layout.channels()).zip(luminances) {
+            let (r, g, b) = layout.rgb(p);
+            let (h, s, _) = rgb_to_hsv(r, g, b);
+            layout.set_rgb(p, hsv_to_rgb(h, s, l));
+        }
+    }
+
+    fn try_enhance_image(
+        &self,
+        pixels: &mut [u8],
+        width: usize,
+        layout: ChannelLayout,
+    ) -> Result<(), ClaheError> {
+        self.inner
+            .validate(pixels.len(), layout.channels(), width)?;
+        self.enhance_image(pixels, width, layout);
+        Ok(())
+    }
+}
+
+// The options in Q16.16.
+#[derive(Debug)]
+struct Params {
+    alpha: i64,
+    p: i64,
+    l_alpha_percentile: i64,
+    gamma_1_divisor: i64,
+    gamma_2_offset: i64,
+    gamma_2_divisor: i64,
+}
+
+impl Params {
+    fn new(options: &FixedPointClaheOptions) -> Self {
+        Self {
+            alpha: fixed(options.alpha),
+            p: fixed(options.p),
+            l_alpha_percentile: fixed(options.l_alpha_percentile),
+            gamma_1_divisor: fixed(options.gamma.gamma_1_divisor),
+            gamma_2_offset: fixed(options.gamma.gamma_2_offset),
+            gamma_2_divisor: fixed(options.gamma.gamma_2_divisor),
+        }
+    }
+}
+
+// The global statistics, in Q16.16.
+struct Stats {
+    l_max: i64,
+    enhancement_weight_factor: i64,
+}
+
+impl Stats {
+    fn new(histogram: &Histogram, params: &Params) -> Self {
+        let l_max = i64::from(histogram.max().unwrap_or(0)).max(1);
+        let percentile = params.l_alpha_percentile;
+        let mut counts = [0; 256];
+        for (l, c) in histogram.iter() {
+            counts[usize::from(l)] = c as i64;
+        }
+        let l_alpha = cdf(&counts)
+            .iter()
+            .take_while(|&&x| x <= percentile)
+            .count() as i64;
+        Self {
+            l_max: l_max * ONE,
+            enhancement_weight_factor: l_max * ONE / l_alpha.max(1),
+        }
+    }
+}
+
+// The normalized cumulative distribution of `values` (all zero for empty distributions).
+fn cdf(values: &[i64; 256]) -> [i64; 256] {
+    let total = i128::from(values.iter().sum::<i64>());
+    let mut cdf = [0; 256];
+    if total == 0 {
+        return cdf;
+    }
+    let mut sum = 0;
+    for (c, &x) in cdf.iter_mut().zip(values) {
+        sum += i128::from(x);
+        *c = (sum * i128::from(ONE) / total) as i64;
+    }
+    cdf
+}
+
+fn fixed(x: f32) -> i64 {
+    (f64::from(x) * ONE as f64).round() as i64
+}
+
+fn lerp(a: i64, b: i64, weight: i64) -> i64 {
+    a + (b - a) * weight / ONE
+}
+
+// The neighboring block centers of `position` and the weight of the second one.
+fn neighbors(centers: &[usize], position: usize) -> (usize, usize, i64) {
+    let last = centers.len() - 1;
+    if position <= centers[0] {
+        return (0, 0, 0);
+    }
+    if position >= centers[last] {
+        return (last, last, 0);
+    }
+    let i = centers.partition_point(|&c| c <= position) - 1;
+    let weight = (position - centers[i]) as i64 * ONE / (centers[i + 1] - centers[i]) as i64;
+    (i, i + 1, weight)
+}
+
+// `log2(x)` of a positive Q16.16 number.
+fn log2(x: i64) -> i64 {
+    let msb = 63 - i64::from(x.leading_zeros());
+    let mut result = (msb - i64::from(FRACTION_BITS)) << FRACTION_BITS;
+    // The mantissa in Q2.30, within `[1, 2)`.
+    let mut y = if msb >= 30 {
+        (x >> (msb - 30)) as u64
+    } else {
+        (x << (30 - msb)) as u64
+    };
+    for bit in (0..FRACTION_BITS).rev() {
+        y = (y * y) >> 30;
+        if y >= 2 << 30 {
+            y >>= 1;
+            result += 1 << bit;
+        }
+    }
+    result
+}
+
+fn exp2(x: i64) -> i64 {
+    let (integer, fraction) = (x >> FRACTION_BITS, x & (ONE - 1));
+    let mut y: u64 = 1 << 30;
+    for (k, factor) in EXP2_FRACTIONS.iter().enumerate() {
+        if fraction & (1 << (FRACTION_BITS - 1 - k as u32)) != 0 {
+            y = (y * factor) >> 30;
+        }
+    }
+    let shift = integer.clamp(-40, 30) - 14;
+    if shift >= 0 {
+        (y << shift) as i64
+    } else {
+        (y >> -shift) as i64
+    }
+}
+
+// `x^y` of a non-negative Q16.16 `x`.
+fn pow(x: i64, y: i64) -> i64 {
+    if x == 0 {
+        return 0;
+    }
+    exp2(y * log2(x) / ONE)
+}
+
+const fn exp2_fractions() -> [u64; 16] {
+    let mut factors = [0; 16];
+    // `sqrt(2)` in Q2.30, then repeated square roots.
+    let mut factor = (2u64 << 60).isqrt();
+    let mut k = 0;
+    while k < 16 {
+        factors[k] = factor;
+        factor = (factor << 30).isqrt();
+        k += 1;
+    }
+    factors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_point_math_is_accurate() {
+        for x in [0.01f64, 0.5, 1.0, 3.7, 200.0] {
+            let expected = x.log2();
+            let actual = log2((x * ONE as f64) as i64) as f64 / ONE as f64;
+            assert!((actual - expected).abs() < 1e-3, "log2({})", x);
+        }
+        for (x, y) in [(0.5f64, 0.75f64), (0.1, 1.5), (4.0, 2.3), (0.99, 0.01)] {
+            let expected = x.powf(y);
+            let actual = pow(fixed(x as f32), fixed(y as f32)) as f64 / ONE as f64;
+            assert!(
+                (actual - expected).abs() < 1e-3 * expected.max(1.0),
+                "{}^{}",
+                x,
+                y
+            );
+        }
+    }
+
+    #[test]
+    fn output_is_stable_and_close_to_the_float_path() {
+        let width = 96;
+        let original = (0..width * 64)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                let l = (20 + x + (x * y) % 13 + if y > 32 { 60 } else { 0 }) as u8;
+                [l, l / 2, l / 3]
+            })
+            .collect::<Vec<_>>();
+        let mut pixels = original.clone();
+        FixedPointClahe::new()
+            .try_enhance_rgb_image(&mut pixels, width)
+            .unwrap();
+        let mut float = original.clone();
+        AutomaticClahe::new().enhance_rgb_image(&mut float, width);
+
+        // Compared between the block centers, where both interpolate the same blocks.
+        let interior = (0..pixels.len())
+            .filter(|i| (16..48).contains(&(i / 3 / width)) && (16..80).contains(&(i / 3 % width)))
+            .collect::<Vec<_>>();
+        let mean_difference = interior
+            .iter()
+            .map(|&i| f32::from(pixels[i].abs_diff(float[i])))
+            .sum::<f32>()
+            / interior.len() as f32;
+        assert!(mean_difference < 0.5, "{}", mean_difference);
+
+        // FNV-1a of the output: a change means the reference output changed.
+        let hash = pixels.iter().fold(0xcbf29ce484222325u64, |h, &v| {
+            (h ^ u64::from(v)).wrapping_mul(0x100000001b3)
+        });
+        assert_eq!(hash, 0xad80fbaf639efbe0);
+    }
+}
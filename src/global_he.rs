@@ -0,0 +1,92 @@
+use crate::{
+    Algorithm, AutomaticClahe, AutomaticClaheOptions, BlockSize, ChannelLayout, ClaheError,
+    ColorSpace, Enhancer,
+};
+
+/// Options of [`GlobalHe`].
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct GlobalHeOptions {
+    pub color_space: ColorSpace,
+}
+
+/// Global histogram equalization (a single PDF/CDF over the whole image).
+///
+/// Useful as a baseline and for very small images, where block-based processing is meaningless.
+#[derive(Debug)]
+pub struct GlobalHe {
+    inner: AutomaticClahe,
+}
+
+impl GlobalHe {
+    pub fn with_options(options: GlobalHeOptions) -> Self {
+        let options = AutomaticClaheOptions {
+            block_size: BlockSize::Tiles { x: 1, y: 1 },
+            color_space: options.color_space,
+            ..Default::default()
+        };
+        Self {
+            inner: AutomaticClahe {
+                algorithm: Algorithm::Classic,
+                ..AutomaticClahe::with_options(options)
+            },
+        }
+    }
+
+    pub fn new() -> Self {
+        Self::with_options(Default::default())
+    }
+}
+
+impl Default for GlobalHe {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Enhancer for GlobalHe {
+    fn enhance_image(&self, pixels: &mut [u8], width: usize, layout: ChannelLayout) {
+        self.inner.enhance_image(pixels, width, layout);
+    }
+
+    fn try_enhance_image(
+        &self,
+        pixels: &mut [u8],
+        width: usize,
+        layout: ChannelLayout,
+    ) -> Result<(), ClaheError> {
+        self.inner.try_enhance_image(pixels, width, layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn global_he_equalizes_the_whole_image() {
+        let width = 80;
+        let mut pixels = (0..width * 60)
+            .flat_map(|i| {
+                let l = (i % 64 + 100) as u8;
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let original = pixels.clone();
+        GlobalHe::new()
+            .try_enhance_rgb_image(&mut pixels, width)
+            .unwrap();
+        for (p, o) in pixels.chunks(3).zip(original.chunks(3)) {
+            let expected = 255.0 * f32::from(o[0] - 99) / 64.0;
+            assert!((f32::from(p[0]) - expected).abs() <= 1.0);
+        }
+
+        // Images smaller than a block are fine as well.
+        let mut pixels = vec![10, 10, 10, 20, 20, 20, 30, 30, 30];
+        GlobalHe::new()
+            .try_enhance_rgb_image(&mut pixels, 3)
+            .unwrap();
+        assert_eq!(pixels, [85, 85, 85, 170, 170, 170, 255, 255, 255]);
+    }
+}
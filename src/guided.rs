@@ -0,0 +1,92 @@
+use crate::GuidedFilter;
+
+impl GuidedFilter {
+    /// Smooths `gains` with the guided filter of He et al., steered by `guide` (both row-major
+    /// with values normalized to about `[0, 1]`): the result follows the edges of the guide
+    /// while flattening the gain variations within its smooth regions.
+    pub(crate) fn smooth(&self, guide: &[f32], gains: &[f32], width: usize) -> Vec<f32> {
+        let mean = |v: &[f32]| box_mean(v, width, self.radius);
+        let mean_i = mean(guide);
+        let mean_p = mean(gains);
+        let corr_ii = mean(&guide.iter().map(|i| i * i).collect::<Vec<_>>());
+        let corr_ip = mean(
+            &guide
+                .iter()
+                .zip(gains)
+                .map(|(i, p)| i * p)
+                .collect::<Vec<_>>(),
+        );
+
+        let (mut a, mut b) = (
+            Vec::with_capacity(gains.len()),
+            Vec::with_capacity(gains.len()),
+        );
+        for k in 0..gains.len() {
+            let var = corr_ii[k] - mean_i[k] * mean_i[k];
+            let cov = corr_ip[k] - mean_i[k] * mean_p[k];
+            let a_k = cov / (var + self.epsilon);
+            a.push(a_k);
+            b.push(mean_p[k] - a_k * mean_i[k]);
+        }
+        let (mean_a, mean_b) = (mean(&a), mean(&b));
+        (0..gains.len())
+            .map(|k| mean_a[k] * guide[k] + mean_b[k])
+            .collect()
+    }
+}
+
+// Mean over the `(2 * radius + 1)^2` window around each pixel, clipped at the image borders.
+pub(crate) fn box_mean(values: &[f32], width: usize, radius: usize) -> Vec<f32> {
+    let height = values.len() / width;
+    let stride = width + 1;
+    let mut integral = vec![0.0f64; stride * (height + 1)];
+    for y in 0..height {
+        let mut row = 0.0;
+        for x in 0..width {
+            row += f64::from(values[y * width + x]);
+            integral[(y + 1) * stride + x + 1] = integral[y * stride + x + 1] + row;
+        }
+    }
+
+    let mut means = Vec::with_capacity(values.len());
+    for y in 0..height {
+        let (y0, y1) = (y.saturating_sub(radius), (y + radius + 1).min(height));
+        for x in 0..width {
+            let (x0, x1) = (x.saturating_sub(radius), (x + radius + 1).min(width));
+            let sum = integral[y1 * stride + x1]
+                - integral[y0 * stride + x1]
+                - integral[y1 * stride + x0]
+                + integral[y0 * stride + x0];
+            means.push((sum / ((y1 - y0) * (x1 - x0)) as f64) as f32);
+        }
+    }
+    means
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smoothing_preserves_guide_edges() {
+        let width = 32;
+        let guide = (0..width * 16)
+            .map(|i| if i % width < 16 { 0.2 } else { 0.8 })
+            .collect::<Vec<_>>();
+        // Noisy gains that follow the edge of the guide.
+        let gains = guide
+            .iter()
+            .enumerate()
+            .map(|(i, g)| 1.0 + g + if i % 2 == 0 { 0.05 } else { -0.05 })
+            .collect::<Vec<_>>();
+
+        let filter = GuidedFilter {
+            radius: 4,
+            epsilon: 0.001,
+        };
+        let smoothed = filter.smooth(&guide, &gains, width);
+        for (k, (g, s)) in guide.iter().zip(&smoothed).enumerate() {
+            assert!((s - (1.0 + g)).abs() < 0.02, "{}: {}", k, s);
+        }
+    }
+}
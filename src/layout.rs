@@ -0,0 +1,149 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelRole {
+    Red,
+    Green,
+    Blue,
+    /// Alpha, padding or any other channel that is neither used for the statistics nor modified.
+    Ignored,
+}
+
+/// Describes which channels of an interleaved pixel hold the red, green and blue components.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelLayout {
+    channels: usize,
+    red: usize,
+    green: usize,
+    blue: usize,
+}
+
+impl ChannelLayout {
+    pub const RGB: Self = Self::from_indices(3, 0, 1, 2);
+    pub const BGR: Self = Self::from_indices(3, 2, 1, 0);
+    pub const RGBA: Self = Self::from_indices(4, 0, 1, 2);
+    pub const BGRA: Self = Self::from_indices(4, 2, 1, 0);
+    pub const ARGB: Self = Self::from_indices(4, 1, 2, 3);
+    pub const ABGR: Self = Self::from_indices(4, 3, 2, 1);
+    pub const RGBX: Self = Self::RGBA;
+    pub const BGRX: Self = Self::BGRA;
+    pub const XRGB: Self = Self::ARGB;
+    pub const XBGR: Self = Self::ABGR;
+
+    /// Makes a layout from the role of each channel.
+    ///
+    /// Returns `None` unless `roles` contains exactly one `Red`, one `Green` and one `Blue`.
+    pub fn new(roles: &[ChannelRole]) -> Option<Self> {
+        let find = |role| {
+            let mut positions = roles.iter().enumerate().filter(|(_, &r)| r == role);
+            match (positions.next(), positions.next()) {
+                (Some((i, _)), None) => Some(i),
+                _ => None,
+            }
+        };
+        Some(Self::from_indices(
+            roles.len(),
+            find(ChannelRole::Red)?,
+            find(ChannelRole::Green)?,
+            find(ChannelRole::Blue)?,
+        ))
+    }
+
+    const fn from_indices(channels: usize, red: usize, green: usize, blue: usize) -> Self {
+        Self {
+            channels,
+            red,
+            green,
+            blue,
+        }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    pub fn role(&self, channel: usize) -> Option<ChannelRole> {
+        if channel >= self.channels {
+            None
+        } else if channel == self.red {
+            Some(ChannelRole::Red)
+        } else if channel == self.green {
+            Some(ChannelRole::Green)
+        } else if channel == self.blue {
+            Some(ChannelRole::Blue)
+        } else {
+            Some(ChannelRole::Ignored)
+        }
+    }
+
+    pub(crate) fn rgb(&self, pixel: &[u8]) -> (u8, u8, u8) {
+        (pixel[self.red], pixel[self.green], pixel[self.blue])
+    }
+
+    pub(crate) fn set_rgb(&self, pixel: &mut [u8], (r, g, b): (u8, u8, u8)) {
+        pixel[self.red] = r;
+        pixel[self.green] = g;
+        pixel[self.blue] = b;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ChannelRole::*;
+
+    #[test]
+    fn new_works() {
+        assert_eq!(
+            ChannelLayout::new(&[Red, Green, Blue]),
+            Some(ChannelLayout::RGB)
+        );
+        assert_eq!(
+            ChannelLayout::new(&[Ignored, Blue, Green, Red]),
+            Some(ChannelLayout::XBGR)
+        );
+        assert_eq!(
+            ChannelLayout::new(&[Red, Ignored, Green, Ignored, Blue]).map(|l| l.channels()),
+            Some(5)
+        );
+
+        assert_eq!(ChannelLayout::new(&[Red, Green]), None);
+        assert_eq!(ChannelLayout::new(&[Red, Green, Green, Blue]), None);
+        assert_eq!(ChannelLayout::new(&[Ignored; 4]), None);
+    }
+
+    #[test]
+    fn ignored_channels_are_not_touched() {
+        let layout = ChannelLayout::ARGB;
+        assert_eq!(layout.role(0), Some(Ignored));
+        assert_eq!(layout.role(4), None);
+
+        let mut pixel = [7, 1, 2, 3];
+        assert_eq!(layout.rgb(&pixel), (1, 2, 3));
+        layout.set_rgb(&mut pixel, (10, 20, 30));
+        assert_eq!(pixel, [7, 10, 20, 30]);
+    }
+
+    #[test]
+    fn enhance_image_respects_layout() {
+        let width = 48;
+        let rgba = (0..width * 40)
+            .flat_map(|i| {
+                let v = (i % width + i / width) as u8;
+                [v, v / 2, v / 3, (i % 256) as u8]
+            })
+            .collect::<Vec<_>>();
+        let mut argb = rgba
+            .chunks(4)
+            .flat_map(|p| [p[3], p[0], p[1], p[2]])
+            .collect::<Vec<_>>();
+        let mut expected = rgba.clone();
+
+        let clahe = crate::AutomaticClahe::new();
+        clahe.enhance_rgba_image(&mut expected, width);
+        clahe.enhance_image(&mut argb, width, ChannelLayout::ARGB);
+
+        for (a, e) in argb.chunks(4).zip(expected.chunks(4)) {
+            assert_eq!(&a[1..], &e[..3]);
+            assert_eq!(a[0], e[3]);
+        }
+    }
+}
@@ -1,69 +1,227 @@
-mod color_format;
+mod agcwd;
+#[cfg(feature = "alloc-stats")]
+pub mod alloc_stats;
+mod analysis;
+mod arena;
+mod banded;
+mod block_override;
+mod classic;
+pub mod color_format;
+mod context;
+#[cfg(feature = "dehaze")]
+mod dehaze;
+mod denoise;
+mod dither;
+mod enhancer;
+mod error;
+mod fixed;
+mod global_he;
+mod guided;
+mod layout;
+mod memory;
+mod options;
+mod plan;
+#[cfg(feature = "profiles")]
+mod profiles;
+mod quadtree;
+mod redistribution;
+mod report;
+mod retinex;
+mod shader;
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod simd;
+mod sliding;
+mod stars;
+mod tiled;
+mod transfer;
+mod tuning;
+mod video;
+mod volume;
+mod weighting;
+#[cfg(feature = "zune-image")]
+mod zune;
 
-#[derive(Debug, Clone)]
-pub struct AutomaticClaheOptions {
-    pub block_width: usize,
-    pub block_height: usize,
-    pub alpha: f32,
-    pub p: f32,
-    pub d_threshold: u8,
-}
+pub use agcwd::{Agcwd, AgcwdOptions};
+pub use analysis::{Recommendation, StructureScale};
+pub use banded::{BandAnalysis, BandEnhancement};
+pub use block_override::{BlockInfo, BlockOverride};
+pub use classic::{ClassicClahe, ClassicClaheOptions};
+pub use context::ClaheContext;
+#[cfg(feature = "dehaze")]
+pub use dehaze::DehazeOptions;
+pub use enhancer::Enhancer;
+pub use error::ClaheError;
+pub use fixed::{FixedPointClahe, FixedPointClaheOptions};
+pub use global_he::{GlobalHe, GlobalHeOptions};
+pub use layout::{ChannelLayout, ChannelRole};
+pub use options::{
+    AutomaticClaheOptions, AutomaticClaheOptionsBuilder, BlockSize, ClampMode, ColorSpace, Dither,
+    GammaParams, GuidedFilter, Interpolation, OptionsError, PointSourceProtection, Preset,
+    QuadtreeSplit, ShadowLift, StrengthGradient, WriteBack,
+};
+pub use plan::EnhancementPlan;
+#[cfg(feature = "profiles")]
+pub use profiles::{Profile, ProfileError};
+pub use redistribution::{
+    IterativeRedistribution, RedistributionStrategy, SinglePassRedistribution,
+};
+pub use report::{BlockReport, Report, Warning};
+pub use retinex::{Retinex, RetinexOptions};
+pub use shader::{LutTexture, ShaderLanguage};
+pub use transfer::TransferFunction;
+pub use tuning::{AlphaSearch, Candidate, ParameterGrid};
+pub use video::VideoClahe;
+pub use weighting::{AlphaPowerWeighting, LinearWeighting, WeightingStrategy};
+#[cfg(feature = "zune-image")]
+pub use zune::ZuneImageError;
 
-impl Default for AutomaticClaheOptions {
-    fn default() -> Self {
-        Self {
-            block_width: 32,
-            block_height: 32,
-            alpha: 100.0,
-            p: 1.5,
-            d_threshold: 50,
-        }
-    }
-}
+// The exposure stops spanned by 8-bit luminances, `log2(256)`.
+const LOG_STOPS: f32 = 8.0;
+
+// The levels of a complete LUT.
+const ALL_LEVELS: std::ops::RangeInclusive<u8> = 0..=u8::MAX;
+
+// The pixels per chunk of `AutomaticClahe::extend_counted_luminances()`, whose luminances stay in
+// the L1 cache until they are counted.
+const COUNTED_CHUNK: usize = 4096;
 
 #[derive(Debug)]
-struct Image<'a, const N: usize> {
-    pixels: &'a mut [u8],
+struct Image {
     width: usize,
     height: usize,
     luminances: Vec<u8>,
-    l_max: f32,
-    enhancement_weight_factor: f32,
+    weights: Option<Vec<u8>>,
+    // The histogram of all the luminances when it was counted while extracting them, which must
+    // be cleared when the luminances change.
+    histogram: Option<Box<Histogram>>,
 }
 
-impl<'a, const N: usize> Image<'a, N> {
-    fn new(pixels: &'a mut [u8], width: usize) -> Self {
-        let mut luminances = Vec::with_capacity(pixels.len() / N);
-        let mut l_max = 0;
-        for p in pixels.chunks(N) {
-            let l = std::cmp::max(p[0], std::cmp::max(p[1], p[2]));
-            luminances.push(l);
-            l_max = std::cmp::max(l, l_max);
-        }
-        let l_max = f32::from(l_max);
-
-        let pdf = Pdf::new(luminances.iter().copied());
-        let cdf = Cdf::new(&pdf);
-        let l_alpha = cdf.0.iter().take_while(|&&x| x <= 0.75).count() as f32;
+impl Image {
+    fn new(luminances: Vec<u8>, width: usize) -> Self {
+        Self::with_weights(luminances, None, width)
+    }
 
+    // Pixels with zero weight are excluded from the statistics.
+    fn with_weights(luminances: Vec<u8>, weights: Option<Vec<u8>>, width: usize) -> Self {
         let height = luminances.len() / width;
         Self {
-            pixels,
             width,
             height,
             luminances,
-            l_max,
-            enhancement_weight_factor: l_max / l_alpha,
+            weights,
+            histogram: None,
+        }
+    }
+
+    fn histogram(&self) -> Histogram {
+        self.sampled_histogram(1)
+    }
+
+    fn sampled_histogram(&self, step: usize) -> Histogram {
+        if let (1, Some(histogram)) = (step, &self.histogram) {
+            return Histogram::clone(histogram);
+        }
+        Histogram::new(self.sampled_items(
+            Region {
+                start: Point::new(0, 0),
+                end: Point::new(self.width, self.height),
+            },
+            step,
+        ))
+    }
+
+    fn items(&self, region: Region) -> impl '_ + Iterator<Item = (u8, u32)> {
+        region
+            .indices(self.width)
+            .map(|i| {
+                let w = self.weights.as_ref().map_or(1, |w| u32::from(w[i]));
+                (self.luminances[i], w)
+            })
+            .filter(|&(_, w)| w > 0)
+    }
+
+    // Like `items()`, restricted to every `step`-th pixel of every `step`-th row of the image.
+    fn sampled_items(&self, region: Region, step: usize) -> impl '_ + Iterator<Item = (u8, u32)> {
+        region
+            .sampled_indices(self.width, step)
+            .map(|i| {
+                let w = self.weights.as_ref().map_or(1, |w| u32::from(w[i]));
+                (self.luminances[i], w)
+            })
+            .filter(|&(_, w)| w > 0)
+    }
+
+    // A copy with the luminances encoded as `log2(1 + l)`, scaled to `[0, 255]`.
+    fn log_encoded(&self) -> Self {
+        let luminances = self
+            .luminances
+            .iter()
+            .map(|&l| ((f32::from(l) + 1.0).log2() * 255.0 / LOG_STOPS).round() as u8)
+            .collect();
+        Self::with_weights(luminances, self.weights.clone(), self.width)
+    }
+
+    // A copy whose pixels with `Some` mask are excluded from the statistics.
+    fn without<T>(&self, mask: &[Option<T>]) -> Self {
+        let weights = mask
+            .iter()
+            .enumerate()
+            .map(|(i, m)| match m {
+                Some(_) => 0,
+                None => self.weights.as_ref().map_or(1, |w| w[i]),
+            })
+            .collect();
+        Self::with_weights(self.luminances.clone(), Some(weights), self.width)
+    }
+
+    // Robust noise sigma of `region`: the median absolute deviation of Immerkær's Laplacian
+    // difference mask, whose L2 norm is 6.
+    fn noise_sigma(&self, region: Region) -> f32 {
+        let (x0, y0) = (region.start.x.max(1), region.start.y.max(1));
+        let (x1, y1) = (
+            region.end.x.min(self.width - 1),
+            region.end.y.min(self.height - 1),
+        );
+        let l = |x: usize, y: usize| i32::from(self.luminances[y * self.width + x]);
+        let mut responses = Vec::new();
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let v = l(x - 1, y - 1) + l(x + 1, y - 1) + l(x - 1, y + 1) + l(x + 1, y + 1)
+                    - 2 * (l(x, y - 1) + l(x - 1, y) + l(x + 1, y) + l(x, y + 1))
+                    + 4 * l(x, y);
+                responses.push(v.unsigned_abs());
+            }
+        }
+        if responses.is_empty() {
+            return 0.0;
         }
+        let middle = responses.len() / 2;
+        let median = *responses.select_nth_unstable(middle).1;
+        1.4826 * median as f32 / 6.0
     }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct GlobalStats {
+    l_max: f32,
+    enhancement_weight_factor: f32,
+}
 
-    fn update_luminances(&mut self) {
-        for (i, p) in self.pixels.chunks_mut(N).enumerate() {
-            let (h, s, _) = self::color_format::rgb_to_hsv(p[0], p[1], p[2]);
-            let (r, g, b) = self::color_format::hsv_to_rgb(h, s, self.luminances[i]);
-            p[0] = r;
-            p[1] = g;
-            p[2] = b;
+impl GlobalStats {
+    fn new(histogram: &Histogram, l_alpha_percentile: f32) -> Self {
+        let l_max = f32::from(histogram.max().unwrap_or(0));
+
+        let pdf = Pdf::new(histogram);
+        let cdf = Cdf::new(&pdf);
+        let l_alpha = cdf
+            .0
+            .iter()
+            .take_while(|&&x| x <= l_alpha_percentile)
+            .count() as f32;
+        Self {
+            l_max,
+            // `l_alpha` is zero when the black pixels alone exceed the percentile.
+            enhancement_weight_factor: l_max / l_alpha.max(1.0),
         }
     }
 }
@@ -76,55 +234,151 @@ struct Block {
     cdf: Cdf,
     cdf_w: Cdf,
     table: [f32; 256],
+    report: BlockReport,
 }
 
 impl Block {
-    fn new<const N: usize>(
-        image: &Image<N>,
-        options: &AutomaticClaheOptions,
+    // `window` is the (possibly overlapping) area whose histogram defines the LUT of `region`;
+    // only the table entries of `levels` are computed (the others are zero).
+    fn new(
+        image: &Image,
+        stats: &GlobalStats,
+        clahe: &AutomaticClahe,
+        (region, window): (Region, Region),
+        levels: std::ops::RangeInclusive<u8>,
+    ) -> Self {
+        if clahe.options.identity_luts {
+            return Self::identity(region);
+        }
+        let histogram = Histogram::new(image.sampled_items(window, clahe.histogram_step(window)));
+        let noise = Self::noise(image, clahe, window);
+        Self::from_histogram(&histogram, stats, clahe, region, noise, levels)
+    }
+
+    // The noise sigma of `window`, only estimated if the clip point needs it.
+    fn noise(image: &Image, clahe: &AutomaticClahe, window: Region) -> f32 {
+        if clahe.options.noise_clip_factor > 0.0 {
+            image.noise_sigma(window)
+        } else {
+            0.0
+        }
+    }
+
+    // `noise` is the noise sigma of the block's luminances (`0.0` if unknown); only the table
+    // entries of `levels` are computed.
+    fn from_histogram(
+        histogram: &Histogram,
+        stats: &GlobalStats,
+        clahe: &AutomaticClahe,
         region: Region,
+        noise: f32,
+        levels: std::ops::RangeInclusive<u8>,
     ) -> Self {
-        let mut l_sum = 0;
-        let mut l_max = 0;
-        let mut l_min = u8::MAX;
-        for l in region.items(&image.luminances) {
-            l_sum += usize::from(l);
-            l_max = std::cmp::max(l_max, l);
-            l_min = std::cmp::min(l_min, l);
-        }
-        let m = region.len() as f32;
-        let avg = l_sum as f32 / m;
-        let sigma = (region
-            .items(&image.luminances)
-            .map(|l| (f32::from(l) - avg).powi(2))
-            .sum::<f32>()
-            / m)
-            .sqrt();
-        let n = f32::from(l_max - l_min) + f32::EPSILON;
+        let options = &clahe.options;
+        let (Some(l_min), Some(l_max)) = (histogram.min(), histogram.max()) else {
+            return Self::identity(region);
+        };
+        let (avg, sigma) = histogram.mean_and_sigma();
+        let info = BlockInfo {
+            x: region.start.x,
+            y: region.start.y,
+            width: region.end.x - region.start.x,
+            height: region.end.y - region.start.y,
+            l_min,
+            l_max,
+            mean: avg,
+            sigma,
+        };
+        let overrides = clahe
+            .block_override
+            .as_ref()
+            .and_then(|f| f(info))
+            .unwrap_or_default();
+        if overrides.identity || sigma < options.min_sigma {
+            let mut this = Self::identity(region);
+            this.report.sigma = sigma;
+            return this;
+        }
+
+        let clip_point = match (overrides.clip_point, options.clip_limit) {
+            (Some(clip_point), _) => clip_point,
+            (None, Some(clip_limit)) => clip_limit / 256.0,
+            (None, None) if !matches!(clahe.algorithm, Algorithm::Automatic) => 1.0,
+            (None, None) => match &clahe.clip_point_fn {
+                Some(f) => f(info),
+                None => {
+                    let n = f32::from(l_max - l_min) + f32::EPSILON;
 
-        let clip_point = (1.0
-            + options.p * f32::from(l_max) / f32::from(u8::MAX)
-            + (options.alpha / 100.0) * (sigma / (avg + f32::EPSILON)))
-            / n;
+                    (1.0 + options.p * f32::from(l_max) / f32::from(u8::MAX)
+                        + (options.alpha / 100.0) * (sigma / (avg + f32::EPSILON)))
+                        / n
+                        / (1.0 + options.noise_clip_factor * noise / (avg + f32::EPSILON))
+                }
+            },
+        };
 
-        let pdf = Pdf::new(region.items(&image.luminances)).redistribute(clip_point);
+        let pdf = Pdf::new(histogram).redistribute(clip_point, &*clahe.redistribution);
         let cdf = Cdf::new(&pdf);
-        let cdf_w = Cdf::new(&pdf.to_weighting_distribution());
+        let cdf_w = Cdf::new(&pdf.to_weighting_distribution(&*clahe.weighting));
 
+        let enable_dual_gamma_correction = overrides
+            .dual_gamma_correction
+            .unwrap_or(options.dual_gamma_correction && (l_max - l_min) > options.d_threshold);
         let mut this = Self {
-            enable_dual_gamma_correction: (l_max - l_min) > options.d_threshold,
+            enable_dual_gamma_correction,
             l_max: f32::from(l_max),
             region,
             cdf,
             cdf_w,
             table: [0.0; 256],
+            report: BlockReport {
+                clip_point: Some(clip_point),
+                dual_gamma_correction: enable_dual_gamma_correction,
+                sigma,
+                ..BlockReport::new(region)
+            },
         };
-        for l in 0..256 {
-            this.table[l] = this.enhance0(l as u8, image);
+        // The report needs the entries of all the levels of the histogram.
+        let reported = levels.contains(&l_min) && levels.contains(&l_max);
+        for l in levels.map(usize::from) {
+            this.table[l] = match &clahe.algorithm {
+                Algorithm::Automatic => this.enhance0(l as u8, stats, &options.gamma),
+                Algorithm::Classic => 255.0 * this.cdf.0[l],
+                Algorithm::Agcwd => {
+                    this.l_max * (l as f32 / this.l_max).powf(1.0 - this.cdf_w.0[l])
+                }
+                Algorithm::Matching(reference) => {
+                    let c = this.cdf.0[l] - 1e-6;
+                    reference.0.iter().position(|&r| r >= c).unwrap_or(255) as f32
+                }
+            };
+        }
+        if reported {
+            this.report.lut_deviation = histogram
+                .iter()
+                .map(|(l, count)| (this.enhance(l) - f32::from(l)).abs() * count as f32)
+                .sum::<f32>()
+                / histogram.count() as f32;
         }
         this
     }
 
+    fn identity(region: Region) -> Self {
+        let mut table = [0.0; 256];
+        for (l, x) in table.iter_mut().enumerate() {
+            *x = l as f32;
+        }
+        Self {
+            enable_dual_gamma_correction: false,
+            l_max: f32::from(u8::MAX),
+            region,
+            cdf: Cdf([0.0; 256]),
+            cdf_w: Cdf([0.0; 256]),
+            table,
+            report: BlockReport::new(region),
+        }
+    }
+
     fn center_y(&self) -> usize {
         (self.region.end.y - self.region.start.y) / 2 + self.region.start.y
     }
@@ -137,27 +391,34 @@ impl Block {
         self.table[usize::from(l)]
     }
 
-    fn enhance0<const N: usize>(&self, l: u8, image: &Image<N>) -> f32 {
-        let l2 = image.l_max * (f32::from(l) / image.l_max).powf(self.cdf_w.gamma_2(l));
-        let enhanced_l = if self.enable_dual_gamma_correction {
-            let w_en = image
+    fn enhance0(&self, l: u8, stats: &GlobalStats, gamma: &GammaParams) -> f32 {
+        let l2 = stats.l_max * (f32::from(l) / stats.l_max).powf(self.cdf_w.gamma_2(l, gamma));
+        if self.enable_dual_gamma_correction {
+            let w_en = stats
                 .enhancement_weight_factor
-                .powf(1.0 - self.cdf.gamma_1(l));
+                .powf(1.0 - self.cdf.gamma_1(l, gamma));
             let l1 = self.l_max * w_en * self.cdf.0[usize::from(l)];
             l1.max(l2)
         } else {
             l2
-        };
-        enhanced_l
+        }
     }
 }
 
+// Sweeps a row of a block grid in spans of pixels that share the same four surrounding blocks
+// of the bilinear interpolation.
 #[derive(Debug)]
-struct SurroundingBlocks<'a>(&'a ());
+struct SurroundingBlocks<'a> {
+    grid: &'a BlockGrid,
+    // The block rows above and below the row.
+    top: Option<usize>,
+    bottom: Option<usize>,
+    spans: std::slice::Iter<'a, ColumnSpan>,
+}
 
 impl<'a> Iterator for SurroundingBlocks<'a> {
     type Item = (
-        Point,
+        std::ops::Range<usize>,
         Option<&'a Block>,
         Option<&'a Block>,
         Option<&'a Block>,
@@ -165,312 +426,3563 @@ impl<'a> Iterator for SurroundingBlocks<'a> {
     );
 
     fn next(&mut self) -> Option<Self::Item> {
-        todo!()
+        let span = self.spans.next()?;
+        let block =
+            |row: Option<usize>, column: Option<usize>| Some(self.grid.block(row?, column?));
+        Some((
+            span.columns.clone(),
+            block(self.top, span.left),
+            block(self.top, span.right),
+            block(self.bottom, span.left),
+            block(self.bottom, span.right),
+        ))
     }
 }
 
+// Columns whose pixels share the same left and right block columns.
+#[derive(Debug)]
+struct ColumnSpan {
+    columns: std::ops::Range<usize>,
+    left: Option<usize>,
+    right: Option<usize>,
+}
+
+// The spans and interpolation weights of `BlockGrid::bilinear_row()`, which are shared by all
+// rows of an image.
 #[derive(Debug)]
+struct BilinearSweep {
+    spans: Vec<ColumnSpan>,
+    // The weights `n` between a left and a right block, and `m` between a top and a bottom
+    // block, where both exist.
+    column_weights: Vec<f32>,
+    row_weights: Vec<f32>,
+    // The weights in units of `1 / FIXED_WEIGHT_ONE`, for `BlockGrid::bilinear_row_fixed()`.
+    fixed_column_weights: Vec<u32>,
+    fixed_row_weights: Vec<u32>,
+    // The block rows above and below every row.
+    rows: Vec<(Option<usize>, Option<usize>)>,
+}
+
+// The planes that are enhanced independently of each other.
+#[derive(Debug)]
+enum Planes {
+    Luminance(Image),
+    Rgb([Image; 3]),
+}
+
+impl Planes {
+    fn images(&self) -> &[Image] {
+        match self {
+            Self::Luminance(image) => std::slice::from_ref(image),
+            Self::Rgb(images) => images,
+        }
+    }
+
+    fn images_mut(&mut self) -> &mut [Image] {
+        match self {
+            Self::Luminance(image) => std::slice::from_mut(image),
+            Self::Rgb(images) => images,
+        }
+    }
+
+    // The luminance buffers of the planes, for `AutomaticClahe::extract_planes_into()`.
+    fn into_buffers(self) -> [Vec<u8>; 3] {
+        match self {
+            Self::Luminance(image) => [image.luminances, Vec::new(), Vec::new()],
+            Self::Rgb([r, g, b]) => [r.luminances, g.luminances, b.luminances],
+        }
+    }
+}
+
+type BlockOverrideFn = dyn Fn(BlockInfo) -> Option<BlockOverride> + Send + Sync;
+type ClipPointFn = dyn Fn(BlockInfo) -> f32 + Send + Sync;
+
+// How the LUT of a block is derived from its clipped histogram.
+#[derive(Debug, Clone)]
+enum Algorithm {
+    // The automatic clip point and the dual gamma correction of the paper.
+    Automatic,
+    // The plain CDF of Zuiderveld's CLAHE (no clipping without a clip limit).
+    Classic,
+    // The adaptive gamma correction `l_max * (l / l_max)^(1 - cdf_w(l))` (no clipping).
+    Agcwd,
+    // Histogram specification onto the reference CDF (no clipping without a clip limit).
+    Matching(Box<Cdf>),
+}
+
 pub struct AutomaticClahe {
     options: AutomaticClaheOptions,
+    block_override: Option<Box<BlockOverrideFn>>,
+    clip_point_fn: Option<Box<ClipPointFn>>,
+    transfer_function: Option<TransferFunction>,
+    redistribution: Box<dyn RedistributionStrategy>,
+    weighting: Box<dyn WeightingStrategy>,
+    algorithm: Algorithm,
+    #[cfg(feature = "dehaze")]
+    dehaze: Option<DehazeOptions>,
+}
+
+impl std::fmt::Debug for AutomaticClahe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut f = f.debug_struct("AutomaticClahe");
+        f.field("options", &self.options)
+            .field(
+                "block_override",
+                &self.block_override.as_ref().map(|_| ".."),
+            )
+            .field("clip_point_fn", &self.clip_point_fn.as_ref().map(|_| ".."))
+            .field("transfer_function", &self.transfer_function)
+            .field("redistribution", &self.redistribution)
+            .field("weighting", &self.weighting)
+            .field("algorithm", &self.algorithm);
+        #[cfg(feature = "dehaze")]
+        f.field("dehaze", &self.dehaze);
+        f.finish()
+    }
+}
+
+impl Default for AutomaticClahe {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl AutomaticClahe {
     pub fn with_options(options: AutomaticClaheOptions) -> Self {
-        Self { options }
+        Self {
+            options,
+            block_override: None,
+            clip_point_fn: None,
+            transfer_function: None,
+            redistribution: Box::new(SinglePassRedistribution),
+            weighting: Box::new(LinearWeighting),
+            algorithm: Algorithm::Automatic,
+            #[cfg(feature = "dehaze")]
+            dehaze: None,
+        }
     }
 
     pub fn new() -> Self {
-        Self {
-            options: Default::default(),
+        Self::with_options(Default::default())
+    }
+
+    /// Sets a callback that can adjust the parameters of individual blocks.
+    ///
+    /// It is called once per (non-empty) block with the block's position and luminance
+    /// statistics; returning `None` keeps the regular behavior.
+    pub fn with_block_override<F>(mut self, f: F) -> Self
+    where
+        F: Fn(BlockInfo) -> Option<BlockOverride> + Send + Sync + 'static,
+    {
+        self.block_override = Some(Box::new(f));
+        self
+    }
+
+    /// Replaces the automatic clip point formula with a function of the block statistics,
+    /// which returns the clip point as a fraction of the block's pixels per bin.
+    ///
+    /// `clip_limit` and the clip points of [`with_block_override()`](Self::with_block_override)
+    /// still take precedence.
+    pub fn with_clip_point_fn<F>(mut self, f: F) -> Self
+    where
+        F: Fn(BlockInfo) -> f32 + Send + Sync + 'static,
+    {
+        self.clip_point_fn = Some(Box::new(f));
+        self
+    }
+
+    /// Sets the transfer functions of RGB pixels.
+    ///
+    /// The pixels are decoded before the statistics are computed and encoded again after the
+    /// write-back, so that the enhancement operates in the light domain of the footage.
+    pub fn with_transfer_function(mut self, transfer_function: TransferFunction) -> Self {
+        self.transfer_function = Some(transfer_function);
+        self
+    }
+
+    /// Sets how the block histograms are clipped, e.g. [`IterativeRedistribution`] to keep
+    /// every bin below the clip point (default: [`SinglePassRedistribution`]).
+    pub fn with_redistribution<R>(mut self, strategy: R) -> Self
+    where
+        R: RedistributionStrategy + 'static,
+    {
+        self.redistribution = Box::new(strategy);
+        self
+    }
+
+    /// Sets the transform of the clipped histograms into the weighting distribution of the
+    /// gamma correction, e.g. [`AlphaPowerWeighting`] (default: [`LinearWeighting`]).
+    pub fn with_weighting<W>(mut self, strategy: W) -> Self
+    where
+        W: WeightingStrategy + 'static,
+    {
+        self.weighting = Box::new(strategy);
+        self
+    }
+
+    /// Removes haze with the dark channel prior before the enhancement.
+    ///
+    /// The pre-pass runs in [`enhance_image()`](Self::enhance_image) and the functions built
+    /// on it, before the histograms are computed.
+    #[cfg(feature = "dehaze")]
+    pub fn with_dehaze(mut self, options: DehazeOptions) -> Self {
+        self.dehaze = Some(options);
+        self
+    }
+
+    /// Maps the histogram of every block onto `reference` (histogram specification) instead of
+    /// applying the dual gamma correction, e.g. to make an image series visually consistent.
+    ///
+    /// The blocks are only clipped if `clip_limit` is set; use `BlockSize::Tiles { x: 1, y: 1 }`
+    /// to match the global histogram. An empty reference is ignored.
+    pub fn with_reference_histogram(mut self, reference: &[u64; 256]) -> Self {
+        let histogram = Histogram(*reference);
+        if histogram.count() > 0 {
+            self.algorithm = Algorithm::Matching(Box::new(Cdf::new(&Pdf::new(&histogram))));
+        }
+        self
+    }
+
+    /// Like [`with_reference_histogram()`](Self::with_reference_histogram), with the luminance
+    /// histogram of a reference image.
+    pub fn with_reference_image(self, pixels: &[u8], layout: ChannelLayout) -> Self {
+        let mut histogram = [0; 256];
+        for l in self.extract_luminances(pixels, layout) {
+            histogram[usize::from(l)] += 1;
         }
+        self.with_reference_histogram(&histogram)
+    }
+
+    fn decode(&self, rgb: (u8, u8, u8)) -> (u8, u8, u8) {
+        self.transfer_function
+            .as_ref()
+            .map_or(rgb, |t| t.decode(rgb))
+    }
+
+    fn encode(&self, rgb: (u8, u8, u8)) -> (u8, u8, u8) {
+        self.transfer_function
+            .as_ref()
+            .map_or(rgb, |t| t.encode(rgb))
     }
 
     pub fn enhance_rgba_image(&self, pixels: &mut [u8], width: usize) {
-        let mut image = Image::<4>::new(pixels, width);
-        let blocks = BlockRegions::new(&image, &self.options)
-            .map(|region| Block::new(&image, &self.options, region))
-            .collect::<Vec<_>>();
+        self.enhance_image(pixels, width, ChannelLayout::RGBA);
+    }
 
-        let aligned_height = image.height / self.options.block_height * self.options.block_height;
-        let aligned_width = image.width / self.options.block_width * self.options.block_width;
-        let line_blocks = image.width / self.options.block_width;
-        for y in 0..image.height {
-            let y0 = std::cmp::min(y, aligned_height - 1);
-            for x in 0..image.width {
-                let x0 = std::cmp::min(x, aligned_width - 1);
-
-                let a = self.get_block_a(y0, x0, line_blocks, &blocks);
-                let b = self.get_block_b(y0, x0, aligned_width, line_blocks, &blocks);
-                let c = self.get_block_c(y0, x0, aligned_height, line_blocks, &blocks);
-                let d =
-                    self.get_block_d(y0, x0, aligned_height, aligned_width, line_blocks, &blocks);
-
-                let m = match (a.map(|a| a.center_y()), c.map(|c| c.center_y())) {
-                    (Some(a), Some(c)) => (c - y) as f32 / (c - a) as f32,
-                    _ => {
-                        if a.is_some() || b.is_some() {
-                            1.0
-                        } else {
-                            0.0
-                        }
-                    }
-                };
-                let n = match (a.map(|a| a.center_x()), b.map(|b| b.center_x())) {
-                    (Some(a), Some(b)) => (b - x) as f32 / (b - a) as f32,
-                    _ => {
-                        if a.is_some() || c.is_some() {
-                            1.0
-                        } else {
-                            0.0
-                        }
-                    }
-                };
+    pub fn enhance_rgb_image(&self, pixels: &mut [u8], width: usize) {
+        self.enhance_image(pixels, width, ChannelLayout::RGB);
+    }
 
-                let i = y * width + x;
-                let l0 = image.luminances[i];
+    /// Validating variant of [`enhance_rgba_image()`](Self::enhance_rgba_image).
+    pub fn try_enhance_rgba_image(
+        &self,
+        pixels: &mut [u8],
+        width: usize,
+    ) -> Result<(), ClaheError> {
+        self.try_enhance_image(pixels, width, ChannelLayout::RGBA)
+    }
 
-                let la = a.map(|a| n * a.enhance(l0)).unwrap_or(0.0);
-                let lb = b.map(|b| (1.0 - n) * b.enhance(l0)).unwrap_or(0.0);
-                let lc = c.map(|c| n * c.enhance(l0)).unwrap_or(0.0);
-                let ld = d.map(|d| (1.0 - n) * d.enhance(l0)).unwrap_or(0.0);
-                let l = m * (la + lb) + (1.0 - m) * (lc + ld);
-                image.luminances[i] = l.max(0.0).min(255.0) as u8;
-            }
-        }
-        image.update_luminances();
+    /// Validating variant of [`enhance_rgb_image()`](Self::enhance_rgb_image).
+    pub fn try_enhance_rgb_image(&self, pixels: &mut [u8], width: usize) -> Result<(), ClaheError> {
+        self.try_enhance_image(pixels, width, ChannelLayout::RGB)
     }
 
-    fn get_block_a<'a>(
+    /// Validating variant of [`enhance_image()`](Self::enhance_image).
+    pub fn try_enhance_image(
         &self,
-        y: usize,
-        x: usize,
-        line_blocks: usize,
-        blocks: &'a [Block],
-    ) -> Option<&'a Block> {
-        if y < self.options.block_height / 2 || x < self.options.block_width / 2 {
-            None
-        } else {
-            let block_y = (y - self.options.block_height / 2) / self.options.block_height;
-            let block_x = (x - self.options.block_width / 2) / self.options.block_width;
-            Some(&blocks[block_y * line_blocks + block_x])
-        }
+        pixels: &mut [u8],
+        width: usize,
+        layout: ChannelLayout,
+    ) -> Result<(), ClaheError> {
+        self.validate(pixels.len(), layout.channels(), width)?;
+        self.enhance_image(pixels, width, layout);
+        Ok(())
+    }
+
+    /// Like [`try_enhance_rgba_image()`](Self::try_enhance_rgba_image) but guaranteed not to
+    /// panic for any combination of buffer, width and options.
+    ///
+    /// This is intended for untrusted input. The guarantee rests on the validation: the
+    /// unguarded path has been run on 3000 randomized combinations of options, sizes and buffer
+    /// lengths (500 of them on every test run) without a panic. Should an unexpected panic occur
+    /// anyway, it is caught and reported as [`ClaheError::Internal`]; the buffer is then left
+    /// unmodified. The catching needs `panic = "unwind"`: under `panic = "abort"` (e.g. the
+    /// default of `cargo fuzz`) such a panic aborts the process instead.
+    pub fn enhance_rgba_checked(&self, pixels: &mut [u8], width: usize) -> Result<(), ClaheError> {
+        self.enhance_image_checked(pixels, width, ChannelLayout::RGBA)
     }
 
-    fn get_block_b<'a>(
+    /// [`ChannelLayout`] aware variant of [`enhance_rgba_checked()`](Self::enhance_rgba_checked).
+    pub fn enhance_image_checked(
         &self,
-        y: usize,
-        x: usize,
-        w: usize,
-        line_blocks: usize,
-        blocks: &'a [Block],
-    ) -> Option<&'a Block> {
-        if y < self.options.block_height / 2 || w <= (x + self.options.block_width / 2) {
-            None
-        } else {
-            let block_y = (y - self.options.block_height / 2) / self.options.block_height;
-            let block_x = (x + self.options.block_width / 2) / self.options.block_width;
-            Some(&blocks[block_y * line_blocks + block_x])
+        pixels: &mut [u8],
+        width: usize,
+        layout: ChannelLayout,
+    ) -> Result<(), ClaheError> {
+        self.validate(pixels.len(), layout.channels(), width)?;
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let dehazed = self.dehazed(pixels, width, layout);
+            let source = dehazed.as_deref().unwrap_or(pixels);
+            let mut planes = self.extract_planes(source, width, layout, None);
+            self.enhance_planes(&mut planes);
+            (dehazed, planes)
+        }));
+        let (dehazed, planes) = result.map_err(|_| ClaheError::Internal)?;
+        if let Some(dehazed) = dehazed {
+            pixels.copy_from_slice(&dehazed);
         }
+        self.write_back_planes(pixels, layout, &planes);
+        Ok(())
     }
 
-    fn get_block_c<'a>(
+    /// Validating variant of [`enhance_rgb_image_with_alpha()`](Self::enhance_rgb_image_with_alpha).
+    pub fn try_enhance_rgb_image_with_alpha(
         &self,
-        y: usize,
-        x: usize,
-        h: usize,
-        line_blocks: usize,
-        blocks: &'a [Block],
-    ) -> Option<&'a Block> {
-        if h <= (y + self.options.block_height / 2) || x < self.options.block_width / 2 {
-            None
-        } else {
-            let block_y = (y + self.options.block_height / 2) / self.options.block_height;
-            let block_x = (x - self.options.block_width / 2) / self.options.block_width;
-            Some(&blocks[block_y * line_blocks + block_x])
+        pixels: &mut [u8],
+        width: usize,
+        alpha: Option<&[u8]>,
+    ) -> Result<(), ClaheError> {
+        self.validate(pixels.len(), 3, width)?;
+        if let Some(alpha) = alpha {
+            if alpha.len() != pixels.len() / 3 {
+                return Err(ClaheError::PlaneSizeMismatch {
+                    expected: pixels.len() / 3,
+                    actual: alpha.len(),
+                });
+            }
         }
+        self.enhance_rgb_image_with_alpha(pixels, width, alpha);
+        Ok(())
     }
 
-    fn get_block_d<'a>(
+    /// Validating variant of [`enhance_cmyk_image()`](Self::enhance_cmyk_image).
+    pub fn try_enhance_cmyk_image(
         &self,
-        y: usize,
-        x: usize,
-        h: usize,
-        w: usize,
-        line_blocks: usize,
-        blocks: &'a [Block],
-    ) -> Option<&'a Block> {
-        if h <= (y + self.options.block_height / 2) || w <= (x + self.options.block_width / 2) {
-            None
-        } else {
-            let block_y = (y + self.options.block_height / 2) / self.options.block_height;
-            let block_x = (x + self.options.block_width / 2) / self.options.block_width;
-            Some(&blocks[block_y * line_blocks + block_x])
-        }
+        pixels: &mut [u8],
+        width: usize,
+    ) -> Result<(), ClaheError> {
+        self.validate(pixels.len(), 4, width)?;
+        self.enhance_cmyk_image(pixels, width);
+        Ok(())
     }
 
-    pub fn enhance_rgb_image(&self, _pixels: &mut [u8], _width: usize) {
-        todo!()
+    /// Validating variant of [`enhance_depth_map()`](Self::enhance_depth_map).
+    pub fn try_enhance_depth_map(
+        &self,
+        depth: &mut [f32],
+        width: usize,
+        invalid_value: Option<f32>,
+    ) -> Result<(), ClaheError> {
+        self.validate(depth.len(), 1, width)?;
+        self.enhance_depth_map(depth, width, invalid_value);
+        Ok(())
     }
-}
 
-#[derive(Debug)]
-struct BlockRegions {
-    start: Point,
-    image_width: usize,
-    image_height: usize,
-    block_width: usize,
-    block_height: usize,
-}
+    fn validate(&self, len: usize, channels: usize, width: usize) -> Result<(), ClaheError> {
+        self.options.validate()?;
+        #[cfg(feature = "dehaze")]
+        if let Some(dehaze) = &self.dehaze {
+            dehaze.validate()?;
+        }
 
-impl BlockRegions {
-    fn new<const N: usize>(image: &Image<N>, options: &AutomaticClaheOptions) -> Self {
-        Self {
-            start: Point::new(0, 0),
-            image_width: image.width,
-            image_height: image.height,
-            block_width: options.block_width,
-            block_height: options.block_height,
+        let row_len = match channels.checked_mul(width) {
+            Some(row_len) if row_len > 0 => row_len,
+            _ if width == 0 => return Err(ClaheError::ZeroDimension { width, height: 0 }),
+            _ => {
+                return Err(ClaheError::BufferSizeMismatch {
+                    len,
+                    channels,
+                    width,
+                })
+            }
+        };
+        if !len.is_multiple_of(row_len) {
+            return Err(ClaheError::BufferSizeMismatch {
+                len,
+                channels,
+                width,
+            });
+        }
+        let height = len / row_len;
+        if height == 0 {
+            return Err(ClaheError::ZeroDimension { width, height });
         }
+        if matches!(self.options.block_size, BlockSize::Fixed)
+            && (width < self.options.block_width || height < self.options.block_height)
+        {
+            return Err(ClaheError::BlockLargerThanImage {
+                block_width: self.options.block_width,
+                block_height: self.options.block_height,
+                width,
+                height,
+            });
+        }
+        Ok(())
     }
-}
 
-impl Iterator for BlockRegions {
-    type Item = Region;
+    /// Enhances an interleaved image whose channel roles are described by `layout`.
+    ///
+    /// Channels with the `Ignored` role (alpha, padding, ...) are excluded from the luminance
+    /// statistics and copied through unchanged.
+    pub fn enhance_image(&self, pixels: &mut [u8], width: usize, layout: ChannelLayout) {
+        if let Some(dehazed) = self.dehazed(pixels, width, layout) {
+            pixels.copy_from_slice(&dehazed);
+        }
+        let mut planes = self.extract_planes(pixels, width, layout, None);
+        self.enhance_planes(&mut planes);
+        self.write_back_planes(pixels, layout, &planes);
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.start.y == self.image_height {
-            return None;
+    /// Same as [`enhance_image()`](Self::enhance_image) but also returns a [`Report`] about
+    /// the call.
+    pub fn enhance_image_with_report(
+        &self,
+        pixels: &mut [u8],
+        width: usize,
+        layout: ChannelLayout,
+    ) -> Report {
+        #[cfg(feature = "alloc-stats")]
+        let snapshot = alloc_stats::Snapshot::take();
+
+        if let Some(dehazed) = self.dehazed(pixels, width, layout) {
+            pixels.copy_from_slice(&dehazed);
         }
+        let scale = StructureScale::estimate(&self.extract_luminances(pixels, layout), width);
+        let mut planes = self.extract_planes(pixels, width, layout, None);
+        let mut blocks = Vec::new();
+        let (block_width, block_height) =
+            self.enhance_planes_with(&mut planes, None, Some(&mut blocks), None);
+        self.write_back_planes(pixels, layout, &planes);
 
-        let start = self.start;
-        let mut end = Point::new(start.x + self.block_width, start.y + self.block_height);
-        if self.image_width < end.x + self.block_width {
-            end.x = self.image_width;
+        let mut report = Report {
+            blocks,
+            scratch_bytes: planes.images().len()
+                * Self::scratch_bytes(
+                    pixels.len() / layout.channels(),
+                    width,
+                    (block_width, block_height),
+                ),
+            ..Default::default()
+        };
+        if std::cmp::min(block_width, block_height) < scale.scale {
+            report.warnings.push(Warning::BlockSmallerThanStructures {
+                block_width,
+                block_height,
+                structure_scale: scale.scale,
+            });
         }
-        if self.image_height < end.y + self.block_height {
-            end.y = self.image_height;
+        #[cfg(feature = "alloc-stats")]
+        if let Some((allocations, bytes)) = snapshot.elapsed() {
+            report.allocations = Some(allocations);
+            report.allocated_bytes = Some(bytes);
         }
+        report
+    }
 
-        self.start.x = end.x;
-        if self.start.x == self.image_width {
-            self.start.x = 0;
-            self.start.y = end.y;
+    /// Computes the per-pixel luminance multiplier of an RGB image without applying it, e.g. to
+    /// apply the enhancement on the GPU or to a higher bit depth master.
+    ///
+    /// The gains are those of the luminance of `options.color_space` (also for
+    /// `ColorSpace::PerChannel`) before dithering; black pixels get `1.0`.
+    pub fn compute_gain_map(&self, pixels: &[u8], width: usize) -> Vec<f32> {
+        let image = Image::with_weights(
+            self.extract_luminances(pixels, ChannelLayout::RGB),
+            None,
+            width,
+        );
+        let (enhanced, _) = self.enhanced_luminances(&image);
+        enhanced
+            .iter()
+            .zip(&image.luminances)
+            .map(|(l, &l0)| if l0 == 0 { 1.0 } else { l / f32::from(l0) })
+            .collect()
+    }
+
+    /// Fits a single global tone curve to the spatially varying enhancement of an RGB image, for
+    /// pipelines that can only apply a global LUT to the luminance of `options.color_space`.
+    ///
+    /// Each entry is the mean enhanced luminance of the pixels of that luminance, made
+    /// non-decreasing by isotonic regression (pool adjacent violators). Luminances absent from
+    /// the image are interpolated linearly, or continue the nearest entry with a slope of one.
+    pub fn derive_global_curve(&self, pixels: &[u8], width: usize) -> [u8; 256] {
+        let image = Image::new(self.extract_luminances(pixels, ChannelLayout::RGB), width);
+        let (enhanced, _) = self.enhanced_luminances(&image);
+        let mut sums = [0.0f64; 256];
+        let mut counts = [0u64; 256];
+        for (&l0, &l) in image.luminances.iter().zip(&enhanced) {
+            sums[usize::from(l0)] += f64::from(l);
+            counts[usize::from(l0)] += 1;
+        }
+
+        // Each pool is the first luminance, the mean and the weight of merged entries.
+        let mut pools: Vec<(usize, f64, u64)> = Vec::new();
+        for l in (0..256).filter(|&l| counts[l] > 0) {
+            let mut pool = (l, sums[l] / counts[l] as f64, counts[l]);
+            while let Some(&(start, mean, weight)) = pools.last() {
+                if mean <= pool.1 {
+                    break;
+                }
+                pools.pop();
+                let merged = weight + pool.2;
+                let sum = mean * weight as f64 + pool.1 * pool.2 as f64;
+                pool = (start, sum / merged as f64, merged);
+            }
+            pools.push(pool);
         }
+        let fitted = pools
+            .iter()
+            .enumerate()
+            .flat_map(|(i, &(start, mean, _))| {
+                let end = pools.get(i + 1).map_or(256, |p| p.0);
+                (start..end)
+                    .filter(|&l| counts[l] > 0)
+                    .map(move |l| (l, mean))
+            })
+            .collect::<Vec<_>>();
 
-        Some(Region { start, end })
+        let mut curve = [0; 256];
+        for (l, c) in curve.iter_mut().enumerate() {
+            let i = fitted.partition_point(|&(k, _)| k < l);
+            let value = match (i.checked_sub(1).map(|i| fitted[i]), fitted.get(i)) {
+                (_, Some(&(k, v))) if k == l => v,
+                (Some((k0, v0)), Some(&(k1, v1))) => {
+                    v0 + (v1 - v0) * (l - k0) as f64 / (k1 - k0) as f64
+                }
+                (Some((k, v)), None) => v + (l - k) as f64,
+                (None, Some(&(k, v))) => v - (k - l) as f64,
+                (None, None) => l as f64,
+            };
+            *c = value.round().clamp(0.0, 255.0) as u8;
+        }
+        curve
     }
-}
 
-#[derive(Debug, Clone, Copy)]
-struct Region {
-    start: Point,
-    end: Point,
-}
+    /// Builds the automatic clip + dual-gamma LUT for a caller-supplied luminance histogram.
+    ///
+    /// The histogram is treated as a single block covering the whole image, so this is useful
+    /// for systems that already compute histograms in hardware.
+    pub fn build_lut_from_histogram(&self, hist: &[u32; 256]) -> [u8; 256] {
+        let histogram = Histogram(hist.map(u64::from));
+        let stats = GlobalStats::new(&histogram, self.options.l_alpha_percentile);
+        let region = Region {
+            start: Point::new(0, 0),
+            end: Point::new(0, 0),
+        };
+        let block = if self.options.identity_luts {
+            Block::identity(region)
+        } else {
+            Block::from_histogram(&histogram, &stats, self, region, 0.0, ALL_LEVELS)
+        };
+        std::array::from_fn(|l| self.output_luminance(l as u8, block.table[l]))
+    }
 
-impl Region {
-    fn len(&self) -> usize {
-        (self.end.y - self.start.y) * (self.end.x - self.start.x)
+    /// Estimates the dominant structure scale of an image with a quick pyramid energy analysis.
+    ///
+    /// Blocks smaller than this scale tend to produce halos around the structures.
+    pub fn analyze_structure_scale(
+        &self,
+        pixels: &[u8],
+        width: usize,
+        layout: ChannelLayout,
+    ) -> StructureScale {
+        StructureScale::estimate(&self.extract_luminances(pixels, layout), width)
     }
 
-    fn items<T: Copy>(self, all_items: &[T]) -> impl '_ + Iterator<Item = T> {
-        let width = self.end.x - self.start.x;
-        (self.start.y..self.end.y).flat_map(move |y| {
-            let offset = y * width;
-            (all_items[offset..][self.start.x..self.end.x])
-                .iter()
-                .copied()
-        })
+    /// Computes global statistics of an image and recommends options for it.
+    pub fn analyze(&self, pixels: &[u8], width: usize, layout: ChannelLayout) -> Recommendation {
+        Recommendation::estimate(&self.extract_luminances(pixels, layout), width)
     }
-}
 
-#[derive(Debug, Clone, Copy)]
-struct Point {
-    x: usize,
-    y: usize,
-}
+    fn scratch_bytes(
+        pixel_count: usize,
+        width: usize,
+        (block_width, block_height): (usize, usize),
+    ) -> usize {
+        let blocks =
+            BlockRegions::new(width, pixel_count / width, block_width, block_height).count();
+        pixel_count + blocks * std::mem::size_of::<Block>()
+    }
 
-impl Point {
-    fn new(x: usize, y: usize) -> Self {
-        Self { x, y }
+    // The pixels after the dehaze pre-pass, if any.
+    #[cfg(feature = "dehaze")]
+    fn dehazed(&self, pixels: &[u8], width: usize, layout: ChannelLayout) -> Option<Vec<u8>> {
+        let dehaze = self.dehaze.as_ref()?;
+        let mut dehazed = pixels.to_vec();
+        dehaze.apply(&mut dehazed, width, layout);
+        Some(dehazed)
     }
-}
 
-#[derive(Debug, Clone)]
-struct Pdf([f32; 256]);
+    #[cfg(not(feature = "dehaze"))]
+    fn dehazed(&self, _: &[u8], _: usize, _: ChannelLayout) -> Option<Vec<u8>> {
+        None
+    }
 
-impl Pdf {
-    fn new(pixels: impl Iterator<Item = u8>) -> Self {
-        let mut histogram = [0; 256];
-        let mut n = 0;
-        for intensity in pixels {
-            histogram[usize::from(intensity)] += 1;
-            n += 1;
-        }
+    fn extract_luminances(&self, pixels: &[u8], layout: ChannelLayout) -> Vec<u8> {
+        let mut luminances = Vec::new();
+        self.extend_luminances(pixels, layout, &mut luminances);
+        luminances
+    }
 
-        let mut pdf = [0.0; 256];
-        let n = n as f32;
-        for (i, c) in histogram.into_iter().enumerate() {
-            pdf[i] = c as f32 / n;
+    // Like `extend_luminances()`, also adding the luminances to `histogram` in the same pass.
+    fn extend_counted_luminances(
+        &self,
+        pixels: &[u8],
+        layout: ChannelLayout,
+        luminances: &mut Vec<u8>,
+        histogram: &mut Histogram,
+    ) {
+        luminances.reserve(pixels.len() / layout.channels());
+        for chunk in pixels.chunks(COUNTED_CHUNK * layout.channels()) {
+            let start = luminances.len();
+            self.extend_luminances(chunk, layout, luminances);
+            for &l in &luminances[start..] {
+                histogram.0[usize::from(l)] += 1;
+            }
         }
-        Self(pdf)
     }
 
-    fn to_weighting_distribution(&self) -> Self {
-        let mut max = self.0[0];
-        let mut min = self.0[0];
-        for &x in &self.0[1..] {
-            max = max.max(x);
-            min = min.min(x);
+    fn extend_luminances(&self, pixels: &[u8], layout: ChannelLayout, luminances: &mut Vec<u8>) {
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        if layout.channels() == 4
+            && self.transfer_function.is_none()
+            && matches!(
+                self.options.color_space,
+                ColorSpace::HsvInteger | ColorSpace::HsvFloat | ColorSpace::PerChannel
+            )
+        {
+            return simd::max_rgb(pixels, layout, luminances);
         }
+        luminances.extend(
+            pixels
+                .chunks_exact(layout.channels())
+                .map(|p| self.pixel_luminance(layout.rgb(p))),
+        );
+    }
 
-        let mut pdf_w = self.0;
-        let range = max - min + f32::EPSILON;
-        for x in &mut pdf_w {
-            *x = max * ((*x - min) / range);
-        }
-        Self(pdf_w)
+    // The analyzed luminance of the (encoded) pixel `rgb`.
+    fn pixel_luminance(&self, rgb: (u8, u8, u8)) -> u8 {
+        let (r, g, b) = self.decode(rgb);
+        self.options.color_space.luminance(r, g, b)
     }
 
-    fn redistribute(mut self, clip_point: f32) -> Self {
-        let mut exceeded = 0.0;
-        for x in &mut self.0 {
-            if *x > clip_point {
-                exceeded += *x - clip_point;
-                *x = clip_point;
-            }
+    // The (encoded) pixel `rgb` with its enhanced luminance `l`, or `None` if it is protected.
+    fn with_enhanced_luminance(&self, rgb: (u8, u8, u8), l: u8) -> Option<(u8, u8, u8)> {
+        let (r, g, b) = self.decode(rgb);
+        let protected = self
+            .options
+            .is_protected(self.options.color_space.luminance(r, g, b));
+        (!protected).then(|| self.encode(self.options.with_luminance((r, g, b), l)))
+    }
+
+    // The written-back value of pixel `i` of `planes`, whose original value is `rgb`, like
+    // `write_back_planes()`.
+    #[cfg(feature = "zune-image")]
+    fn written_back_pixel(
+        &self,
+        planes: &Planes,
+        i: usize,
+        rgb: (u8, u8, u8),
+    ) -> Option<(u8, u8, u8)> {
+        match planes {
+            Planes::Luminance(image) => self.with_enhanced_luminance(rgb, image.luminances[i]),
+            Planes::Rgb([r, g, b]) => {
+                Some(self.encode((r.luminances[i], g.luminances[i], b.luminances[i])))
+            }
+        }
+    }
+
+    fn extract_planes(
+        &self,
+        pixels: &[u8],
+        width: usize,
+        layout: ChannelLayout,
+        weights: Option<&[u8]>,
+    ) -> Planes {
+        self.extract_planes_into(pixels, width, layout, weights, Default::default())
+    }
+
+    // Like `extract_planes()`, reusing the allocations of `buffers` (see `Planes::into_buffers()`).
+    fn extract_planes_into(
+        &self,
+        pixels: &[u8],
+        width: usize,
+        layout: ChannelLayout,
+        weights: Option<&[u8]>,
+        buffers: [Vec<u8>; 3],
+    ) -> Planes {
+        let image = |values| Image::with_weights(values, weights.map(|w| w.to_vec()), width);
+        let [mut r, mut g, mut b] = buffers;
+        for buffer in [&mut r, &mut g, &mut b] {
+            buffer.clear();
+        }
+        if self.options.color_space == ColorSpace::PerChannel {
+            let channel = |mut values: Vec<u8>, f: fn((u8, u8, u8)) -> u8| {
+                values.extend(
+                    pixels
+                        .chunks_exact(layout.channels())
+                        .map(|p| f(self.decode(layout.rgb(p)))),
+                );
+                values
+            };
+            Planes::Rgb([
+                image(channel(r, |(r, _, _)| r)),
+                image(channel(g, |(_, g, _)| g)),
+                image(channel(b, |(_, _, b)| b)),
+            ])
+        } else if weights.is_none() && self.options.histogram_subsampling == 1 {
+            let mut histogram = Histogram([0; 256]);
+            self.extend_counted_luminances(pixels, layout, &mut r, &mut histogram);
+            Planes::Luminance(Image {
+                histogram: Some(Box::new(histogram)),
+                ..image(r)
+            })
+        } else {
+            self.extend_luminances(pixels, layout, &mut r);
+            Planes::Luminance(image(r))
+        }
+    }
+
+    // Returns the block dimensions used for the first plane.
+    fn enhance_planes(&self, planes: &mut Planes) -> (usize, usize) {
+        self.enhance_planes_with(planes, None, None, None)
+    }
+
+    // `histories` has one entry per plane; `blocks` collects the blocks of the first plane;
+    // `scratch` is shared by the planes.
+    fn enhance_planes_with(
+        &self,
+        planes: &mut Planes,
+        mut histories: Option<&mut [video::LutHistory]>,
+        mut blocks: Option<&mut Vec<BlockReport>>,
+        mut scratch: Option<&mut context::Scratch>,
+    ) -> (usize, usize) {
+        let mut block_size = (0, 0);
+        for (i, image) in planes.images_mut().iter_mut().enumerate() {
+            let state = PlaneState {
+                history: histories.as_deref_mut().map(|h| &mut h[i]),
+                blocks: blocks.take(),
+                plan: None,
+                scratch: scratch.as_deref_mut(),
+            };
+            let size = self.enhance_luminances_with(image, state);
+            if i == 0 {
+                block_size = size;
+            }
         }
-        if exceeded > 0.0 {
-            let offset = exceeded / 256.0;
-            for x in &mut self.0 {
-                *x += offset;
+        block_size
+    }
+
+    fn write_back_planes(&self, pixels: &mut [u8], layout: ChannelLayout, planes: &Planes) {
+        match planes {
+            Planes::Luminance(image) => {
+                self.write_back_luminances(pixels, layout, &image.luminances);
+            }
+            Planes::Rgb([r, g, b]) => {
+                for (i, p) in pixels.chunks_exact_mut(layout.channels()).enumerate() {
+                    let rgb = (r.luminances[i], g.luminances[i], b.luminances[i]);
+                    layout.set_rgb(p, self.encode(rgb));
+                }
             }
         }
-        self
     }
+
+    fn write_back_luminances(&self, pixels: &mut [u8], layout: ChannelLayout, luminances: &[u8]) {
+        for (p, &l) in pixels.chunks_exact_mut(layout.channels()).zip(luminances) {
+            if let Some(rgb) = self.with_enhanced_luminance(layout.rgb(p), l) {
+                layout.set_rgb(p, rgb);
+            }
+        }
+    }
+
+    /// Enhances a `(height, width, channels)` array of RGB (3 channels) or RGBA (4 channels)
+    /// pixels in place. Views that are not in standard layout (e.g. slices of a wider array) are
+    /// enhanced through a contiguous copy.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the array has another channel count; see
+    /// [`try_enhance_array3()`](Self::try_enhance_array3).
+    #[cfg(feature = "ndarray")]
+    pub fn enhance_array3(&self, arr: &mut ndarray::ArrayViewMut3<u8>) {
+        let result = with_array3_pixels(arr, |pixels, width, layout| {
+            self.enhance_image(pixels, width, layout);
+            Ok(())
+        });
+        if let Err(e) = result {
+            panic!("{}", e);
+        }
+    }
+
+    /// Validating variant of [`enhance_array3()`](Self::enhance_array3).
+    #[cfg(feature = "ndarray")]
+    pub fn try_enhance_array3(
+        &self,
+        arr: &mut ndarray::ArrayViewMut3<u8>,
+    ) -> Result<(), ClaheError> {
+        with_array3_pixels(arr, |pixels, width, layout| {
+            self.try_enhance_image(pixels, width, layout)
+        })
+    }
+
+    /// Enhances an interleaved RGB image whose alpha is stored as a separate plane.
+    ///
+    /// The alpha plane only weights each pixel's contribution to the histograms (fully
+    /// transparent pixels are ignored); every pixel is still enhanced.
+    pub fn enhance_rgb_image_with_alpha(
+        &self,
+        pixels: &mut [u8],
+        width: usize,
+        alpha: Option<&[u8]>,
+    ) {
+        let layout = ChannelLayout::RGB;
+        if let Some(alpha) = alpha {
+            assert_eq!(pixels.len() / layout.channels(), alpha.len());
+        }
+
+        let mut planes = self.extract_planes(pixels, width, layout, alpha);
+        self.enhance_planes(&mut planes);
+        self.write_back_planes(pixels, layout, &planes);
+    }
+
+    /// Enhances an interleaved 8-bit CMYK image in place.
+    ///
+    /// The enhanced luminance is applied by adjusting the black ink first, which keeps the
+    /// relationship between the C/M/Y inks intact.
+    pub fn enhance_cmyk_image(&self, pixels: &mut [u8], width: usize) {
+        let luminances = pixels
+            .chunks_exact(4)
+            .map(|p| self::color_format::cmyk_to_value(p[0], p[1], p[2], p[3]))
+            .collect();
+        let mut image = Image::new(luminances, width);
+        self.enhance_luminances(&mut image);
+        for (p, &l) in pixels.chunks_exact_mut(4).zip(&image.luminances) {
+            let (c, m, y, k) = self::color_format::cmyk_with_value(p[0], p[1], p[2], l);
+            p.copy_from_slice(&[c, m, y, k]);
+        }
+    }
+
+    /// Enhances a single-channel depth or disparity map in place.
+    ///
+    /// Pixels equal to `invalid_value`, as well as non-finite ones, are excluded from the
+    /// statistics and left untouched. The valid values are mapped onto 256 levels spanning their
+    /// range, enhanced, and mapped back onto the same range.
+    pub fn enhance_depth_map(&self, depth: &mut [f32], width: usize, invalid_value: Option<f32>) {
+        let is_valid = |d: f32| d.is_finite() && Some(d) != invalid_value;
+        let (min, max) = depth
+            .iter()
+            .copied()
+            .filter(|&d| is_valid(d))
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), d| {
+                (min.min(d), max.max(d))
+            });
+        if min > max {
+            return;
+        }
+
+        let range = (max - min).max(f32::EPSILON);
+        let weights = depth
+            .iter()
+            .map(|&d| if is_valid(d) { 1 } else { 0 })
+            .collect::<Vec<_>>();
+        let luminances = depth
+            .iter()
+            .zip(&weights)
+            .map(|(&d, &w)| {
+                if w > 0 {
+                    ((d - min) / range * 255.0).round() as u8
+                } else {
+                    0
+                }
+            })
+            .collect();
+        let mut image = Image::with_weights(luminances, Some(weights), width);
+        self.enhance_luminances(&mut image);
+        for (d, &l) in depth.iter_mut().zip(&image.luminances) {
+            if is_valid(*d) {
+                *d = min + f32::from(l) / 255.0 * range;
+            }
+        }
+    }
+
+    // `enhance_image()` of planar pixels (without the dehaze pre-pass), with the same per-pixel
+    // conversions.
+    #[cfg(feature = "zune-image")]
+    fn enhance_rgb_planes(&self, r: &mut [u8], g: &mut [u8], b: &mut [u8], width: usize) {
+        let mut planes = self.extract_planar_planes([r, g, b], width);
+        self.enhance_planes(&mut planes);
+        for (i, ((r, g), b)) in r.iter_mut().zip(g.iter_mut()).zip(b.iter_mut()).enumerate() {
+            if let Some(rgb) = self.written_back_pixel(&planes, i, (*r, *g, *b)) {
+                (*r, *g, *b) = rgb;
+            }
+        }
+    }
+
+    // `enhance_rgb_planes()` of a gray image, whose pixels stay gray.
+    #[cfg(feature = "zune-image")]
+    fn enhance_luma_plane(&self, luma: &mut [u8], width: usize) {
+        let mut planes = self.extract_planar_planes([&*luma; 3], width);
+        self.enhance_planes(&mut planes);
+        for (i, l) in luma.iter_mut().enumerate() {
+            if let Some((v, _, _)) = self.written_back_pixel(&planes, i, (*l, *l, *l)) {
+                *l = v;
+            }
+        }
+    }
+
+    // `extract_planes()` of planar pixels.
+    #[cfg(feature = "zune-image")]
+    fn extract_planar_planes(&self, [r, g, b]: [&[u8]; 3], width: usize) -> Planes {
+        let pixels = || r.iter().zip(g).zip(b).map(|((&r, &g), &b)| (r, g, b));
+        if self.options.color_space == ColorSpace::PerChannel {
+            let channel = |f: fn((u8, u8, u8)) -> u8| {
+                Image::new(pixels().map(|p| f(self.decode(p))).collect(), width)
+            };
+            Planes::Rgb([
+                channel(|(r, _, _)| r),
+                channel(|(_, g, _)| g),
+                channel(|(_, _, b)| b),
+            ])
+        } else {
+            let luminances = pixels().map(|p| self.pixel_luminance(p)).collect();
+            Planes::Luminance(Image::new(luminances, width))
+        }
+    }
+
+    /// Returns the block width and height used for an image of the given size.
+    fn block_dimensions(&self, width: usize, height: usize, luminances: &[u8]) -> (usize, usize) {
+        match self.options.block_size {
+            BlockSize::Fixed => (self.options.block_width, self.options.block_height),
+            BlockSize::Auto => analysis::auto_block_dimensions(luminances, width),
+            BlockSize::Tiles { x, y } => ((width / x).max(1), (height / y).max(1)),
+        }
+    }
+
+    // The subsampling step of the histogram of `window`, enlarged to fit the sample budget.
+    fn histogram_step(&self, window: Region) -> usize {
+        let step = self.options.histogram_subsampling;
+        let Some(budget) = self.options.histogram_sample_budget else {
+            return step;
+        };
+        let area = (window.end.x - window.start.x) * (window.end.y - window.start.y);
+        let mut step = step.max(((area / budget) as f64).sqrt() as usize);
+        while window.sample_count(step) > budget {
+            step += 1;
+        }
+        step
+    }
+
+    // `output_value()` after the strength gradient, for the pixel `(x, y)` of a `width x height`
+    // image.
+    fn graded_output_value(
+        &self,
+        (x, y): (usize, usize),
+        (width, height): (usize, usize),
+        l0: u8,
+        l: f32,
+    ) -> f32 {
+        let l = match &self.options.strength_gradient {
+            Some(gradient) => {
+                let position = (
+                    (x as f32 + 0.5) / width as f32,
+                    (y as f32 + 0.5) / height as f32,
+                );
+                f32::from(l0) + gradient.weight(position) * (l - f32::from(l0))
+            }
+            None => l,
+        };
+        self.output_value(l0, l)
+    }
+
+    fn output_luminance(&self, l0: u8, l: f32) -> u8 {
+        self.output_value(l0, l) as u8
+    }
+
+    // Converts the interpolated enhancement result `l` of the original luminance `l0` into the
+    // final (unquantized) output value.
+    fn output_value(&self, l0: u8, l: f32) -> f32 {
+        if self.options.is_protected(l0) {
+            return f32::from(l0);
+        }
+        let l = match self.options.max_gain {
+            Some(max_gain) => l.min(f32::from(l0) * max_gain),
+            None => l,
+        };
+        let l = match self.options.shadow_lift {
+            Some(lift) => f32::from(l0) + lift.weight(l0) * (l - f32::from(l0)).max(0.0),
+            None => l,
+        };
+        let l = match self.options.highlight_knee {
+            Some(knee) if l > f32::from(knee) && l > f32::from(l0) => {
+                let (knee, headroom) = (f32::from(knee), 255.0 - f32::from(knee));
+                let compressed = knee + headroom * (1.0 - (-(l - knee) / headroom).exp());
+                compressed.max(f32::from(l0))
+            }
+            _ => l,
+        };
+        let blend = |strength: f32| strength * l + (1.0 - strength) * f32::from(l0);
+        let l = if self.options.fusion_strengths.is_empty() {
+            blend(self.options.strength)
+        } else {
+            let (mut sum, mut weights) = (0.0, 0.0);
+            for &strength in &self.options.fusion_strengths {
+                let l = blend(strength);
+                let weight = (-(l / 255.0 - 0.5).powi(2) / 0.08).exp();
+                sum += weight * l;
+                weights += weight;
+            }
+            sum / weights
+        };
+        let (black, white) = (
+            f32::from(self.options.output_black),
+            f32::from(self.options.output_white),
+        );
+        match self.options.clamp_mode {
+            ClampMode::Clamp => l.clamp(black, white),
+            ClampMode::Scale => black + l.clamp(0.0, 255.0) / 255.0 * (white - black),
+        }
+    }
+
+    fn enhance_luminances(&self, image: &mut Image) -> (usize, usize) {
+        self.enhance_luminances_with(image, Default::default())
+    }
+
+    fn enhance_luminances_with(&self, image: &mut Image, state: PlaneState) -> (usize, usize) {
+        let mut scratch = state.scratch;
+        let state = PlaneState {
+            scratch: scratch.as_deref_mut(),
+            ..state
+        };
+        let (enhanced, block_size) = self.enhanced_luminances_with(image, state);
+        image.histogram = None;
+        let mut ditherer = dither::Ditherer::new(
+            self.options.dither,
+            image.width,
+            self.options.output_black,
+            self.options.output_white,
+        );
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let i = y * image.width + x;
+                let l0 = image.luminances[i];
+                image.luminances[i] = if self.options.is_protected(l0) {
+                    l0
+                } else {
+                    ditherer.quantize(x, y, enhanced[i])
+                };
+            }
+            ditherer.next_row();
+        }
+        if let Some(scratch) = scratch {
+            scratch.enhanced = enhanced;
+        }
+        block_size
+    }
+
+    // The image that the statistics are computed from (`None` if it is `image` itself) and the
+    // background levels of the point sources.
+    fn analyzed_image(&self, image: &Image) -> (Option<Image>, Option<Vec<Option<u8>>>) {
+        let encoded = self.options.log_luminance.then(|| image.log_encoded());
+        let image = encoded.as_ref().unwrap_or(image);
+        let denoised = (self.options.denoise_strength > 0.0)
+            .then(|| image.denoised(self.options.denoise_strength));
+        let source = denoised.as_ref().unwrap_or(image);
+        let backgrounds = self
+            .options
+            .point_source_protection
+            .map(|p| p.backgrounds(&source.luminances, source.width));
+        let masked = backgrounds
+            .as_ref()
+            .map(|backgrounds| source.without(backgrounds));
+        (masked.or(denoised).or(encoded), backgrounds)
+    }
+
+    // The block layouts of every block scale and the (unscaled) block dimensions; `exact`
+    // computes the sliding window enhancement instead of blocks.
+    fn block_layouts(&self, image: &Image, exact: bool) -> (Vec<BlockLayout>, (usize, usize)) {
+        self.block_layouts_in(image, exact, Vec::new(), false)
+    }
+
+    // Like `block_layouts()`, building the first block grid into the allocation of `blocks`;
+    // `lazy` only computes the LUT entries of the block grids that the enhancement of `image`
+    // looks up.
+    fn block_layouts_in(
+        &self,
+        image: &Image,
+        exact: bool,
+        blocks: Vec<Block>,
+        lazy: bool,
+    ) -> (Vec<BlockLayout>, (usize, usize)) {
+        // The divergence limit compares the LUTs at every level.
+        let lazy = lazy && self.options.max_block_divergence.is_none();
+        let mut blocks = Some(blocks);
+        let (block_width, block_height) =
+            self.block_dimensions(image.width, image.height, &image.luminances);
+        let stats = GlobalStats::new(
+            &image.sampled_histogram(self.options.histogram_subsampling),
+            self.options.l_alpha_percentile,
+        );
+        let scales = if self.options.block_scales.is_empty() {
+            &[1.0][..]
+        } else {
+            &self.options.block_scales[..]
+        };
+        let layouts = scales
+            .iter()
+            .map(|&scale| {
+                let scaled = |size: usize, max: usize| {
+                    ((size as f32 * scale).round() as usize).clamp(1, max)
+                };
+                let block_width = scaled(block_width, image.width);
+                let block_height = scaled(block_height, image.height);
+                match &self.options.quadtree {
+                    _ if exact => BlockLayout::Exact {
+                        values: sliding::enhance_exact(
+                            image,
+                            &stats,
+                            self,
+                            (block_width, block_height),
+                        ),
+                        width: image.width,
+                    },
+                    Some(split) => BlockLayout::Quadtree(quadtree::BlockQuadtree::new(
+                        image,
+                        &stats,
+                        self,
+                        (block_width, block_height),
+                        split,
+                    )),
+                    None => {
+                        let mut grid = BlockGrid::new(
+                            blocks.take().unwrap_or_default(),
+                            image,
+                            &stats,
+                            self,
+                            (block_width, block_height),
+                            lazy,
+                        );
+                        if let Some(divergence) = self.options.max_block_divergence {
+                            grid.limit_divergence(divergence);
+                        }
+                        BlockLayout::Grid(grid)
+                    }
+                }
+            })
+            .collect();
+        (layouts, (block_width, block_height))
+    }
+
+    // Returns the unquantized output luminances and the block dimensions.
+    fn enhanced_luminances(&self, image: &Image) -> (Vec<f32>, (usize, usize)) {
+        self.enhanced_luminances_with(image, Default::default())
+    }
+
+    fn enhanced_luminances_with(
+        &self,
+        image: &Image,
+        state: PlaneState,
+    ) -> (Vec<f32>, (usize, usize)) {
+        let linear = image;
+        let (analyzed, backgrounds) = self.analyzed_image(image);
+        let image = analyzed.as_ref().unwrap_or(image);
+        let width = image.width;
+        let mut scratch = state.scratch;
+        let mut computed = Vec::new();
+        let (grids, (block_width, block_height)) = match state.plan {
+            Some((layouts, block_size)) => (layouts, block_size),
+            None => {
+                let exact = self.options.interpolation == Interpolation::SlidingWindow;
+                let recycled = scratch
+                    .as_deref_mut()
+                    .map(|scratch| std::mem::take(&mut scratch.blocks));
+                // The LUT history blends every level, the reports measure the LUTs at the levels
+                // of the block windows, and point sources look up the levels of their
+                // backgrounds.
+                let lazy =
+                    state.history.is_none() && state.blocks.is_none() && backgrounds.is_none();
+                let (mut layouts, block_size) =
+                    self.block_layouts_in(image, exact, recycled.unwrap_or_default(), lazy);
+                if let Some(history) = state.history {
+                    history.smooth(&mut layouts);
+                }
+                if let Some(blocks) = state.blocks {
+                    blocks.extend(
+                        layouts
+                            .iter_mut()
+                            .flat_map(|grid| grid.blocks_mut().iter().map(|block| block.report)),
+                    );
+                }
+                computed = layouts;
+                (&computed[..], block_size)
+            }
+        };
+
+        let mut enhanced = match (grids, &backgrounds, self.options.interpolation) {
+            ([BlockLayout::Grid(grid)], None, Interpolation::Bilinear) => {
+                let owned;
+                let (values, sweep) = match scratch.as_deref_mut() {
+                    Some(scratch) => {
+                        let values = std::mem::take(&mut scratch.enhanced);
+                        (values, scratch.sweep(grid, image.width, image.height))
+                    }
+                    None => {
+                        owned = grid.bilinear_sweep(image.width, image.height);
+                        (Vec::new(), &owned)
+                    }
+                };
+                if self.options.fixed_point_luts {
+                    let tables = grid.fixed_tables();
+                    map_rows(values, image.width, image.height, |y, row| {
+                        let luminances = &image.luminances[y * width..(y + 1) * width];
+                        grid.bilinear_row_fixed(y, luminances, row, sweep, &tables);
+                    })
+                } else {
+                    map_rows(values, image.width, image.height, |y, row| {
+                        let luminances = &image.luminances[y * width..(y + 1) * width];
+                        grid.bilinear_row(y, luminances, row, sweep);
+                    })
+                }
+            }
+            _ => map_pixels(image.width, image.height, |i| {
+                let (y, x, l0) = (i / width, i % width, image.luminances[i]);
+                // Point sources are shifted by the enhancement of their background.
+                let background = backgrounds
+                    .as_ref()
+                    .and_then(|b| b[i])
+                    .filter(|_| self.options.interpolation != Interpolation::SlidingWindow);
+                let l = background.unwrap_or(l0);
+                let enhanced = grids
+                    .iter()
+                    .map(|grid| grid.interpolate(self.options.interpolation, y, x, l))
+                    .sum::<f32>()
+                    / grids.len() as f32;
+                match background {
+                    Some(b) => (enhanced - f32::from(b) + f32::from(l0)).clamp(0.0, 255.0),
+                    None => enhanced,
+                }
+            }),
+        };
+        if let (Some(scratch), Some(BlockLayout::Grid(grid))) =
+            (scratch, computed.into_iter().next())
+        {
+            scratch.blocks = grid.blocks;
+        }
+        if let Some(filter) = &self.options.gain_smoothing {
+            // The gains are offset by one so that black pixels are not singular.
+            let guide = image
+                .luminances
+                .iter()
+                .map(|&l0| f32::from(l0) / 255.0)
+                .collect::<Vec<_>>();
+            let gains = enhanced
+                .iter()
+                .zip(&image.luminances)
+                .map(|(l, &l0)| (l + 1.0) / (f32::from(l0) + 1.0))
+                .collect::<Vec<_>>();
+            let gains = filter.smooth(&guide, &gains, width);
+            for ((l, &l0), gain) in enhanced.iter_mut().zip(&image.luminances).zip(gains) {
+                *l = (gain * (f32::from(l0) + 1.0) - 1.0).clamp(0.0, 255.0);
+            }
+        }
+        let originals = if self.options.log_luminance {
+            // The change in stops is applied as a gain, which keeps levels sharing a code apart.
+            for ((l, &code), &l0) in enhanced
+                .iter_mut()
+                .zip(&image.luminances)
+                .zip(&linear.luminances)
+            {
+                let stops = (*l - f32::from(code)) * LOG_STOPS / 255.0;
+                *l = ((f32::from(l0) + 1.0) * stops.exp2() - 1.0).clamp(0.0, 255.0);
+            }
+            &linear.luminances
+        } else {
+            &image.luminances
+        };
+        for (i, (l, &l0)) in enhanced.iter_mut().zip(originals).enumerate() {
+            *l = self.graded_output_value((i % width, i / width), (width, image.height), l0, *l);
+        }
+        (enhanced, (block_width, block_height))
+    }
+}
+
+// Per-plane state threaded through the enhancement besides the options.
+#[derive(Default)]
+struct PlaneState<'a> {
+    // Blends the block LUTs with those of the previous frames.
+    history: Option<&'a mut video::LutHistory>,
+    // Collects the reports of the blocks.
+    blocks: Option<&'a mut Vec<BlockReport>>,
+    // Precomputed layouts and block dimensions, used instead of analyzing the plane.
+    plan: Option<(&'a [BlockLayout], (usize, usize))>,
+    // Buffers reused across calls.
+    scratch: Option<&'a mut context::Scratch>,
 }
 
 #[derive(Debug)]
-struct Cdf([f32; 256]);
+enum BlockLayout {
+    Grid(BlockGrid),
+    Quadtree(quadtree::BlockQuadtree),
+    // The enhanced luminance of every pixel.
+    Exact { values: Vec<f32>, width: usize },
+}
 
-impl Cdf {
-    fn new(pdf: &Pdf) -> Self {
-        let mut cdf = [0.0; 256];
-        let mut sum = 0.0;
-        for (i, x) in pdf.0.iter().copied().enumerate() {
-            sum += x;
-            cdf[i] = sum;
+impl BlockLayout {
+    fn interpolate(&self, interpolation: Interpolation, y: usize, x: usize, l0: u8) -> f32 {
+        match self {
+            Self::Grid(grid) => grid.interpolate(interpolation, y, x, l0),
+            Self::Quadtree(tree) => tree.interpolate(interpolation, y, x, l0),
+            Self::Exact { values, width } => values[y * width + x],
         }
-        for x in &mut cdf {
-            *x /= sum;
+    }
+
+    fn blocks_mut(&mut self) -> &mut [Block] {
+        match self {
+            Self::Grid(grid) => &mut grid.blocks,
+            Self::Quadtree(tree) => tree.leaves_mut(),
+            Self::Exact { .. } => &mut [],
+        }
+    }
+}
+
+// Builds the blocks of `items` in order (reusing the allocation of `blocks`), in parallel with
+// the `rayon` feature.
+#[cfg(feature = "rayon")]
+fn map_blocks<T, F>(mut blocks: Vec<Block>, items: impl Iterator<Item = T>, f: F) -> Vec<Block>
+where
+    T: Send,
+    F: Fn(T) -> Block + Send + Sync,
+{
+    use rayon::prelude::*;
+    blocks.clear();
+    blocks.par_extend(items.collect::<Vec<_>>().into_par_iter().map(f));
+    blocks
+}
+
+#[cfg(not(feature = "rayon"))]
+fn map_blocks<T, F>(mut blocks: Vec<Block>, items: impl Iterator<Item = T>, f: F) -> Vec<Block>
+where
+    F: Fn(T) -> Block,
+{
+    blocks.clear();
+    blocks.extend(items.map(f));
+    blocks
+}
+
+fn blend_bilinear([m, n, a, b, c, d]: [f32; 6]) -> f32 {
+    let (la, lb, lc, ld) = (n * a, (1.0 - n) * b, n * c, (1.0 - n) * d);
+    m * (la + lb) + (1.0 - m) * (lc + ld)
+}
+
+// The number of pixels that `bilinear_row()` blends at a time.
+const BILINEAR_LANES: usize = 8;
+
+// Blends the bilinear terms `[m, n, a, b, c, d]` of `BILINEAR_LANES` pixels, stored term by
+// term.
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+fn blend_bilinear_lanes(lanes: &[[f32; BILINEAR_LANES]; 6]) -> [f32; BILINEAR_LANES] {
+    simd::blend_bilinear(lanes)
+}
+
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+fn blend_bilinear_lanes(lanes: &[[f32; BILINEAR_LANES]; 6]) -> [f32; BILINEAR_LANES] {
+    std::array::from_fn(|i| blend_bilinear(lanes.map(|term| term[i])))
+}
+
+// Fills every row of the image with `f(y, row)` (reusing the allocation of `values`), in
+// parallel with the `rayon` feature.
+#[cfg(feature = "rayon")]
+fn map_rows<F>(mut values: Vec<f32>, width: usize, height: usize, f: F) -> Vec<f32>
+where
+    F: Fn(usize, &mut [f32]) + Send + Sync,
+{
+    use rayon::prelude::*;
+    values.clear();
+    values.resize(width * height, 0.0);
+    values
+        .par_chunks_mut(width)
+        .enumerate()
+        .for_each(|(y, row)| f(y, row));
+    values
+}
+
+#[cfg(not(feature = "rayon"))]
+fn map_rows<F>(mut values: Vec<f32>, width: usize, height: usize, f: F) -> Vec<f32>
+where
+    F: Fn(usize, &mut [f32]),
+{
+    values.clear();
+    values.resize(width * height, 0.0);
+    for (y, row) in values.chunks_mut(width).enumerate() {
+        f(y, row);
+    }
+    values
+}
+
+// Evaluates `f` for the index of every pixel, in parallel rows with the `rayon` feature.
+#[cfg(feature = "rayon")]
+fn map_pixels<F>(width: usize, height: usize, f: F) -> Vec<f32>
+where
+    F: Fn(usize) -> f32 + Send + Sync,
+{
+    use rayon::prelude::*;
+    (0..height)
+        .into_par_iter()
+        .flat_map_iter(|y| (y * width..(y + 1) * width).map(&f))
+        .collect()
+}
+
+#[cfg(not(feature = "rayon"))]
+fn map_pixels<F>(width: usize, height: usize, f: F) -> Vec<f32>
+where
+    F: Fn(usize) -> f32,
+{
+    (0..width * height).map(f).collect()
+}
+
+// Runs `f` on the interleaved pixels of `arr`, through a standard-layout copy if needed.
+#[cfg(feature = "ndarray")]
+fn with_array3_pixels<F>(arr: &mut ndarray::ArrayViewMut3<u8>, f: F) -> Result<(), ClaheError>
+where
+    F: FnOnce(&mut [u8], usize, ChannelLayout) -> Result<(), ClaheError>,
+{
+    let (_, width, channels) = arr.dim();
+    let layout = match channels {
+        3 => ChannelLayout::RGB,
+        4 => ChannelLayout::RGBA,
+        _ => return Err(ClaheError::UnsupportedChannelCount(channels)),
+    };
+    if let Some(pixels) = arr.as_slice_mut() {
+        return f(pixels, width, layout);
+    }
+    let mut owned = arr.as_standard_layout().into_owned();
+    f(
+        owned.as_slice_mut().expect("standard layout"),
+        width,
+        layout,
+    )?;
+    arr.assign(&owned);
+    Ok(())
+}
+
+// The fixed-point scales of `AutomaticClaheOptions::fixed_point_luts`: the LUT entries are
+// signed Q23.8 and the interpolation weights Q0.8, so that a blend of four entries fits into
+// `i64`. The entries are not clamped to `0..=255` because the float path only clamps after
+// the blend (and after the strength gradient).
+const FIXED_LUT_ONE: f32 = 256.0;
+const FIXED_WEIGHT_ONE: u32 = 1 << 8;
+
+// Bounds of the relaxation that limits the divergence of adjacent block LUTs.
+const MAX_DIVERGENCE_PASSES: usize = 64;
+const DIVERGENCE_TOLERANCE: f32 = 1e-3;
+
+#[derive(Debug)]
+struct BlockGrid {
+    blocks: Vec<Block>,
+    block_width: usize,
+    block_height: usize,
+    line_blocks: usize,
+    aligned_width: usize,
+    aligned_height: usize,
+}
+
+impl BlockGrid {
+    // The blocks are built into the allocation of `blocks`; `lazy` only computes the LUT entries
+    // that the interpolation of `image` looks up.
+    fn new(
+        blocks: Vec<Block>,
+        image: &Image,
+        stats: &GlobalStats,
+        clahe: &AutomaticClahe,
+        (block_width, block_height): (usize, usize),
+        lazy: bool,
+    ) -> Self {
+        let mut levels = if lazy {
+            Self::footprint_levels(image, block_width, block_height)
+        } else {
+            Vec::new()
+        }
+        .into_iter();
+        let regions = BlockRegions::new(image.width, image.height, block_width, block_height)
+            .with_overlap(clahe.options.block_overlap)
+            .collect::<Vec<_>>();
+        // The histograms of unweighted, fully sampled windows that match their region are built
+        // from the block-major tiles.
+        let options = &clahe.options;
+        let tiled = (image.weights.is_none()
+            && !options.identity_luts
+            && options.block_overlap == 0.0
+            && options.histogram_subsampling == 1
+            && options.histogram_sample_budget.is_none())
+        .then(|| {
+            let regions = regions
+                .iter()
+                .map(|&(region, _)| region)
+                .collect::<Vec<_>>();
+            tiled::TiledLuminances::new(image, &regions, image.width / block_width)
+        });
+        let mut tiles = tiled.as_ref().map(|tiled| tiled.tiles());
+        let items = regions.into_iter().map(|regions| {
+            let tile = tiles.as_mut().and_then(Iterator::next);
+            (regions, levels.next().unwrap_or(ALL_LEVELS), tile)
+        });
+        Self::from_blocks(
+            map_blocks(
+                blocks,
+                items,
+                |((region, window), levels, tile)| match tile {
+                    Some(tile) => {
+                        let noise = Block::noise(image, clahe, window);
+                        let histogram = Histogram::of_luminances(tile);
+                        Block::from_histogram(&histogram, stats, clahe, region, noise, levels)
+                    }
+                    None => Block::new(image, stats, clahe, (region, window), levels),
+                },
+            ),
+            (image.width, image.height),
+            (block_width, block_height),
+        )
+    }
+
+    // The levels of the pixels of every block and its eight neighbors, which are all that the
+    // interpolation looks up in the LUT of the block.
+    fn footprint_levels(
+        image: &Image,
+        block_width: usize,
+        block_height: usize,
+    ) -> Vec<std::ops::RangeInclusive<u8>> {
+        let (columns, rows) = (image.width / block_width, image.height / block_height);
+        let span = |i: usize, size: usize, count: usize, extent: usize| {
+            i * size..if i + 1 == count {
+                extent
+            } else {
+                (i + 1) * size
+            }
+        };
+        let mut ranges = vec![(u8::MAX, u8::MIN); columns * rows];
+        for (y, row) in image.luminances.chunks_exact(image.width).enumerate() {
+            let block_row = (y / block_height).min(rows - 1);
+            let ranges = &mut ranges[block_row * columns..][..columns];
+            for (column, (min, max)) in ranges.iter_mut().enumerate() {
+                let values = &row[span(column, block_width, columns, image.width)];
+                *min = values.iter().fold(*min, |a, &b| a.min(b));
+                *max = values.iter().fold(*max, |a, &b| a.max(b));
+            }
+        }
+        (0..columns * rows)
+            .map(|i| {
+                let (column, row) = (i % columns, i / columns);
+                let (mut min, mut max) = ranges[i];
+                for r in row.saturating_sub(1)..(row + 2).min(rows) {
+                    for c in column.saturating_sub(1)..(column + 2).min(columns) {
+                        let (low, high) = ranges[r * columns + c];
+                        (min, max) = (min.min(low), max.max(high));
+                    }
+                }
+                min..=max
+            })
+            .collect()
+    }
+
+    fn from_blocks(
+        blocks: Vec<Block>,
+        (width, height): (usize, usize),
+        (block_width, block_height): (usize, usize),
+    ) -> Self {
+        Self {
+            blocks,
+            block_width,
+            block_height,
+            line_blocks: width / block_width,
+            aligned_width: width / block_width * block_width,
+            aligned_height: height / block_height * block_height,
+        }
+    }
+
+    // Pulls the LUTs of adjacent blocks towards their mean until they differ by at most
+    // `max_divergence` at every level.
+    fn limit_divergence(&mut self, max_divergence: f32) {
+        let columns = self.line_blocks.max(1);
+        let count = self.blocks.len();
+        let pairs = (0..count)
+            .flat_map(|i| {
+                let right = (i % columns + 1 < columns).then_some((i, i + 1));
+                let below = (i + columns < count).then_some((i, i + columns));
+                right.into_iter().chain(below)
+            })
+            .collect::<Vec<_>>();
+        for _ in 0..MAX_DIVERGENCE_PASSES {
+            let mut converged = true;
+            for &(a, b) in &pairs {
+                for l in 0..256 {
+                    let (ta, tb) = (self.blocks[a].table[l], self.blocks[b].table[l]);
+                    let excess = (ta - tb).abs() - max_divergence;
+                    if excess > DIVERGENCE_TOLERANCE {
+                        let shift = excess / 2.0 * (ta - tb).signum();
+                        self.blocks[a].table[l] -= shift;
+                        self.blocks[b].table[l] += shift;
+                        converged = false;
+                    }
+                }
+            }
+            if converged {
+                break;
+            }
+        }
+    }
+
+    fn interpolate(&self, interpolation: Interpolation, y: usize, x: usize, l0: u8) -> f32 {
+        match interpolation {
+            Interpolation::Nearest => self.nearest(y, x).enhance(l0),
+            Interpolation::Bilinear => self.bilinear(y, x, l0),
+            Interpolation::Gaussian => self.gaussian(y, x, l0),
+            Interpolation::SlidingWindow => unreachable!("sliding windows are not interpolated"),
+        }
+    }
+
+    // The block that contains the pixel.
+    fn nearest(&self, y: usize, x: usize) -> &Block {
+        let rows = self.blocks.len() / self.line_blocks;
+        self.block(
+            std::cmp::min(y / self.block_height, rows - 1),
+            std::cmp::min(x / self.block_width, self.line_blocks - 1),
+        )
+    }
+
+    fn bilinear(&self, y: usize, x: usize, l0: u8) -> f32 {
+        blend_bilinear(self.bilinear_terms(y, x, l0))
+    }
+
+    // The spans and weights of the bilinear interpolation of a `width x height` image; the
+    // block lookups are clamped to the aligned size like in `bilinear()`.
+    fn bilinear_sweep(&self, width: usize, height: usize) -> BilinearSweep {
+        let (half_width, half_height) = (self.block_width / 2, self.block_height / 2);
+        let mut spans: Vec<ColumnSpan> = Vec::new();
+        for x in 0..width {
+            let x0 = std::cmp::min(x, self.aligned_width - 1);
+            let left = (x0 >= half_width).then(|| (x0 - half_width) / self.block_width);
+            let right = (x0 + half_width < self.aligned_width)
+                .then(|| (x0 + half_width) / self.block_width);
+            match spans.last_mut() {
+                Some(span) if (span.left, span.right) == (left, right) => span.columns.end = x + 1,
+                _ => spans.push(ColumnSpan {
+                    columns: x..x + 1,
+                    left,
+                    right,
+                }),
+            }
+        }
+        let column_weights: Vec<f32> = spans
+            .iter()
+            .flat_map(|span| {
+                let centers = span
+                    .left
+                    .zip(span.right)
+                    .map(|(a, b)| (self.block(0, a).center_x(), self.block(0, b).center_x()));
+                span.columns.clone().map(move |x| match centers {
+                    Some((a, b)) if a != b => (b - x) as f32 / (b - a) as f32,
+                    Some(_) => 1.0,
+                    None => 0.0,
+                })
+            })
+            .collect();
+
+        let rows = (0..height)
+            .map(|y| {
+                let y0 = std::cmp::min(y, self.aligned_height - 1);
+                let top = (y0 >= half_height).then(|| (y0 - half_height) / self.block_height);
+                let bottom = (y0 + half_height < self.aligned_height)
+                    .then(|| (y0 + half_height) / self.block_height);
+                (top, bottom)
+            })
+            .collect::<Vec<_>>();
+        let row_weights: Vec<f32> = rows
+            .iter()
+            .enumerate()
+            .map(|(y, &(top, bottom))| match top.zip(bottom) {
+                Some((top, bottom)) => {
+                    let a = self.block(top, 0).center_y();
+                    let c = self.block(bottom, 0).center_y();
+                    if a != c {
+                        (c - y) as f32 / (c - a) as f32
+                    } else {
+                        1.0
+                    }
+                }
+                None => 0.0,
+            })
+            .collect();
+        let fixed = |weights: &[f32]| {
+            weights
+                .iter()
+                .map(|w| (w * FIXED_WEIGHT_ONE as f32).round() as u32)
+                .collect()
+        };
+        BilinearSweep {
+            spans,
+            rows,
+            fixed_column_weights: fixed(&column_weights),
+            fixed_row_weights: fixed(&row_weights),
+            column_weights,
+            row_weights,
         }
-        Self(cdf)
     }
 
-    fn gamma_1(&self, l: u8) -> f32 {
-        (self.0[usize::from(l)] + f32::EPSILON).ln() / 8.0
+    // `bilinear()` of every pixel of row `y`, sweeping the spans of `SurroundingBlocks` and
+    // blending `BILINEAR_LANES` pixels at a time.
+    fn bilinear_row(&self, y: usize, luminances: &[u8], out: &mut [f32], sweep: &BilinearSweep) {
+        let (top, bottom) = sweep.rows[y];
+        let spans = SurroundingBlocks {
+            grid: self,
+            top,
+            bottom,
+            spans: sweep.spans.iter(),
+        };
+        let mut lanes = [[0.0; BILINEAR_LANES]; 6];
+        let mut count = 0;
+        for (span, a, b, c, d) in spans {
+            let m = match (a, c) {
+                (Some(_), Some(_)) => sweep.row_weights[y],
+                _ if a.is_some() || b.is_some() => 1.0,
+                _ => 0.0,
+            };
+            let n = match (a, b) {
+                (Some(_), Some(_)) => None,
+                _ if a.is_some() || c.is_some() => Some(1.0),
+                _ => Some(0.0),
+            };
+            for x in span {
+                let l0 = luminances[x];
+                let value = |block: Option<&Block>| block.map_or(0.0, |block| block.enhance(l0));
+                let n = n.unwrap_or(sweep.column_weights[x]);
+                let terms = [m, n, value(a), value(b), value(c), value(d)];
+                for (lane, term) in lanes.iter_mut().zip(terms) {
+                    lane[count] = term;
+                }
+                count += 1;
+                if count == BILINEAR_LANES {
+                    out[x + 1 - BILINEAR_LANES..=x].copy_from_slice(&blend_bilinear_lanes(&lanes));
+                    count = 0;
+                }
+            }
+        }
+        let len = out.len();
+        out[len - count..].copy_from_slice(&blend_bilinear_lanes(&lanes)[..count]);
+    }
+
+    // The LUTs of the blocks in fixed point, see `FIXED_LUT_ONE`.
+    fn fixed_tables(&self) -> Vec<[i32; 256]> {
+        self.blocks
+            .iter()
+            .map(|block| block.table.map(|t| (t * FIXED_LUT_ONE).round() as i32))
+            .collect()
+    }
+
+    // `bilinear_row()` with the `fixed_tables()` of the blocks, blended in integer arithmetic.
+    fn bilinear_row_fixed(
+        &self,
+        y: usize,
+        luminances: &[u8],
+        out: &mut [f32],
+        sweep: &BilinearSweep,
+        tables: &[[i32; 256]],
+    ) {
+        const ONE: i64 = FIXED_WEIGHT_ONE as i64;
+        let (top, bottom) = sweep.rows[y];
+        let table = |row: Option<usize>, column: Option<usize>| {
+            Some(&tables[row? * self.line_blocks + column?])
+        };
+        for span in &sweep.spans {
+            let (a, b) = (table(top, span.left), table(top, span.right));
+            let (c, d) = (table(bottom, span.left), table(bottom, span.right));
+            let m = match (a, c) {
+                (Some(_), Some(_)) => i64::from(sweep.fixed_row_weights[y]),
+                _ if a.is_some() || b.is_some() => ONE,
+                _ => 0,
+            };
+            let n = match (a, b) {
+                (Some(_), Some(_)) => None,
+                _ if a.is_some() || c.is_some() => Some(ONE),
+                _ => Some(0),
+            };
+            for x in span.columns.clone() {
+                let l0 = usize::from(luminances[x]);
+                let value = |t: Option<&[i32; 256]>| t.map_or(0, |t| i64::from(t[l0]));
+                let n = n.unwrap_or(i64::from(sweep.fixed_column_weights[x]));
+                let upper = n * value(a) + (ONE - n) * value(b);
+                let lower = n * value(c) + (ONE - n) * value(d);
+                // Rounds the Q23.24 sum back to Q23.8.
+                let l = (m * upper + (ONE - m) * lower + (ONE * ONE / 2)).div_euclid(ONE * ONE);
+                out[x] = l as f32 / FIXED_LUT_ONE;
+            }
+        }
+    }
+
+    // The vertical and horizontal weights `m` and `n` and the LUT values of the four
+    // surrounding blocks (`0.0` for missing blocks).
+    fn bilinear_terms(&self, y: usize, x: usize, l0: u8) -> [f32; 6] {
+        let y0 = std::cmp::min(y, self.aligned_height - 1);
+        let x0 = std::cmp::min(x, self.aligned_width - 1);
+
+        let a = self.get_block_a(y0, x0);
+        let b = self.get_block_b(y0, x0);
+        let c = self.get_block_c(y0, x0);
+        let d = self.get_block_d(y0, x0);
+
+        let m = match (a.map(|a| a.center_y()), c.map(|c| c.center_y())) {
+            (Some(a), Some(c)) if a != c => (c - y) as f32 / (c - a) as f32,
+            _ => {
+                if a.is_some() || b.is_some() {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        };
+        let n = match (a.map(|a| a.center_x()), b.map(|b| b.center_x())) {
+            (Some(a), Some(b)) if a != b => (b - x) as f32 / (b - a) as f32,
+            _ => {
+                if a.is_some() || c.is_some() {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        };
+
+        let value = |block: Option<&Block>| block.map_or(0.0, |block| block.enhance(l0));
+        [m, n, value(a), value(b), value(c), value(d)]
+    }
+
+    // Weights the 3x3 blocks around the pixel by a Gaussian of the distance to their centers
+    // (measured in block sizes, with a sigma of half a block).
+    fn gaussian(&self, y: usize, x: usize, l0: u8) -> f32 {
+        let rows = self.blocks.len() / self.line_blocks;
+        let block_y = std::cmp::min(y / self.block_height, rows - 1);
+        let block_x = std::cmp::min(x / self.block_width, self.line_blocks - 1);
+
+        let mut sum = 0.0;
+        let mut weights = 0.0;
+        for by in block_y.saturating_sub(1)..std::cmp::min(block_y + 2, rows) {
+            for bx in block_x.saturating_sub(1)..std::cmp::min(block_x + 2, self.line_blocks) {
+                let block = self.block(by, bx);
+                let dy = (y as f32 - block.center_y() as f32) / self.block_height as f32;
+                let dx = (x as f32 - block.center_x() as f32) / self.block_width as f32;
+                let w = (-2.0 * (dx * dx + dy * dy)).exp();
+                sum += w * block.enhance(l0);
+                weights += w;
+            }
+        }
+        sum / weights
+    }
+
+    fn block(&self, block_y: usize, block_x: usize) -> &Block {
+        &self.blocks[block_y * self.line_blocks + block_x]
     }
 
-    fn gamma_2(&self, l: u8) -> f32 {
-        (self.0[usize::from(l)] + 1.0) / 2.0
+    fn get_block_a(&self, y: usize, x: usize) -> Option<&Block> {
+        if y < self.block_height / 2 || x < self.block_width / 2 {
+            None
+        } else {
+            let block_y = (y - self.block_height / 2) / self.block_height;
+            let block_x = (x - self.block_width / 2) / self.block_width;
+            Some(self.block(block_y, block_x))
+        }
+    }
+
+    fn get_block_b(&self, y: usize, x: usize) -> Option<&Block> {
+        if y < self.block_height / 2 || self.aligned_width <= (x + self.block_width / 2) {
+            None
+        } else {
+            let block_y = (y - self.block_height / 2) / self.block_height;
+            let block_x = (x + self.block_width / 2) / self.block_width;
+            Some(self.block(block_y, block_x))
+        }
+    }
+
+    fn get_block_c(&self, y: usize, x: usize) -> Option<&Block> {
+        if self.aligned_height <= (y + self.block_height / 2) || x < self.block_width / 2 {
+            None
+        } else {
+            let block_y = (y + self.block_height / 2) / self.block_height;
+            let block_x = (x - self.block_width / 2) / self.block_width;
+            Some(self.block(block_y, block_x))
+        }
+    }
+
+    fn get_block_d(&self, y: usize, x: usize) -> Option<&Block> {
+        if self.aligned_height <= (y + self.block_height / 2)
+            || self.aligned_width <= (x + self.block_width / 2)
+        {
+            None
+        } else {
+            let block_y = (y + self.block_height / 2) / self.block_height;
+            let block_x = (x + self.block_width / 2) / self.block_width;
+            Some(self.block(block_y, block_x))
+        }
+    }
+}
+
+#[derive(Debug)]
+struct BlockRegions {
+    start: Point,
+    image_width: usize,
+    image_height: usize,
+    block_width: usize,
+    block_height: usize,
+    margin: Point,
+}
+
+impl BlockRegions {
+    fn new(
+        image_width: usize,
+        image_height: usize,
+        block_width: usize,
+        block_height: usize,
+    ) -> Self {
+        Self {
+            start: Point::new(0, 0),
+            image_width,
+            image_height,
+            block_width,
+            block_height,
+            margin: Point::new(0, 0),
+        }
+    }
+
+    // Extends the histogram windows so that neighboring windows overlap by the given fraction of
+    // their size (the block grid itself, and hence the interpolation, is unchanged).
+    fn with_overlap(mut self, overlap: f32) -> Self {
+        self.margin = Self::overlap_margin(self.block_width, self.block_height, overlap);
+        self
+    }
+
+    // The margin by which the windows of `width x height` blocks are extended on each side.
+    fn overlap_margin(width: usize, height: usize, overlap: f32) -> Point {
+        let margin = |size: usize| (size as f32 * overlap / (2.0 * (1.0 - overlap))).round();
+        Point::new(margin(width) as usize, margin(height) as usize)
+    }
+}
+
+impl Iterator for BlockRegions {
+    // The block region and its histogram window.
+    type Item = (Region, Region);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.start.y == self.image_height {
+            return None;
+        }
+
+        let start = self.start;
+        let mut end = Point::new(start.x + self.block_width, start.y + self.block_height);
+        if self.image_width < end.x + self.block_width {
+            end.x = self.image_width;
+        }
+        if self.image_height < end.y + self.block_height {
+            end.y = self.image_height;
+        }
+
+        self.start.x = end.x;
+        if self.start.x == self.image_width {
+            self.start.x = 0;
+            self.start.y = end.y;
+        }
+
+        let region = Region { start, end };
+        let window = region.grown(self.margin, self.image_width, self.image_height);
+        Some((region, window))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Region {
+    start: Point,
+    end: Point,
+}
+
+impl Region {
+    fn size(self) -> (usize, usize) {
+        (self.end.x - self.start.x, self.end.y - self.start.y)
+    }
+
+    fn contains(self, x: usize, y: usize) -> bool {
+        (self.start.x..self.end.x).contains(&x) && (self.start.y..self.end.y).contains(&y)
+    }
+
+    // Extends the region by `margin` on each side, clipped to the image.
+    fn grown(self, margin: Point, image_width: usize, image_height: usize) -> Self {
+        Self {
+            start: Point::new(
+                self.start.x.saturating_sub(margin.x),
+                self.start.y.saturating_sub(margin.y),
+            ),
+            end: Point::new(
+                std::cmp::min(self.end.x + margin.x, image_width),
+                std::cmp::min(self.end.y + margin.y, image_height),
+            ),
+        }
+    }
+
+    fn indices(self, image_width: usize) -> impl Iterator<Item = usize> {
+        (self.start.y..self.end.y).flat_map(move |y| {
+            let offset = y * image_width;
+            (offset + self.start.x)..(offset + self.end.x)
+        })
+    }
+
+    // The number of indices of `sampled_indices()`.
+    fn sample_count(self, step: usize) -> usize {
+        let count = |start: usize, end: usize| end.div_ceil(step) - start.div_ceil(step);
+        count(self.start.x, self.end.x) * count(self.start.y, self.end.y)
+    }
+
+    // The indices of every `step`-th pixel of every `step`-th row, aligned to the image origin.
+    fn sampled_indices(self, image_width: usize, step: usize) -> impl Iterator<Item = usize> {
+        let x0 = self.start.x.next_multiple_of(step);
+        (self.start.y.next_multiple_of(step)..self.end.y)
+            .step_by(step)
+            .flat_map(move |y| {
+                let offset = y * image_width;
+                (offset + x0..offset + self.end.x).step_by(step)
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Point {
+    x: usize,
+    y: usize,
+}
+
+impl Point {
+    fn new(x: usize, y: usize) -> Self {
+        Self { x, y }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Histogram([u64; 256]);
+
+impl Histogram {
+    fn new(pixels: impl Iterator<Item = (u8, u32)>) -> Self {
+        let mut histogram = [0; 256];
+        for (intensity, weight) in pixels {
+            histogram[usize::from(intensity)] += u64::from(weight);
+        }
+        Self(histogram)
+    }
+
+    // The histogram of unweighted luminances.
+    fn of_luminances(luminances: &[u8]) -> Self {
+        let mut histogram = [0; 256];
+        for &l in luminances {
+            histogram[usize::from(l)] += 1;
+        }
+        Self(histogram)
+    }
+
+    fn iter(&self) -> impl '_ + DoubleEndedIterator<Item = (u8, u64)> {
+        self.0.iter().enumerate().map(|(l, &c)| (l as u8, c))
+    }
+
+    fn count(&self) -> u64 {
+        self.0.iter().sum()
+    }
+
+    fn sum(&self) -> u64 {
+        self.iter().map(|(l, c)| u64::from(l) * c).sum()
+    }
+
+    fn min(&self) -> Option<u8> {
+        self.iter().find(|&(_, c)| c > 0).map(|(l, _)| l)
+    }
+
+    fn max(&self) -> Option<u8> {
+        self.iter().rev().find(|&(_, c)| c > 0).map(|(l, _)| l)
+    }
+
+    // Both are NaN for an empty histogram.
+    fn mean_and_sigma(&self) -> (f32, f32) {
+        let m = self.count() as f32;
+        let avg = self.sum() as f32 / m;
+        let sigma = (self
+            .iter()
+            .map(|(l, c)| c as f32 * (f32::from(l) - avg).powi(2))
+            .sum::<f32>()
+            / m)
+            .sqrt();
+        (avg, sigma)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Pdf([f32; 256]);
+
+impl Pdf {
+    fn new(histogram: &Histogram) -> Self {
+        let mut pdf = [0.0; 256];
+        let n = histogram.count() as f32;
+        for (i, c) in histogram.iter() {
+            pdf[usize::from(i)] = c as f32 / n;
+        }
+        Self(pdf)
+    }
+
+    fn to_weighting_distribution(&self, strategy: &dyn WeightingStrategy) -> Self {
+        Self(strategy.weight(&self.0))
+    }
+
+    fn redistribute(mut self, clip_point: f32, strategy: &dyn RedistributionStrategy) -> Self {
+        strategy.redistribute(&mut self.0, clip_point);
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Cdf([f32; 256]);
+
+impl Cdf {
+    fn new(pdf: &Pdf) -> Self {
+        let mut cdf = [0.0; 256];
+        let mut sum = 0.0;
+        for (i, x) in pdf.0.iter().copied().enumerate() {
+            sum += x;
+            cdf[i] = sum;
+        }
+        for x in &mut cdf {
+            *x /= sum;
+        }
+        Self(cdf)
+    }
+
+    fn gamma_1(&self, l: u8, params: &GammaParams) -> f32 {
+        (self.0[usize::from(l)] + f32::EPSILON).ln() / params.gamma_1_divisor
+    }
+
+    fn gamma_2(&self, l: u8, params: &GammaParams) -> f32 {
+        (self.0[usize::from(l)] + params.gamma_2_offset) / params.gamma_2_divisor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn region_indices_use_the_image_row_stride() {
+        let region = Region {
+            start: Point { x: 2, y: 1 },
+            end: Point { x: 5, y: 3 },
+        };
+        assert_eq!(
+            region.indices(6).collect::<Vec<_>>(),
+            [8, 9, 10, 14, 15, 16]
+        );
+    }
+
+    #[test]
+    fn try_enhance_validates_input() {
+        let clahe = AutomaticClahe::new();
+        let mut pixels = vec![0; 64 * 64 * 4];
+
+        assert!(clahe.try_enhance_rgba_image(&mut pixels, 64).is_ok());
+        assert!(matches!(
+            clahe.try_enhance_rgba_image(&mut pixels, 0),
+            Err(ClaheError::ZeroDimension { .. })
+        ));
+        assert!(matches!(
+            clahe.try_enhance_rgba_image(&mut pixels[1..], 64),
+            Err(ClaheError::BufferSizeMismatch { .. })
+        ));
+        assert!(matches!(
+            clahe.try_enhance_rgba_image(&mut pixels[..64 * 16 * 4], 64),
+            Err(ClaheError::BlockLargerThanImage { .. })
+        ));
+        assert!(matches!(
+            clahe.try_enhance_rgb_image_with_alpha(&mut pixels[..64 * 64 * 3], 64, Some(&[0; 3])),
+            Err(ClaheError::PlaneSizeMismatch { .. })
+        ));
+
+        let clahe = AutomaticClahe::with_options(AutomaticClaheOptions {
+            block_width: 0,
+            ..Default::default()
+        });
+        assert!(matches!(
+            clahe.try_enhance_rgba_image(&mut pixels, 64),
+            Err(ClaheError::InvalidOptions(OptionsError::ZeroBlockSize))
+        ));
+    }
+
+    #[test]
+    fn block_size_scales_with_image() {
+        let clahe = AutomaticClahe::with_options(AutomaticClaheOptions {
+            block_size: BlockSize::Auto,
+            ..Default::default()
+        });
+
+        let (width, height) = (800, 400);
+        let luminances = (0..width * height)
+            .map(|i| ((i % width) * 7 % 256) as u8)
+            .collect::<Vec<_>>();
+        let (block_width, block_height) = clahe.block_dimensions(width, height, &luminances);
+        assert!((100..=200).contains(&block_width));
+        assert!((50..=100).contains(&block_height));
+
+        let clahe = AutomaticClahe::with_options(AutomaticClaheOptions {
+            block_size: BlockSize::Tiles { x: 8, y: 6 },
+            ..Default::default()
+        });
+        assert_eq!(clahe.block_dimensions(810, 400, &luminances), (101, 66));
+        assert_eq!(
+            BlockRegions::new(810, 400, 101, 66).count(),
+            8 * 6,
+            "remainders are merged into the last blocks"
+        );
+
+        // Images smaller than the default 32x32 blocks are accepted as well.
+        let mut pixels = vec![10; 5 * 3 * 4];
+        assert!(clahe.try_enhance_rgba_image(&mut pixels, 5).is_ok());
+    }
+
+    #[test]
+    fn enhance_checked_never_panics() {
+        let mut seed = 12345u64;
+        let mut rand = |n: usize| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((seed >> 33) as usize) % n
+        };
+        let floats = [0.0, 1.5, 100.0, 1e30, -1.0, f32::NAN, f32::INFINITY];
+        for _ in 0..500 {
+            let options = AutomaticClaheOptions {
+                block_width: rand(40),
+                block_height: rand(40),
+                alpha: floats[rand(floats.len())],
+                p: floats[rand(floats.len())],
+                d_threshold: rand(256) as u8,
+                ..Default::default()
+            };
+            let width = options.block_width + rand(40);
+            let height = options.block_height + rand(40);
+            let len = if rand(8) == 0 {
+                rand(70 * 70 * 4)
+            } else {
+                width * height * 4
+            };
+            let mut pixels = (0..len).map(|_| rand(256) as u8).collect::<Vec<_>>();
+            if rand(4) == 0 {
+                pixels.iter_mut().for_each(|p| *p = 0);
+            }
+
+            // Checks the unguarded path so that panics are not hidden by `catch_unwind()`.
+            let clahe = AutomaticClahe::with_options(options);
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                clahe.try_enhance_rgba_image(&mut pixels, width)
+            }));
+            assert!(result.is_ok(), "{:?}, width={}, len={}", clahe, width, len);
+            assert!(!matches!(
+                clahe.enhance_rgba_checked(&mut pixels, width),
+                Err(ClaheError::Internal)
+            ));
+        }
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn enhance_array3_handles_contiguous_and_strided_views() {
+        let (width, height) = (40, 36);
+        let original = (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                [(x * 6) as u8, (y * 7) as u8, ((x * y) % 251) as u8, 200]
+            })
+            .collect::<Vec<_>>();
+        let clahe = AutomaticClahe::new();
+        let mut expected = original.clone();
+        clahe.enhance_rgba_image(&mut expected, width);
+
+        let mut array =
+            ndarray::Array3::from_shape_vec((height, width, 4), original.clone()).unwrap();
+        clahe.enhance_array3(&mut array.view_mut());
+        assert_eq!(array.as_slice().unwrap(), &expected[..]);
+
+        // The left half of an image twice as wide, which is not contiguous.
+        let mut wide = ndarray::Array3::<u8>::zeros((height, 2 * width, 4));
+        wide.slice_mut(ndarray::s![.., ..width, ..])
+            .assign(&ndarray::Array3::from_shape_vec((height, width, 4), original).unwrap());
+        let mut view = wide.slice_mut(ndarray::s![.., ..width, ..]);
+        assert!(view.as_slice_mut().is_none());
+        clahe.try_enhance_array3(&mut view).unwrap();
+        assert_eq!(
+            wide.slice(ndarray::s![.., ..width, ..])
+                .iter()
+                .copied()
+                .collect::<Vec<_>>(),
+            expected
+        );
+        assert!(wide
+            .slice(ndarray::s![.., width.., ..])
+            .iter()
+            .all(|&v| v == 0));
+
+        let mut gray = ndarray::Array3::<u8>::zeros((height, width, 2));
+        assert!(matches!(
+            clahe.try_enhance_array3(&mut gray.view_mut()),
+            Err(ClaheError::UnsupportedChannelCount(2))
+        ));
+    }
+
+    #[test]
+    fn build_lut_from_histogram_matches_single_block_enhancement() {
+        let mut pixels = (0..32 * 32)
+            .flat_map(|i| {
+                let l = (40 + i % 32 + i / 64) as u8;
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let mut hist = [0; 256];
+        for p in pixels.chunks(3) {
+            hist[usize::from(p[0])] += 1;
+        }
+        let original = pixels.clone();
+
+        let clahe = AutomaticClahe::new();
+        let lut = clahe.build_lut_from_histogram(&hist);
+        assert_ne!(lut, std::array::from_fn(|i| i as u8));
+
+        clahe.enhance_rgb_image(&mut pixels, 32);
+        for (p, o) in pixels.chunks(3).zip(original.chunks(3)) {
+            assert_eq!(p[0], lut[usize::from(o[0])]);
+        }
+    }
+
+    #[test]
+    fn clip_limit_overrides_automatic_clip_point() {
+        let mut hist = [0; 256];
+        for (l, c) in hist.iter_mut().enumerate().skip(40).take(60) {
+            *c = if l % 10 == 0 { 500 } else { 10 };
+        }
+        let lut = |alpha, clip_limit| {
+            AutomaticClahe::with_options(AutomaticClaheOptions {
+                alpha,
+                clip_limit,
+                ..Default::default()
+            })
+            .build_lut_from_histogram(&hist)
+        };
+
+        assert_ne!(lut(100.0, None), lut(400.0, None));
+        assert_eq!(lut(100.0, Some(2.0)), lut(400.0, Some(2.0)));
+        assert_ne!(lut(100.0, Some(2.0)), lut(100.0, Some(40.0)));
+    }
+
+    #[test]
+    fn strength_blends_with_original() {
+        let hist = std::array::from_fn(|l| if (60..120).contains(&l) { 5 } else { 0 });
+        let lut = |strength| {
+            AutomaticClahe::with_options(AutomaticClaheOptions {
+                strength,
+                ..Default::default()
+            })
+            .build_lut_from_histogram(&hist)
+        };
+
+        let (none, half, full) = (lut(0.0), lut(0.5), lut(1.0));
+        assert_eq!(none, std::array::from_fn(|l| l as u8));
+        for l in 60..120 {
+            let (lo, hi) = (none[l].min(full[l]), none[l].max(full[l]));
+            assert!((lo..=hi).contains(&half[l]));
+        }
+    }
+
+    #[test]
+    fn fusion_strengths_favor_well_exposed_results() {
+        let hist = std::array::from_fn(|l| if (60..120).contains(&l) { 5 } else { 0 });
+        let lut = |strength, fusion_strengths| {
+            AutomaticClahe::with_options(AutomaticClaheOptions {
+                strength,
+                fusion_strengths,
+                ..Default::default()
+            })
+            .build_lut_from_histogram(&hist)
+        };
+
+        let (none, full) = (lut(0.0, vec![]), lut(1.0, vec![]));
+        let fused_none = lut(1.0, vec![0.0, 0.0]);
+        assert!((0..256).all(|l| none[l].abs_diff(fused_none[l]) <= 1));
+        let fused = lut(1.0, vec![0.0, 1.0]);
+        for l in 60..120 {
+            let (lo, hi) = (none[l].min(full[l]), none[l].max(full[l]));
+            assert!((lo..=hi).contains(&fused[l]));
+            // The fused luminance is closer to mid-gray than the plain average.
+            let average = (f32::from(none[l]) + f32::from(full[l])) / 2.0;
+            assert!((f32::from(fused[l]) - 127.5).abs() <= (average - 127.5).abs() + 1.0);
+        }
+    }
+
+    #[test]
+    fn per_channel_enhances_channels_independently() {
+        let width = 64;
+        let gray = (0..width * 48)
+            .flat_map(|i| {
+                let l = (30 + i % width + i / width) as u8;
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let color = gray
+            .chunks(3)
+            .flat_map(|p| [p[0], p[1] / 2, 255 - p[2]])
+            .collect::<Vec<_>>();
+
+        let luminance = AutomaticClahe::new();
+        let per_channel = AutomaticClahe::with_options(AutomaticClaheOptions {
+            color_space: ColorSpace::PerChannel,
+            ..Default::default()
+        });
+        let enhance = |clahe: &AutomaticClahe, pixels: &[u8]| {
+            let mut pixels = pixels.to_vec();
+            clahe.enhance_rgb_image(&mut pixels, width);
+            pixels
+        };
+
+        assert_eq!(enhance(&per_channel, &gray), enhance(&luminance, &gray));
+        assert_ne!(enhance(&per_channel, &color), enhance(&luminance, &color));
+
+        let mut red = Image::new(color.iter().step_by(3).copied().collect(), width);
+        luminance.enhance_luminances(&mut red);
+        let enhanced = enhance(&per_channel, &color);
+        assert!(enhanced.iter().step_by(3).eq(red.luminances.iter()));
+    }
+
+    #[test]
+    fn dual_gamma_correction_can_be_disabled() {
+        let hist = std::array::from_fn(|l| if l % 3 == 0 { 4 } else { 1 });
+        let lut = |dual_gamma_correction, d_threshold| {
+            AutomaticClahe::with_options(AutomaticClaheOptions {
+                dual_gamma_correction,
+                d_threshold,
+                ..Default::default()
+            })
+            .build_lut_from_histogram(&hist)
+        };
+
+        assert_ne!(lut(true, 50), lut(true, 255));
+        assert_eq!(lut(false, 50), lut(true, 255));
+    }
+
+    #[test]
+    fn l_alpha_percentile_changes_dark_lift() {
+        let hist = std::array::from_fn(|l| if l < 100 { 10 } else { 1 });
+        let lut = |l_alpha_percentile| {
+            AutomaticClahe::with_options(AutomaticClaheOptions {
+                l_alpha_percentile,
+                ..Default::default()
+            })
+            .build_lut_from_histogram(&hist)
+        };
+
+        let (low, high) = (lut(0.5), lut(0.95));
+        assert!((0..256).all(|l| low[l] >= high[l]));
+        assert_ne!(low, high);
+    }
+
+    #[test]
+    fn l_alpha_percentile_below_the_black_pixels() {
+        // Mostly black, so that the CDF of level 0 exceeds the percentile, and whole black blocks.
+        let width = 64;
+        let mut pixels = (0..width * 64)
+            .flat_map(|i| {
+                let l = if i % width < 48 {
+                    0
+                } else {
+                    (i / width * 4) as u8
+                };
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        AutomaticClahe::with_options(AutomaticClaheOptions {
+            output_black: 16,
+            ..Default::default()
+        })
+        .enhance_rgb_image(&mut pixels, width);
+        assert!(pixels.iter().all(|&l| l >= 16));
+    }
+
+    #[test]
+    fn output_range_limits_luminance() {
+        let hist = std::array::from_fn(|l| if l % 5 == 0 { 3 } else { 1 });
+        let lut = |clamp_mode| {
+            AutomaticClahe::with_options(AutomaticClaheOptions {
+                output_black: 16,
+                output_white: 235,
+                clamp_mode,
+                ..Default::default()
+            })
+            .build_lut_from_histogram(&hist)
+        };
+        let full = AutomaticClahe::new().build_lut_from_histogram(&hist);
+
+        let clamped = lut(ClampMode::Clamp);
+        let scaled = lut(ClampMode::Scale);
+        for l in 0..256 {
+            assert_eq!(clamped[l], full[l].clamp(16, 235));
+            assert!((16..=235).contains(&scaled[l]));
+        }
+        assert_ne!(clamped, scaled);
+    }
+
+    #[test]
+    fn shadow_lift_only_brightens_shadows() {
+        let hist = std::array::from_fn(|l| if l % 3 == 0 { 5 } else { 1 });
+        let lut = |shadow_lift| {
+            AutomaticClahe::with_options(AutomaticClaheOptions {
+                shadow_lift,
+                ..Default::default()
+            })
+            .build_lut_from_histogram(&hist)
+        };
+
+        let full = lut(None);
+        let lifted = lut(Some(ShadowLift {
+            threshold: 64,
+            rolloff: 32,
+        }));
+        for l in 0..256 {
+            if l <= 64 {
+                assert_eq!(lifted[l], full[l].max(l as u8));
+            } else if l >= 96 {
+                assert_eq!(lifted[l], l as u8);
+            } else {
+                assert!((l as u8..=full[l].max(l as u8)).contains(&lifted[l]));
+            }
+        }
+    }
+
+    #[test]
+    fn highlight_knee_keeps_highlights_below_white() {
+        let hist = std::array::from_fn(|l| if l < 128 { 1 } else { 8 });
+        let lut = |highlight_knee| {
+            AutomaticClahe::with_options(AutomaticClaheOptions {
+                highlight_knee,
+                ..Default::default()
+            })
+            .build_lut_from_histogram(&hist)
+        };
+
+        let full = lut(None);
+        let kneed = lut(Some(200));
+        for l in 0..256 {
+            if full[l] <= 200 || full[l] <= l as u8 {
+                assert_eq!(kneed[l], full[l]);
+            } else {
+                assert!(kneed[l] <= full[l] && kneed[l] >= l as u8);
+            }
+            if l < 255 && full[l] > l as u8 {
+                assert!(kneed[l] < 255, "{}", l);
+            }
+        }
+    }
+
+    #[test]
+    fn point_sources_do_not_bloom() {
+        let width = 64;
+        let mut original = vec![30; width * 64];
+        for (i, l) in original.iter_mut().enumerate() {
+            *l += ((i % width) / 8) as u8;
+        }
+        for &(x, y) in &[(10, 10), (40, 20), (20, 50)] {
+            original[y * width + x] = 200;
+        }
+        let rgb = original.iter().flat_map(|&l| [l, l, l]).collect::<Vec<_>>();
+        let enhance = |point_source_protection| {
+            let mut pixels = rgb.clone();
+            AutomaticClahe::with_options(AutomaticClaheOptions {
+                point_source_protection,
+                ..Default::default()
+            })
+            .enhance_rgb_image(&mut pixels, width);
+            pixels.chunks(3).map(|p| p[0]).collect::<Vec<_>>()
+        };
+
+        let plain = enhance(None);
+        let protected = enhance(Some(PointSourceProtection::default()));
+        for &(x, y) in &[(10, 10), (40, 20), (20, 50)] {
+            let star = y * width + x;
+            let background = star + 3;
+            // The star keeps its contrast to the background, which no longer sees the star in
+            // its block statistics.
+            let contrast = i32::from(protected[star]) - i32::from(protected[background]);
+            assert!(contrast.abs_diff(200 - i32::from(original[background])) <= 2);
+            assert_ne!(protected[background], plain[background]);
+        }
+    }
+
+    #[test]
+    fn max_gain_limits_amplification() {
+        let hist = std::array::from_fn(|l| if l < 40 { 10 } else { 0 });
+        let lut = |max_gain| {
+            AutomaticClahe::with_options(AutomaticClaheOptions {
+                max_gain,
+                ..Default::default()
+            })
+            .build_lut_from_histogram(&hist)
+        };
+
+        let (capped, full) = (lut(Some(1.5)), lut(None));
+        for l in 0..40 {
+            assert!(f32::from(capped[l]) <= l as f32 * 1.5);
+            assert!(capped[l] <= full[l]);
+        }
+        assert_ne!(capped, full);
+    }
+
+    #[test]
+    fn flat_blocks_are_left_untouched() {
+        let width = 64;
+        let mut pixels = (0..width * 32)
+            .flat_map(|i| {
+                let x = i % width;
+                let l = if x < 32 {
+                    50 + (i % 3) as u8
+                } else {
+                    (x * 4) as u8
+                };
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let original = pixels.clone();
+        let clahe = AutomaticClahe::with_options(AutomaticClaheOptions {
+            min_sigma: 2.0,
+            ..Default::default()
+        });
+        clahe.enhance_rgb_image(&mut pixels, width);
+
+        for (i, (p, o)) in pixels.chunks(3).zip(original.chunks(3)).enumerate() {
+            if i % width < 16 {
+                assert_eq!(p, o);
+            }
+        }
+        assert_ne!(pixels, original);
+    }
+
+    #[test]
+    fn saturation_factor_scales_saturation() {
+        let width = 48;
+        let pixels = (0..width * 32)
+            .flat_map(|i| {
+                let l = (40 + i % width + i / width) as u8;
+                [l, l / 2, l / 3]
+            })
+            .collect::<Vec<_>>();
+        for color_space in [
+            ColorSpace::HsvInteger,
+            ColorSpace::HsvFloat,
+            ColorSpace::Hsl,
+            ColorSpace::YCbCrBt709,
+            ColorSpace::Lab,
+            ColorSpace::Oklab,
+            ColorSpace::Bt709,
+        ] {
+            let enhance = |saturation_factor| {
+                let mut pixels = pixels.clone();
+                AutomaticClahe::with_options(AutomaticClaheOptions {
+                    color_space,
+                    saturation_factor,
+                    ..Default::default()
+                })
+                .enhance_rgb_image(&mut pixels, width);
+                pixels
+            };
+
+            for p in enhance(0.0).chunks(3) {
+                assert!(p[0].abs_diff(p[1]) <= 1 && p[1].abs_diff(p[2]) <= 1);
+            }
+            let spread = |pixels: Vec<u8>| {
+                pixels
+                    .chunks(3)
+                    .map(|p| u32::from(p[0] - p[2]))
+                    .sum::<u32>()
+            };
+            assert!(spread(enhance(1.5)) > spread(enhance(1.0)));
+        }
+    }
+
+    #[test]
+    fn chroma_noise_reduction_desaturates_boosted_shadows() {
+        let width = 48;
+        let pixels = (0..width * 32)
+            .flat_map(|i| {
+                let l = (10 + i % width / 2 + i / width) as u8;
+                [l, l / 2, l / 3]
+            })
+            .collect::<Vec<_>>();
+        let spread = |chroma_noise_reduction| {
+            let mut pixels = pixels.clone();
+            AutomaticClahe::with_options(AutomaticClaheOptions {
+                chroma_noise_reduction,
+                ..Default::default()
+            })
+            .enhance_rgb_image(&mut pixels, width);
+            pixels
+                .chunks(3)
+                .map(|p| u32::from(p[0] - p[2]))
+                .sum::<u32>()
+        };
+        assert!(spread(2.0) < spread(0.0));
+    }
+
+    #[test]
+    fn protected_ranges_are_untouched() {
+        let width = 64;
+        let mut pixels = (0..width * 32)
+            .flat_map(|i| match i % 5 {
+                0 => [0, 0, 0],
+                1 => [255, 250, 254],
+                _ => [(i % 200) as u8, 30, 90],
+            })
+            .collect::<Vec<_>>();
+        let original = pixels.clone();
+        let clahe = AutomaticClahe::with_options(AutomaticClaheOptions {
+            protected_ranges: vec![0..=2, 253..=255],
+            ..Default::default()
+        });
+        clahe.enhance_rgb_image(&mut pixels, width);
+
+        for (i, (p, o)) in pixels.chunks(3).zip(original.chunks(3)).enumerate() {
+            if i % 5 < 2 {
+                assert_eq!(p, o);
+            }
+        }
+        assert_ne!(pixels, original);
+    }
+
+    #[test]
+    fn block_override_is_applied() {
+        let width = 64;
+        let mut pixels = (0..width * 32)
+            .flat_map(|i| {
+                let l = (30 + (i % width) * 2 + i / width) as u8;
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let original = pixels.clone();
+        let clahe = AutomaticClahe::new().with_block_override(|info| {
+            assert!(info.l_min <= info.l_max && info.sigma >= 0.0);
+            (info.x == 0).then(|| BlockOverride {
+                identity: true,
+                ..Default::default()
+            })
+        });
+        clahe.enhance_rgb_image(&mut pixels, width);
+
+        for (i, (p, o)) in pixels.chunks(3).zip(original.chunks(3)).enumerate() {
+            if i % width < 16 {
+                assert_eq!(p, o);
+            }
+        }
+        assert_ne!(pixels, original);
+    }
+
+    #[test]
+    fn clip_point_fn_replaces_the_formula() {
+        let width = 64;
+        let original = (0..width * 32)
+            .flat_map(|i| {
+                let l = (30 + (i % width) * 2 + i / width) as u8;
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let enhance = |clahe: AutomaticClahe| {
+            let mut pixels = original.clone();
+            clahe.enhance_rgb_image(&mut pixels, width);
+            pixels
+        };
+        let limited = AutomaticClahe::with_options(AutomaticClaheOptions {
+            clip_limit: Some(1.5),
+            ..Default::default()
+        });
+        let custom = AutomaticClahe::new().with_clip_point_fn(|info| {
+            assert!(info.l_min <= info.l_max && info.sigma >= 0.0);
+            1.5 / 256.0
+        });
+        assert_eq!(enhance(custom), enhance(limited));
+        assert_ne!(
+            enhance(AutomaticClahe::new().with_clip_point_fn(|_| 1.5 / 256.0)),
+            enhance(AutomaticClahe::new())
+        );
+    }
+
+    #[test]
+    fn fixed_point_luts_are_within_a_level() {
+        let width = 61;
+        let original = (0..width * 45)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                [(x * 4) as u8, (y * 5) as u8, ((x * y) % 251) as u8]
+            })
+            .collect::<Vec<_>>();
+        let enhance = |options: AutomaticClaheOptions| {
+            let mut pixels = original.clone();
+            AutomaticClahe::with_options(options).enhance_rgb_image(&mut pixels, width);
+            pixels
+        };
+        let fixed = enhance(AutomaticClaheOptions {
+            fixed_point_luts: true,
+            ..Default::default()
+        });
+        let float = enhance(Default::default());
+        assert!(fixed.iter().zip(&float).all(|(a, b)| a.abs_diff(*b) <= 1));
+        let identity = AutomaticClaheOptions {
+            identity_luts: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            enhance(AutomaticClaheOptions {
+                fixed_point_luts: true,
+                ..identity.clone()
+            }),
+            enhance(identity)
+        );
+    }
+
+    #[test]
+    fn fixed_point_luts_keep_entries_beyond_white() {
+        let mut seed = 12345u64;
+        let mut rand = || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as u8
+        };
+        let width = 64;
+        // Random noise, and dark blocks next to saturated ones whose LUTs exceed 255.
+        let noise = (0..width * 48 * 3).map(|_| rand()).collect::<Vec<_>>();
+        let saturating = (0..width * 48)
+            .flat_map(|i| {
+                let l = if (i % width / 16 + i / width / 16) % 2 == 0 {
+                    250 + rand() % 6
+                } else {
+                    rand() % 8
+                };
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        for original in [noise, saturating] {
+            for strength_gradient in [
+                None,
+                Some(StrengthGradient::Linear {
+                    start: (0.0, 0.0),
+                    end: (1.0, 1.0),
+                }),
+            ] {
+                let enhance = |fixed_point_luts| {
+                    let mut pixels = original.clone();
+                    AutomaticClahe::with_options(AutomaticClaheOptions {
+                        block_width: 16,
+                        block_height: 16,
+                        strength_gradient,
+                        fixed_point_luts,
+                        ..Default::default()
+                    })
+                    .enhance_rgb_image(&mut pixels, width);
+                    pixels
+                };
+                let (fixed, float) = (enhance(true), enhance(false));
+                assert!(fixed.iter().zip(&float).all(|(a, b)| a.abs_diff(*b) <= 1));
+            }
+        }
+    }
+
+    #[test]
+    fn log_luminance_enhances_stops() {
+        let width = 64;
+        // Mostly near-black sky with a few bright stars.
+        let original = (0..width * 64)
+            .flat_map(|i| {
+                let l = if i % 97 == 0 {
+                    250
+                } else {
+                    (i % width / 8 + i / width / 16) as u8
+                };
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let enhance = |options: AutomaticClaheOptions| {
+            let mut pixels = original.clone();
+            AutomaticClahe::with_options(options).enhance_rgb_image(&mut pixels, width);
+            pixels
+        };
+        let log = AutomaticClaheOptions {
+            log_luminance: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            enhance(AutomaticClaheOptions {
+                identity_luts: true,
+                ..log.clone()
+            }),
+            original
+        );
+        let enhanced = enhance(log);
+        assert_ne!(enhanced, enhance(Default::default()));
+        for (p, o) in enhanced.chunks(3).zip(original.chunks(3)) {
+            if o[0] == 250 {
+                assert!(p[0] >= 200);
+            }
+        }
+    }
+
+    #[test]
+    fn adjacent_block_luts_are_limited() {
+        let width = 64;
+        // A dark textured half next to a bright flat one.
+        let luminances = (0..width * 64)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                if x < 32 {
+                    (10 + (x * 7 + y * 3) % 40) as u8
+                } else {
+                    200 + (x % 3) as u8
+                }
+            })
+            .collect::<Vec<_>>();
+        let image = Image::new(luminances, width);
+        let options = |max_block_divergence| AutomaticClaheOptions {
+            block_width: 16,
+            block_height: 16,
+            alpha: 400.0,
+            max_block_divergence,
+            ..Default::default()
+        };
+        let tables = |max_block_divergence| {
+            let clahe = AutomaticClahe::with_options(options(max_block_divergence));
+            let (mut layouts, _) = clahe.block_layouts(&image, false);
+            layouts[0]
+                .blocks_mut()
+                .iter()
+                .map(|block| block.table)
+                .collect::<Vec<_>>()
+        };
+        let divergence = |tables: &[[f32; 256]]| {
+            let mut max = 0.0f32;
+            for (i, a) in tables.iter().enumerate() {
+                for j in [i + 1, i + 4] {
+                    if j < tables.len() && (j == i + 4 || j % 4 != 0) {
+                        for (a, b) in a.iter().zip(&tables[j]) {
+                            max = max.max((a - b).abs());
+                        }
+                    }
+                }
+            }
+            max
+        };
+
+        let limited = tables(Some(8.0));
+        assert!(divergence(&tables(None)) > 20.0);
+        assert!(divergence(&limited) <= 8.0 + 1e-2);
+    }
+
+    #[test]
+    fn strength_gradient_limits_the_enhanced_area() {
+        let width = 64;
+        let original = (0..width * 64)
+            .flat_map(|i| {
+                let l = (30 + (i % width) * 2 + i / width) as u8;
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let enhance = |strength_gradient| {
+            let mut pixels = original.clone();
+            AutomaticClahe::with_options(AutomaticClaheOptions {
+                strength_gradient,
+                ..Default::default()
+            })
+            .enhance_rgb_image(&mut pixels, width);
+            pixels
+        };
+        let full = enhance(None);
+        let bottom = enhance(Some(StrengthGradient::Linear {
+            start: (0.0, 0.4),
+            end: (0.0, 0.6),
+        }));
+        let rows = |pixels: &[u8], range: std::ops::Range<usize>| {
+            pixels[range.start * width * 3..range.end * width * 3].to_vec()
+        };
+        assert_eq!(rows(&bottom, 0..25), rows(&original, 0..25));
+        assert_eq!(rows(&bottom, 39..64), rows(&full, 39..64));
+        assert_ne!(rows(&full, 0..25), rows(&original, 0..25));
+
+        let center = enhance(Some(StrengthGradient::Radial {
+            center: (0.5, 0.5),
+            inner_radius: 0.1,
+            outer_radius: 0.3,
+        }));
+        assert_eq!(&center[..3], &original[..3]);
+        let middle = (32 * width + 32) * 3;
+        assert_eq!(center[middle], full[middle]);
+        assert_eq!(
+            "radial:0.5,0.5,0.1,0.3".parse::<StrengthGradient>(),
+            Ok(StrengthGradient::Radial {
+                center: (0.5, 0.5),
+                inner_radius: 0.1,
+                outer_radius: 0.3,
+            })
+        );
+    }
+
+    #[test]
+    fn bilinear_rows_match_the_pixels() {
+        for (width, height, block_width, block_height) in [
+            (61, 37, 7, 5),
+            (64, 48, 16, 16),
+            (50, 33, 8, 6),
+            (20, 20, 20, 20),
+            (9, 9, 1, 1),
+        ] {
+            let luminances = (0..width * height)
+                .map(|i| ((i % width) * 3 + (i / width) * 5) as u8)
+                .collect::<Vec<_>>();
+            let image = Image::new(luminances, width);
+            let clahe = AutomaticClahe::with_options(AutomaticClaheOptions {
+                block_width,
+                block_height,
+                ..Default::default()
+            });
+            let (layouts, _) = clahe.block_layouts(&image, false);
+            let BlockLayout::Grid(grid) = &layouts[0] else {
+                panic!("expected a grid");
+            };
+            let sweep = grid.bilinear_sweep(width, height);
+            let mut row = vec![0.0; width];
+            for y in 0..height {
+                let luminances = &image.luminances[y * width..(y + 1) * width];
+                grid.bilinear_row(y, luminances, &mut row, &sweep);
+                for (x, (&l, &l0)) in row.iter().zip(luminances).enumerate() {
+                    assert_eq!(l, grid.bilinear(y, x, l0));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn report_describes_blocks() {
+        let width = 64;
+        let mut pixels = (0..width * 32)
+            .flat_map(|i| {
+                let l = (30 + (i % width) * 2 + i / width) as u8;
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let clahe = AutomaticClahe::with_options(AutomaticClaheOptions {
+            block_width: 16,
+            block_height: 16,
+            ..Default::default()
+        })
+        .with_block_override(|info| {
+            (info.x == 0).then(|| BlockOverride {
+                identity: true,
+                ..Default::default()
+            })
+        });
+        let report = clahe.enhance_image_with_report(&mut pixels, width, ChannelLayout::RGB);
+
+        assert_eq!(report.blocks.len(), 4 * 2);
+        for block in &report.blocks {
+            assert_eq!((block.width, block.height), (16, 16));
+            assert!(block.sigma > 0.0);
+            if block.x == 0 {
+                assert_eq!(block.clip_point, None);
+                assert_eq!(block.lut_deviation, 0.0);
+            } else {
+                assert!(block.clip_point.is_some_and(|c| c > 0.0));
+                assert!(block.lut_deviation > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn subsampled_histograms_give_nearly_identical_output() {
+        let width = 256;
+        let original = (0..width * 128)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                let l = (x / 2 + (x * y) % 23 + y / 4) as u8;
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let enhance = |histogram_subsampling| {
+            let mut pixels = original.clone();
+            AutomaticClahe::with_options(AutomaticClaheOptions {
+                histogram_subsampling,
+                ..Default::default()
+            })
+            .enhance_rgb_image(&mut pixels, width);
+            pixels
+        };
+
+        let (full, proxy) = (enhance(1), enhance(4));
+        let mean_difference = full
+            .iter()
+            .zip(&proxy)
+            .map(|(&a, &b)| f32::from(a.abs_diff(b)))
+            .sum::<f32>()
+            / full.len() as f32;
+        assert_ne!(full, proxy);
+        assert!(mean_difference < 2.0, "{}", mean_difference);
+    }
+
+    #[test]
+    fn extracted_planes_count_their_histogram() {
+        let width = 100;
+        let pixels = (0..width * 90 * 4)
+            .map(|i| ((i * 7919) % 251) as u8)
+            .collect::<Vec<_>>();
+        let clahe = AutomaticClahe::new();
+        let Planes::Luminance(mut image) =
+            clahe.extract_planes(&pixels, width, ChannelLayout::RGBA, None)
+        else {
+            unreachable!("luminance color space");
+        };
+        let counted = image.histogram.as_deref().unwrap().0;
+        assert_eq!(
+            counted,
+            Image::new(image.luminances.clone(), width).histogram().0
+        );
+        assert_eq!(image.histogram().0, counted);
+
+        clahe.enhance_luminances(&mut image);
+        assert!(image.histogram.is_none());
+    }
+
+    #[test]
+    fn sample_budgets_cap_the_block_histograms() {
+        let width = 256;
+        let original = (0..width * 128)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                let l = (x / 2 + (x * y) % 23 + y / 4) as u8;
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let clahe = |histogram_sample_budget| {
+            AutomaticClahe::with_options(AutomaticClaheOptions {
+                histogram_sample_budget,
+                ..Default::default()
+            })
+        };
+        let window = Region {
+            start: Point::new(32, 64),
+            end: Point::new(64, 96),
+        };
+        assert_eq!(clahe(Some(64)).histogram_step(window), 4);
+        assert_eq!(clahe(Some(63)).histogram_step(window), 5);
+        assert_eq!(clahe(Some(1024)).histogram_step(window), 1);
+
+        let enhance = |budget| {
+            let mut pixels = original.clone();
+            clahe(budget).enhance_rgb_image(&mut pixels, width);
+            pixels
+        };
+        let full = enhance(None);
+        assert_eq!(enhance(Some(1024)), full);
+        let budgeted = enhance(Some(64));
+        let mean_difference = full
+            .iter()
+            .zip(&budgeted)
+            .map(|(&a, &b)| f32::from(a.abs_diff(b)))
+            .sum::<f32>()
+            / full.len() as f32;
+        assert_ne!(full, budgeted);
+        assert!(mean_difference < 2.0, "{}", mean_difference);
+    }
+
+    #[test]
+    fn global_curve_approximates_the_enhancement() {
+        let width = 128;
+        let original = (0..width * 64)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                let l = (20 + x / 2 + (x * y) % 17) as u8;
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let clahe = AutomaticClahe::new();
+        let curve = clahe.derive_global_curve(&original, width);
+        assert!(curve.windows(2).all(|w| w[0] <= w[1]));
+
+        let mut enhanced = original.clone();
+        clahe.enhance_rgb_image(&mut enhanced, width);
+        let error = |approximation: &dyn Fn(u8) -> u8| {
+            original
+                .iter()
+                .zip(&enhanced)
+                .map(|(&o, &e)| u32::from(approximation(o).abs_diff(e)))
+                .sum::<u32>()
+        };
+        assert!(error(&|l| curve[usize::from(l)]) * 2 < error(&|l| l));
+    }
+
+    #[test]
+    fn interpolation_modes_work() {
+        let width = 96;
+        let pixels = (0..width * 64)
+            .flat_map(|i| {
+                let l = (20 + (i % width) + (i / width) * 2) as u8;
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let enhance = |interpolation| {
+            let mut pixels = pixels.clone();
+            AutomaticClahe::with_options(AutomaticClaheOptions {
+                interpolation,
+                ..Default::default()
+            })
+            .enhance_rgb_image(&mut pixels, width);
+            pixels
+        };
+
+        let bilinear = enhance(Interpolation::Bilinear);
+        let nearest = enhance(Interpolation::Nearest);
+        let gaussian = enhance(Interpolation::Gaussian);
+        assert_ne!(bilinear, nearest);
+        assert_ne!(bilinear, gaussian);
+
+        // With nearest interpolation every pixel of a block goes through the same LUT.
+        let mut lut = [None; 256];
+        for y in 0..32 {
+            for x in 0..32 {
+                let l = 20 + x + y * 2;
+                let out = nearest[(y * width + x) * 3];
+                assert_eq!(*lut[l].get_or_insert(out), out);
+            }
+        }
+    }
+
+    #[test]
+    fn gaussian_interpolation_smooths_tile_seams() {
+        let (width, height) = (256, 64);
+        // Blocks of very different brightness, so their LUTs differ.
+        let luminances = (0..width * height)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                ((x / 64 * 53 + y / 64 * 31) % 200 + (x + y) % 16) as u8
+            })
+            .collect::<Vec<_>>();
+        let image = Image::new(luminances, width);
+        let stats = GlobalStats::new(&image.histogram(), 0.75);
+        let clahe = AutomaticClahe::new();
+        let grid = BlockGrid::new(Vec::new(), &image, &stats, &clahe, (64, 64), false);
+
+        // The kinks of the LUT blend of a fixed luminance along a row.
+        let kinks = |interpolation| {
+            let row = (0..width)
+                .map(|x| grid.interpolate(interpolation, 32, x, 128))
+                .collect::<Vec<_>>();
+            row.windows(3)
+                .map(|w| (w[0] - 2.0 * w[1] + w[2]).abs())
+                .fold(0.0, f32::max)
+        };
+        assert!(kinks(Interpolation::Gaussian) < kinks(Interpolation::Bilinear));
+    }
+
+    #[test]
+    fn lazy_luts_cover_the_interpolated_levels() {
+        let (width, height) = (70, 45);
+        // Regions of disjoint luminance ranges.
+        let luminances = (0..width * height)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                (x / 16 * 40 + y / 16 * 15 + (x * y) % 11) as u8
+            })
+            .collect::<Vec<_>>();
+        let image = Image::new(luminances, width);
+        let stats = GlobalStats::new(&image.histogram(), 0.75);
+        let clahe = AutomaticClahe::new();
+        let full = BlockGrid::new(Vec::new(), &image, &stats, &clahe, (16, 16), false);
+        let lazy = BlockGrid::new(Vec::new(), &image, &stats, &clahe, (16, 16), true);
+
+        for interpolation in [
+            Interpolation::Nearest,
+            Interpolation::Bilinear,
+            Interpolation::Gaussian,
+        ] {
+            for (i, &l0) in image.luminances.iter().enumerate() {
+                let (y, x) = (i / width, i % width);
+                assert_eq!(
+                    lazy.interpolate(interpolation, y, x, l0),
+                    full.interpolate(interpolation, y, x, l0)
+                );
+            }
+        }
+        assert!(lazy.blocks[0].table[255] == 0.0 && full.blocks[0].table[255] > 0.0);
+    }
+
+    #[test]
+    fn block_overlap_extends_histogram_windows() {
+        let (region, window) = BlockRegions::new(96, 64, 32, 32)
+            .with_overlap(0.5)
+            .nth(1)
+            .unwrap();
+        assert_eq!((region.start.x, region.end.x), (32, 64));
+        assert_eq!((window.start.x, window.end.x), (16, 80));
+        assert_eq!((window.start.y, window.end.y), (0, 48));
+
+        let width = 96;
+        let original = (0..width * 64)
+            .flat_map(|i| {
+                let l = if i % width < 40 { 30 } else { 180 } + (i % 7) as u8;
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let mut pixels = original.clone();
+        AutomaticClahe::new().enhance_rgb_image(&mut pixels, width);
+        let mut overlapped = original;
+        AutomaticClahe::with_options(AutomaticClaheOptions {
+            block_overlap: 0.5,
+            ..Default::default()
+        })
+        .enhance_rgb_image(&mut overlapped, width);
+        assert_ne!(pixels, overlapped);
+    }
+
+    #[test]
+    fn float_color_spaces_keep_colors_with_identity_luts() {
+        let original = (0..64 * 64)
+            .flat_map(|i| [(i % 251) as u8, (i * 7 % 256) as u8, (i / 17) as u8])
+            .collect::<Vec<_>>();
+        for color_space in [
+            ColorSpace::HsvFloat,
+            ColorSpace::Hsl,
+            ColorSpace::YCbCrBt601,
+            ColorSpace::Lab,
+            ColorSpace::Oklab,
+        ] {
+            let mut pixels = original.clone();
+            AutomaticClahe::with_options(AutomaticClaheOptions {
+                identity_luts: true,
+                color_space,
+                ..Default::default()
+            })
+            .enhance_rgb_image(&mut pixels, 64);
+            assert_eq!(pixels, original, "{:?}", color_space);
+        }
+    }
+
+    #[test]
+    fn transfer_function_is_applied() {
+        let width = 64;
+        let original = (0..width * 48)
+            .flat_map(|i| {
+                let l = (30 + i % width + i / width) as u8;
+                [l, l / 2, l / 3]
+            })
+            .collect::<Vec<_>>();
+        let enhance = |clahe: AutomaticClahe| {
+            let mut pixels = original.clone();
+            clahe.enhance_rgb_image(&mut pixels, width);
+            pixels
+        };
+
+        let plain = enhance(AutomaticClahe::new());
+        let identity = TransferFunction::new(|v| v, |v| v);
+        assert_eq!(
+            enhance(AutomaticClahe::new().with_transfer_function(identity)),
+            plain
+        );
+        let gamma = TransferFunction::new(|v| v.powf(2.2), |v| v.powf(1.0 / 2.2));
+        assert_ne!(
+            enhance(AutomaticClahe::new().with_transfer_function(gamma)),
+            plain
+        );
+    }
+
+    #[test]
+    fn block_scales_blend_multiple_grids() {
+        let width = 128;
+        let original = (0..width * 96)
+            .flat_map(|i| {
+                let l = ((i % width) / 2 + (i / width) % 40) as u8;
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let enhance = |block_scales| {
+            let mut pixels = original.clone();
+            AutomaticClahe::with_options(AutomaticClaheOptions {
+                block_scales,
+                ..Default::default()
+            })
+            .enhance_rgb_image(&mut pixels, width);
+            pixels
+        };
+
+        let single = enhance(Vec::new());
+        assert_eq!(enhance(vec![1.0]), single);
+        let multi = enhance(vec![1.0, 2.0]);
+        assert_ne!(multi, single);
+        assert_ne!(multi, enhance(vec![2.0]));
+    }
+
+    #[test]
+    fn gain_smoothing_flattens_halos() {
+        let width = 128;
+        let original = (0..width * 64)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                let l = if x < 40 { 40 } else { 200 } + ((x + y) % 3) as u8;
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let enhance = |gain_smoothing| {
+            let mut pixels = original.clone();
+            AutomaticClahe::with_options(AutomaticClaheOptions {
+                gain_smoothing,
+                ..Default::default()
+            })
+            .enhance_rgb_image(&mut pixels, width);
+            pixels
+        };
+        // Spread of the enhanced dark side, which bilinear interpolation brightens toward the
+        // edge.
+        let spread = |pixels: &[u8]| {
+            let dark = pixels
+                .chunks(3 * width)
+                .flat_map(|row| row[..3 * 40].iter().copied())
+                .collect::<Vec<_>>();
+            dark.iter().max().unwrap() - dark.iter().min().unwrap()
+        };
+
+        let plain = enhance(None);
+        let smoothed = enhance(Some(GuidedFilter {
+            radius: 16,
+            epsilon: 0.01,
+        }));
+        assert!(spread(&smoothed) < spread(&plain));
+    }
+
+    #[test]
+    fn gain_map_matches_enhanced_luminances() {
+        let width = 64;
+        let original = (0..width * 48)
+            .flat_map(|i| {
+                let l = (30 + i % width + i / width) as u8;
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let clahe = AutomaticClahe::new();
+        let gains = clahe.compute_gain_map(&original, width);
+        let mut pixels = original.clone();
+        clahe.enhance_rgb_image(&mut pixels, width);
+
+        assert_eq!(gains.len(), width * 48);
+        for (i, gain) in gains.iter().enumerate() {
+            let (l0, l) = (f32::from(original[i * 3]), f32::from(pixels[i * 3]));
+            assert!(
+                (gain * l0 - l).abs() < 1.0,
+                "{}: {} * {} != {}",
+                i,
+                gain,
+                l0,
+                l
+            );
+        }
+    }
+
+    #[test]
+    fn noise_clip_factor_clips_noisy_blocks_harder() {
+        let width = 64;
+        let image = |noise: usize| {
+            (0..width * 64)
+                .flat_map(|i| {
+                    let v = i * 7919 % 13;
+                    let l = (30 + (i % width) / 16 + v * v / 30 * noise) as u8;
+                    [l, l, l]
+                })
+                .collect::<Vec<_>>()
+        };
+        let enhance = |pixels: &[u8], noise_clip_factor| {
+            let mut pixels = pixels.to_vec();
+            AutomaticClahe::with_options(AutomaticClaheOptions {
+                noise_clip_factor,
+                ..Default::default()
+            })
+            .enhance_rgb_image(&mut pixels, width);
+            pixels
+        };
+        // Amplified noise shows up as differences between neighboring pixels.
+        let roughness = |pixels: &[u8]| {
+            pixels
+                .windows(4)
+                .map(|w| u32::from(w[0].abs_diff(w[3])))
+                .sum::<u32>()
+        };
+
+        // A clean gradient has no high-pass response.
+        let clean = image(0);
+        assert_eq!(enhance(&clean, 20.0), enhance(&clean, 0.0));
+
+        let noisy = image(1);
+        assert!(roughness(&enhance(&noisy, 20.0)) < roughness(&enhance(&noisy, 0.0)));
+    }
+
+    #[test]
+    fn reference_histogram_is_matched() {
+        let width = 64;
+        let image = |offset: usize| {
+            (0..width * 64)
+                .flat_map(|i| {
+                    let l = (offset + i % width + i / width) as u8;
+                    [l, l, l]
+                })
+                .collect::<Vec<_>>()
+        };
+        let global = || {
+            AutomaticClahe::with_options(AutomaticClaheOptions {
+                block_size: BlockSize::Tiles { x: 1, y: 1 },
+                ..Default::default()
+            })
+        };
+
+        // Matching an image to its own histogram keeps it.
+        let original = image(20);
+        let mut pixels = original.clone();
+        global()
+            .with_reference_image(&original, ChannelLayout::RGB)
+            .enhance_rgb_image(&mut pixels, width);
+        assert_eq!(pixels, original);
+
+        // Matching to a brighter image shifts the luminances up to it.
+        let bright = image(100);
+        global()
+            .with_reference_image(&bright, ChannelLayout::RGB)
+            .enhance_rgb_image(&mut pixels, width);
+        assert_eq!(pixels, bright);
+    }
+
+    #[test]
+    fn enhance_depth_map_skips_invalid_values() {
+        let width = 64;
+        let mut depth = (0..width * 48)
+            .map(|i| {
+                if i % 7 == 0 {
+                    0.0
+                } else {
+                    1.0 + (i % width) as f32 * 0.01 + (i / width) as f32 * 0.001
+                }
+            })
+            .collect::<Vec<_>>();
+        depth[5] = f32::NAN;
+        let original = depth.clone();
+
+        AutomaticClahe::new().enhance_depth_map(&mut depth, width, Some(0.0));
+        for (i, (&d, &o)) in depth.iter().zip(&original).enumerate() {
+            if i == 5 {
+                assert!(d.is_nan());
+            } else if o == 0.0 {
+                assert_eq!(d, 0.0);
+            } else {
+                assert!((1.0..=1.7).contains(&d), "d={}", d);
+            }
+        }
     }
 }
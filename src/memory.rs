@@ -0,0 +1,163 @@
+use crate::arena::block_dimensions;
+use crate::{
+    AutomaticClahe, AutomaticClaheOptions, Block, BlockSize, ColorSpace, ColumnSpan, Dither,
+    Interpolation,
+};
+use std::mem::size_of;
+
+impl AutomaticClahe {
+    /// Estimates the heap memory that [`enhance_image()`](Self::enhance_image) needs at its peak
+    /// for a `width x height` image besides the pixels: the luminance planes, the blocks (each
+    /// carrying a 256-entry `f32` LUT and two CDFs), the unquantized output luminances, the
+    /// planes of the gain smoothing and the interpolation and dithering buffers.
+    /// Memory-constrained callers (e.g. wasm or mobile) can use it to decide whether to downscale
+    /// the image first.
+    ///
+    /// `BlockSize::Auto` and quadtrees are counted with their smallest possible blocks; the
+    /// dehaze stage is not included. `options` must pass
+    /// [`AutomaticClaheOptions::validate()`].
+    pub fn estimated_memory_bytes(
+        width: usize,
+        height: usize,
+        options: &AutomaticClaheOptions,
+    ) -> usize {
+        if width == 0 || height == 0 {
+            return 0;
+        }
+        let pixels = width * height;
+        let planes = if options.color_space == ColorSpace::PerChannel {
+            3
+        } else {
+            1
+        };
+        // The planes are enhanced one after the other, so only one carries the analyzed copies
+        // and the output luminances at a time.
+        let copies = usize::from(options.log_luminance)
+            + usize::from(options.denoise_strength > 0.0)
+            + usize::from(options.point_source_protection.is_some());
+        let mut bytes = (planes + copies) * pixels + pixels * size_of::<f32>();
+        if options.point_source_protection.is_some() {
+            // The background levels and the two box-filtered planes they are computed from.
+            bytes += pixels * (size_of::<Option<u8>>() + 2 * size_of::<f32>());
+        }
+
+        let (block_width, block_height) = match options.block_size {
+            BlockSize::Auto => ((width / 8).max(1), (height / 8).max(1)),
+            _ => block_dimensions(width, height, options),
+        };
+        let scales = if options.block_scales.is_empty() {
+            &[1.0][..]
+        } else {
+            &options.block_scales[..]
+        };
+        for &scale in scales {
+            let scaled =
+                |size: usize, max: usize| ((size as f32 * scale).round() as usize).clamp(1, max);
+            let blocks =
+                (width / scaled(block_width, width)) * (height / scaled(block_height, height));
+            bytes += match &options.quadtree {
+                _ if options.interpolation == Interpolation::SlidingWindow => {
+                    pixels * size_of::<f32>()
+                }
+                // Every leaf and its entry in the candidates of the finest cells; leaves are at
+                // least 4x4 pixels unless the grid blocks already are smaller.
+                Some(split) => {
+                    let splits = 1usize
+                        .checked_shl(2 * split.max_depth)
+                        .unwrap_or(usize::MAX);
+                    let leaves = blocks.saturating_mul(splits).min((pixels / 16).max(blocks));
+                    let candidates = size_of::<Vec<usize>>() + 4 * size_of::<usize>();
+                    leaves * (size_of::<Block>() + candidates)
+                }
+                None => {
+                    let fixed = if options.fixed_point_luts {
+                        256 * size_of::<i32>()
+                    } else {
+                        0
+                    };
+                    blocks * (size_of::<Block>() + fixed)
+                }
+            };
+        }
+
+        if options.interpolation == Interpolation::Bilinear && options.quadtree.is_none() {
+            // The column spans and the float and fixed-point weights of the bilinear sweep.
+            bytes += width * (size_of::<ColumnSpan>() + size_of::<f32>() + size_of::<u32>());
+            bytes += height
+                * (size_of::<f32>()
+                    + size_of::<u32>()
+                    + size_of::<(Option<usize>, Option<usize>)>());
+        }
+        if options.gain_smoothing.is_some() {
+            // The guide, the gains, the eight intermediate planes and the output of the guided
+            // filter, and the `f64` integral image of its box means.
+            bytes += 11 * pixels * size_of::<f32>() + (width + 1) * (height + 1) * size_of::<f64>();
+        }
+        if options.dither == Dither::FloydSteinberg {
+            bytes += 2 * (width + 2) * size_of::<f32>();
+        }
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GuidedFilter, QuadtreeSplit};
+
+    #[test]
+    fn memory_estimates_grow_with_the_work() {
+        let estimate = |options: AutomaticClaheOptions| {
+            AutomaticClahe::estimated_memory_bytes(640, 480, &options)
+        };
+        let default = estimate(AutomaticClaheOptions::default());
+        let blocks = (640 / 32) * (480 / 32);
+        assert!(default >= 640 * 480 * 5 + blocks * (256 * 4 + 2 * 256 * 4));
+        assert!(default < 640 * 480 * 5 + blocks * 4096 + 640 * 64 + 480 * 64);
+
+        let larger = [
+            AutomaticClaheOptions {
+                color_space: ColorSpace::PerChannel,
+                ..Default::default()
+            },
+            AutomaticClaheOptions {
+                block_width: 8,
+                block_height: 8,
+                ..Default::default()
+            },
+            AutomaticClaheOptions {
+                block_scales: vec![1.0, 2.0],
+                ..Default::default()
+            },
+            AutomaticClaheOptions {
+                fixed_point_luts: true,
+                dither: Dither::FloydSteinberg,
+                ..Default::default()
+            },
+        ];
+        for options in larger {
+            assert!(estimate(options.clone()) > default, "{:?}", options);
+        }
+        let smoothed = estimate(AutomaticClaheOptions {
+            gain_smoothing: Some(GuidedFilter::default()),
+            ..Default::default()
+        });
+        assert!(smoothed >= default + 640 * 480 * 11 * 4);
+        // 32x32 blocks split three times already reach the 4x4 leaves.
+        let quadtree = |max_depth| {
+            estimate(AutomaticClaheOptions {
+                quadtree: Some(QuadtreeSplit {
+                    max_depth,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+        };
+        assert!(quadtree(1) > default);
+        assert_eq!(quadtree(16), quadtree(3));
+        assert_eq!(
+            AutomaticClahe::estimated_memory_bytes(0, 480, &Default::default()),
+            0
+        );
+    }
+}
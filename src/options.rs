@@ -0,0 +1,1553 @@
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct AutomaticClaheOptions {
+    pub block_size: BlockSize,
+
+    /// Block width in pixels (used with `BlockSize::Fixed`).
+    pub block_width: usize,
+
+    /// Block height in pixels (used with `BlockSize::Fixed`).
+    pub block_height: usize,
+
+    /// Block depth in voxels (used by `AutomaticClahe::enhance_volume()`).
+    pub block_depth: usize,
+
+    /// Fraction in `[0, 1)` by which the histogram windows of neighboring blocks overlap
+    /// (e.g. `0.5` doubles the window size); smooths block statistics and reduces halos at the
+    /// cost of `1 / (1 - overlap)^2` times more histogram work.
+    pub block_overlap: f32,
+
+    /// Block size multipliers of a multi-scale enhancement (e.g. `vec![1.0, 2.0, 4.0]`); the
+    /// results of all scales are averaged, which reduces both the halos of small blocks and the
+    /// flatness of large ones. Empty means a single scale of `1.0`.
+    pub block_scales: Vec<f32>,
+
+    /// Builds the histograms from every `n`-th pixel of every `n`-th row only, which cuts the
+    /// analysis cost by `n^2` (e.g. for interactive previews) while the LUTs are still applied
+    /// to every pixel. Blocks smaller than `n` may end up without samples and are left
+    /// unenhanced; ignored by `Interpolation::SlidingWindow`.
+    pub histogram_subsampling: usize,
+
+    /// Caps the number of pixels sampled into each block histogram: the histograms of larger
+    /// windows are built with the smallest subsampling step (at least `histogram_subsampling`)
+    /// that fits the budget, which keeps the analysis cost per block constant on high-resolution
+    /// images. A few thousand samples are indistinguishable from the full histogram in practice.
+    pub histogram_sample_budget: Option<usize>,
+
+    /// Recursively splits the blocks of the grid into quadrants where their content is detailed,
+    /// so that fine blocks are only used where needed. The leaves are blended with Gaussian
+    /// weights of their center distances (`Interpolation::Nearest` uses the containing leaf).
+    pub quadtree: Option<QuadtreeSplit>,
+    pub alpha: f32,
+    pub p: f32,
+
+    /// Divides the automatic clip point of each block by `1 + noise_clip_factor * noise / mean`,
+    /// where `noise` is the block's noise sigma (median absolute deviation of a high-pass), so
+    /// that noisy dark blocks are clipped harder (`0.0` disables it).
+    pub noise_clip_factor: f32,
+
+    /// Smooths the luminance with an edge-preserving bilateral filter before the analysis,
+    /// with a range sigma of `denoise_strength` times the estimated noise sigma
+    /// (`0.0` disables it, `1.0`-`3.0` suits noisy night shots).
+    pub denoise_strength: f32,
+
+    /// Analyzes and enhances `log2(1 + l)` (exposure stops) instead of the luminance, applying
+    /// the result as a gain, which suits extremely high-dynamic-range data such as
+    /// astrophotography.
+    pub log_luminance: bool,
+    pub d_threshold: u8,
+
+    /// Blocks whose luminance standard deviation is below this value are left unenhanced,
+    /// which prevents flat regions (sky, walls, ...) from turning into amplified noise.
+    pub min_sigma: f32,
+
+    pub gamma: GammaParams,
+
+    /// CDF point of the whole image that defines `l_alpha` in the enhancement weight factor
+    /// `l_max / l_alpha`; higher values lift dark images less.
+    pub l_alpha_percentile: f32,
+
+    /// If `false`, the dual gamma correction is never applied, regardless of `d_threshold`.
+    pub dual_gamma_correction: bool,
+
+    /// Fixed clip limit relative to the average bin height (like classic CLAHE's `clip_limit`).
+    ///
+    /// If `None`, the clip point is derived from `alpha` and `p` as described in the paper.
+    pub clip_limit: Option<f32>,
+
+    /// Limits the difference between the LUTs of horizontally or vertically adjacent grid blocks
+    /// to this many levels at every luminance, which suppresses halos and seams at high `alpha`
+    /// without smoothing the image (quadtree leaves are not constrained).
+    pub max_block_divergence: Option<f32>,
+
+    /// Blend factor between the original (`0.0`) and the fully enhanced (`1.0`) luminance.
+    pub strength: f32,
+
+    /// Scales the enhancement of every pixel by a spatial ramp, e.g. to only enhance the road
+    /// in dashcam footage or the center of endoscopy frames.
+    pub strength_gradient: Option<StrengthGradient>,
+
+    /// Strengths whose results are fused exposure-fusion style (e.g. `vec![0.3, 0.7, 1.0]`):
+    /// each pixel averages them weighted by their well-exposedness
+    /// `exp(-(l / 255 - 0.5)^2 / 0.08)`, which tames the blown-out or crushed results of a
+    /// single strength on high-contrast scenes. Empty means the single `strength`.
+    pub fusion_strengths: Vec<f32>,
+
+    /// Multiplies the saturation of the enhanced pixels (ignored with `ColorSpace::PerChannel`).
+    pub saturation_factor: f32,
+
+    /// Reduces the saturation of boosted dark pixels to hide their chroma noise: the saturation
+    /// is divided by `1 + chroma_noise_reduction * (gain - 1) * (1 - l / 255)`, where `l` is the
+    /// original luminance (`0.0` disables it).
+    pub chroma_noise_reduction: f32,
+
+    /// Pixels whose original luminance falls into one of these ranges are left untouched
+    /// (e.g. `vec![0..=2, 253..=255]` to keep pure black and white UI overlays intact).
+    pub protected_ranges: Vec<std::ops::RangeInclusive<u8>>,
+
+    /// Upper limit of the ratio between the enhanced and the original luminance of a pixel,
+    /// which keeps noise in dark flat regions from being amplified.
+    pub max_gain: Option<f32>,
+
+    /// Edge-aware smoothing of the gain map (enhanced over original luminance) before it is
+    /// applied, which suppresses the halos that block interpolation leaves around strong edges.
+    pub gain_smoothing: Option<GuidedFilter>,
+
+    /// Restricts the enhancement to brightening the shadows; midtones and highlights are left
+    /// untouched.
+    pub shadow_lift: Option<ShadowLift>,
+
+    /// Luminance above which brightening is compressed by a soft knee (slope 1 at the knee,
+    /// approaching 255 asymptotically), so that highlights and skies are not pushed into
+    /// clipping. Pixels are never made darker than their original luminance by the knee.
+    pub highlight_knee: Option<u8>,
+
+    /// Detects small bright point sources (e.g. stars), excludes them from the block statistics
+    /// and lifts them by the enhancement of their local background instead of their own, so that
+    /// star fields do not bloom (with `Interpolation::SlidingWindow` only the exclusion applies).
+    pub point_source_protection: Option<PointSourceProtection>,
+
+    /// Lowest output luminance (e.g. 16 for video-legal levels).
+    pub output_black: u8,
+
+    /// Highest output luminance (e.g. 235 for video-legal levels).
+    pub output_white: u8,
+
+    /// How the enhanced luminance is fit into `output_black..=output_white`.
+    pub clamp_mode: ClampMode,
+
+    /// Dithering applied when quantizing the enhanced luminance, to reduce banding in smooth
+    /// gradients.
+    pub dither: Dither,
+
+    /// How the LUTs of neighboring blocks are combined.
+    pub interpolation: Interpolation,
+
+    /// The color space whose luminance is enhanced in RGB images.
+    pub color_space: ColorSpace,
+
+    /// How the enhanced luminance is written back to RGB pixels.
+    pub write_back: WriteBack,
+
+    /// Uses identity LUTs for all blocks while still running the tiling, interpolation and
+    /// write-back stages (useful for measuring the overhead of that machinery).
+    pub identity_luts: bool,
+
+    /// Quantizes the block LUTs to `i32` fixed point (8 fractional bits) and blends them with
+    /// integer arithmetic, which suits targets with slow floats (wasm, embedded). The output
+    /// differs by at most one level; only the bilinear interpolation of a single block grid
+    /// without point-source protection has a fixed-point path.
+    pub fixed_point_luts: bool,
+}
+
+impl Default for AutomaticClaheOptions {
+    fn default() -> Self {
+        Self {
+            block_size: BlockSize::Fixed,
+            block_width: 32,
+            block_height: 32,
+            block_depth: 32,
+            block_overlap: 0.0,
+            block_scales: Vec::new(),
+            histogram_subsampling: 1,
+            histogram_sample_budget: None,
+            quadtree: None,
+            alpha: 100.0,
+            p: 1.5,
+            noise_clip_factor: 0.0,
+            denoise_strength: 0.0,
+            log_luminance: false,
+            d_threshold: 50,
+            min_sigma: 0.0,
+            gamma: GammaParams::default(),
+            l_alpha_percentile: 0.75,
+            dual_gamma_correction: true,
+            clip_limit: None,
+            max_block_divergence: None,
+            strength: 1.0,
+            strength_gradient: None,
+            fusion_strengths: Vec::new(),
+            saturation_factor: 1.0,
+            chroma_noise_reduction: 0.0,
+            protected_ranges: Vec::new(),
+            max_gain: None,
+            gain_smoothing: None,
+            shadow_lift: None,
+            highlight_knee: None,
+            point_source_protection: None,
+            output_black: 0,
+            output_white: 255,
+            clamp_mode: ClampMode::Clamp,
+            dither: Dither::None,
+            interpolation: Interpolation::Bilinear,
+            color_space: ColorSpace::HsvInteger,
+            write_back: WriteBack::ColorSpace,
+            identity_luts: false,
+            fixed_point_luts: false,
+        }
+    }
+}
+
+impl AutomaticClaheOptions {
+    pub fn preset(preset: Preset) -> Self {
+        let (block_size, alpha, p, d_threshold) = match preset {
+            Preset::Subtle => (64, 40.0, 1.0, 80),
+            Preset::Balanced => (32, 100.0, 1.5, 50),
+            Preset::Aggressive => (24, 200.0, 3.0, 30),
+            Preset::LowLight | Preset::ShadowLift => (48, 150.0, 4.0, 20),
+        };
+        Self {
+            block_width: block_size,
+            block_height: block_size,
+            alpha,
+            p,
+            d_threshold,
+            shadow_lift: (preset == Preset::ShadowLift).then(ShadowLift::default),
+            ..Default::default()
+        }
+    }
+
+    /// Upper limit of `block_width` and `block_height`.
+    pub const MAX_BLOCK_DIMENSION: usize = 1 << 16;
+
+    pub fn builder() -> AutomaticClaheOptionsBuilder {
+        AutomaticClaheOptionsBuilder::default()
+    }
+
+    pub(crate) fn is_protected(&self, l: u8) -> bool {
+        self.protected_ranges.iter().any(|r| r.contains(&l))
+    }
+
+    /// Returns the pixel with its working luminance changed to `l`.
+    pub(crate) fn with_luminance(&self, (r, g, b): (u8, u8, u8), l: u8) -> (u8, u8, u8) {
+        use crate::color_format::{scale_rgb_desaturating, scale_saturation};
+        let from = self.color_space.luminance(r, g, b);
+        let saturation_factor = self.saturation_factor(from, l);
+        match self.write_back {
+            WriteBack::ColorSpace => {
+                self.color_space
+                    .with_luminance((r, g, b), l, saturation_factor)
+            }
+            WriteBack::Ratio => {
+                let (r, g, b) = scale_rgb_desaturating(r, g, b, from, l);
+                if saturation_factor == 1.0 {
+                    (r, g, b)
+                } else {
+                    scale_saturation(r, g, b, l, saturation_factor)
+                }
+            }
+        }
+    }
+
+    // The saturation factor of a pixel whose luminance is changed from `from` to `to`.
+    fn saturation_factor(&self, from: u8, to: u8) -> f32 {
+        if self.chroma_noise_reduction == 0.0 || to <= from {
+            return self.saturation_factor;
+        }
+        let gain = f32::from(to) / f32::from(from).max(1.0);
+        let darkness = 1.0 - f32::from(from) / 255.0;
+        self.saturation_factor / (1.0 + self.chroma_noise_reduction * (gain - 1.0) * darkness)
+    }
+
+    pub fn validate(&self) -> Result<(), OptionsError> {
+        if self.block_width == 0 || self.block_height == 0 || self.block_depth == 0 {
+            return Err(OptionsError::ZeroBlockSize);
+        }
+        if self.block_width > Self::MAX_BLOCK_DIMENSION
+            || self.block_height > Self::MAX_BLOCK_DIMENSION
+        {
+            return Err(OptionsError::BlockSizeTooLarge {
+                width: self.block_width,
+                height: self.block_height,
+            });
+        }
+        if let BlockSize::Tiles { x, y } = self.block_size {
+            if x == 0 || y == 0 {
+                return Err(OptionsError::ZeroTiles { x, y });
+            }
+        }
+        if !(0.0..1.0).contains(&self.block_overlap) {
+            return Err(OptionsError::InvalidBlockOverlap(self.block_overlap));
+        }
+        if let Some(&scale) = self
+            .block_scales
+            .iter()
+            .find(|s| !(s.is_finite() && **s > 0.0))
+        {
+            return Err(OptionsError::InvalidBlockScale(scale));
+        }
+        if let Some(split) = self.quadtree {
+            if !(split.sigma_threshold.is_finite() && split.sigma_threshold >= 0.0)
+                || split.max_depth > MAX_QUADTREE_DEPTH
+            {
+                return Err(OptionsError::InvalidQuadtreeSplit(split));
+            }
+        }
+        if !(self.alpha.is_finite() && self.alpha >= 0.0) {
+            return Err(OptionsError::InvalidAlpha(self.alpha));
+        }
+        if !(self.p.is_finite() && self.p >= 0.0) {
+            return Err(OptionsError::InvalidP(self.p));
+        }
+        if self.histogram_subsampling == 0 {
+            return Err(OptionsError::ZeroHistogramSubsampling);
+        }
+        if self.histogram_sample_budget == Some(0) {
+            return Err(OptionsError::ZeroHistogramSampleBudget);
+        }
+        if !(self.noise_clip_factor.is_finite() && self.noise_clip_factor >= 0.0) {
+            return Err(OptionsError::InvalidNoiseClipFactor(self.noise_clip_factor));
+        }
+        if !(self.denoise_strength.is_finite() && self.denoise_strength >= 0.0) {
+            return Err(OptionsError::InvalidDenoiseStrength(self.denoise_strength));
+        }
+        if !(self.min_sigma.is_finite() && self.min_sigma >= 0.0) {
+            return Err(OptionsError::InvalidMinSigma(self.min_sigma));
+        }
+        let gamma = &self.gamma;
+        if !(gamma.gamma_1_divisor.is_finite()
+            && gamma.gamma_1_divisor != 0.0
+            && gamma.gamma_2_offset.is_finite()
+            && gamma.gamma_2_divisor.is_finite()
+            && gamma.gamma_2_divisor != 0.0)
+        {
+            return Err(OptionsError::InvalidGammaParams(*gamma));
+        }
+        if !(self.l_alpha_percentile > 0.0 && self.l_alpha_percentile <= 1.0) {
+            return Err(OptionsError::InvalidLAlphaPercentile(
+                self.l_alpha_percentile,
+            ));
+        }
+        if let Some(clip_limit) = self.clip_limit {
+            if !(clip_limit.is_finite() && clip_limit > 0.0) {
+                return Err(OptionsError::InvalidClipLimit(clip_limit));
+            }
+        }
+        if let Some(divergence) = self.max_block_divergence {
+            if !(divergence.is_finite() && divergence >= 0.0) {
+                return Err(OptionsError::InvalidBlockDivergence(divergence));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.strength) {
+            return Err(OptionsError::InvalidStrength(self.strength));
+        }
+        if let Some(gradient) = self.strength_gradient {
+            if !gradient.is_valid() {
+                return Err(OptionsError::InvalidStrengthGradient(gradient));
+            }
+        }
+        if let Some(&strength) = self
+            .fusion_strengths
+            .iter()
+            .find(|s| !(0.0..=1.0).contains(*s))
+        {
+            return Err(OptionsError::InvalidStrength(strength));
+        }
+        if !(self.saturation_factor.is_finite() && self.saturation_factor >= 0.0) {
+            return Err(OptionsError::InvalidSaturationFactor(
+                self.saturation_factor,
+            ));
+        }
+        if !(self.chroma_noise_reduction.is_finite() && self.chroma_noise_reduction >= 0.0) {
+            return Err(OptionsError::InvalidChromaNoiseReduction(
+                self.chroma_noise_reduction,
+            ));
+        }
+        if let Some(max_gain) = self.max_gain {
+            if !(max_gain.is_finite() && max_gain >= 1.0) {
+                return Err(OptionsError::InvalidMaxGain(max_gain));
+            }
+        }
+        if let Some(filter) = self.gain_smoothing {
+            if !(filter.epsilon.is_finite() && filter.epsilon > 0.0) {
+                return Err(OptionsError::InvalidGainSmoothing(filter));
+            }
+        }
+        if let Some(lift) = self.shadow_lift {
+            if lift.rolloff == 0 {
+                return Err(OptionsError::ZeroShadowRolloff);
+            }
+        }
+        if let Some(protection) = self.point_source_protection {
+            if protection.radius == 0 {
+                return Err(OptionsError::ZeroPointSourceRadius);
+            }
+        }
+        if self.output_black > self.output_white {
+            return Err(OptionsError::InvalidOutputRange {
+                black: self.output_black,
+                white: self.output_white,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Parameters of the gamma curves.
+///
+/// With `c` the (weighting) CDF value of a luminance, `gamma_1 = ln(c) / gamma_1_divisor` and
+/// `gamma_2 = (c + gamma_2_offset) / gamma_2_divisor`. The defaults are the paper's constants.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct GammaParams {
+    pub gamma_1_divisor: f32,
+    pub gamma_2_offset: f32,
+    pub gamma_2_divisor: f32,
+}
+
+impl Default for GammaParams {
+    fn default() -> Self {
+        Self {
+            gamma_1_divisor: 8.0,
+            gamma_2_offset: 1.0,
+            gamma_2_divisor: 2.0,
+        }
+    }
+}
+
+/// Parameters of a guided filter steered by the original luminance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct GuidedFilter {
+    /// Radius of the square filter window in pixels.
+    pub radius: usize,
+
+    /// Regularization relative to the squared normalized luminance; edges whose local variance
+    /// is well above it are preserved, weaker ones are smoothed over.
+    pub epsilon: f32,
+}
+
+impl Default for GuidedFilter {
+    fn default() -> Self {
+        Self {
+            radius: 8,
+            epsilon: 0.01,
+        }
+    }
+}
+
+/// The luminance range enhanced by the shadow-lift mode.
+///
+/// The enhancement is fully applied up to the original luminance `threshold` and faded out with a
+/// smoothstep over the following `rolloff` levels; enhancements that darken are discarded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ShadowLift {
+    pub threshold: u8,
+    pub rolloff: u8,
+}
+
+impl Default for ShadowLift {
+    fn default() -> Self {
+        Self {
+            threshold: 64,
+            rolloff: 64,
+        }
+    }
+}
+
+impl ShadowLift {
+    // The fraction of the enhancement applied to pixels of original luminance `l`.
+    pub(crate) fn weight(&self, l: u8) -> f32 {
+        let t =
+            ((f32::from(l) - f32::from(self.threshold)) / f32::from(self.rolloff)).clamp(0.0, 1.0);
+        1.0 - t * t * (3.0 - 2.0 * t)
+    }
+}
+
+/// A spatial ramp of the enhancement strength, in coordinates relative to the image size
+/// (`(0.0, 0.0)` is the top-left and `(1.0, 1.0)` the bottom-right corner). The ramps are
+/// eased with a smoothstep.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StrengthGradient {
+    /// Rises from no enhancement at `start` to the full enhancement at `end`, constant beyond
+    /// them (e.g. `start: (0.0, 0.4), end: (0.0, 0.6)` for the bottom half).
+    Linear { start: (f32, f32), end: (f32, f32) },
+    /// The full enhancement within `inner_radius` of `center`, faded out towards
+    /// `outer_radius` (the circles become ellipses on non-square images).
+    Radial {
+        center: (f32, f32),
+        inner_radius: f32,
+        outer_radius: f32,
+    },
+}
+
+impl StrengthGradient {
+    fn is_valid(&self) -> bool {
+        let finite = |(x, y): (f32, f32)| x.is_finite() && y.is_finite();
+        match *self {
+            Self::Linear { start, end } => finite(start) && finite(end) && start != end,
+            Self::Radial {
+                center,
+                inner_radius,
+                outer_radius,
+            } => {
+                finite(center)
+                    && inner_radius >= 0.0
+                    && outer_radius.is_finite()
+                    && outer_radius > inner_radius
+            }
+        }
+    }
+
+    // The fraction of the enhancement applied at the relative position `(x, y)`.
+    pub(crate) fn weight(&self, (x, y): (f32, f32)) -> f32 {
+        let t = match *self {
+            Self::Linear { start, end } => {
+                let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+                ((x - start.0) * dx + (y - start.1) * dy) / (dx * dx + dy * dy)
+            }
+            Self::Radial {
+                center,
+                inner_radius,
+                outer_radius,
+            } => {
+                let distance = (x - center.0).hypot(y - center.1);
+                1.0 - (distance - inner_radius) / (outer_radius - inner_radius)
+            }
+        }
+        .clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl std::str::FromStr for StrengthGradient {
+    type Err = String;
+
+    // `linear:x0,y0,x1,y1` or `radial:x,y,inner_radius,outer_radius`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("invalid strength gradient: {:?}", s);
+        let (kind, values) = s.split_once(':').ok_or_else(error)?;
+        let values = values
+            .split(',')
+            .map(|v| v.trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| error())?;
+        let &[a, b, c, d] = values.as_slice() else {
+            return Err(error());
+        };
+        match kind.to_ascii_lowercase().as_str() {
+            "linear" => Ok(Self::Linear {
+                start: (a, b),
+                end: (c, d),
+            }),
+            "radial" => Ok(Self::Radial {
+                center: (a, b),
+                inner_radius: c,
+                outer_radius: d,
+            }),
+            _ => Err(error()),
+        }
+    }
+}
+
+/// Detection parameters of the point-source protection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct PointSourceProtection {
+    /// Luminance by which a point source exceeds the mean of its surrounding window.
+    pub prominence: u8,
+
+    /// Radius of the window in pixels; sources must be well below this size.
+    pub radius: usize,
+}
+
+impl Default for PointSourceProtection {
+    fn default() -> Self {
+        Self {
+            prominence: 40,
+            radius: 4,
+        }
+    }
+}
+
+/// When a block of the quadtree layout is split.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct QuadtreeSplit {
+    /// Blocks whose luminance standard deviation exceeds this value are split.
+    pub sigma_threshold: f32,
+
+    /// Maximum number of times a block of the grid is split (at most 16).
+    pub max_depth: u32,
+}
+
+// The largest valid `QuadtreeSplit::max_depth`; deeper splits would only create leaves below
+// the minimum block dimension anyway.
+pub(crate) const MAX_QUADTREE_DEPTH: u32 = 16;
+
+impl Default for QuadtreeSplit {
+    fn default() -> Self {
+        Self {
+            sigma_threshold: 32.0,
+            max_depth: 2,
+        }
+    }
+}
+
+/// How the block dimensions are chosen.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BlockSize {
+    /// Uses `block_width` x `block_height` pixel blocks.
+    #[default]
+    Fixed,
+    /// Picks the block dimensions from the image resolution (about 8x8 blocks per image,
+    /// enlarged to cover the dominant structures of the image).
+    Auto,
+    /// Splits the image into an `x` by `y` grid of blocks, like OpenCV's `tileGridSize`.
+    ///
+    /// The remainder pixels are added to the last column and row of blocks.
+    Tiles { x: usize, y: usize },
+}
+
+/// How out-of-range luminances are handled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ClampMode {
+    /// Clips the enhanced luminance to the output range.
+    #[default]
+    Clamp,
+    /// Linearly maps the full `0..=255` range onto the output range.
+    Scale,
+}
+
+/// Interpolation between the LUTs of neighboring blocks.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Interpolation {
+    /// Uses the LUT of the block containing the pixel (useful to inspect single blocks).
+    Nearest,
+    /// Bilinear interpolation between the four surrounding block centers.
+    #[default]
+    Bilinear,
+    /// Gaussian-weighted average of the 3x3 surrounding blocks; smoother tile seams.
+    Gaussian,
+    /// No interpolation: the LUT of every pixel is derived from the block-sized window centered
+    /// on it. Slow but exact, e.g. as a quality reference or for offline processing.
+    SlidingWindow,
+}
+
+impl std::str::FromStr for Interpolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "nearest" => Ok(Self::Nearest),
+            "bilinear" => Ok(Self::Bilinear),
+            "gaussian" => Ok(Self::Gaussian),
+            "sliding-window" => Ok(Self::SlidingWindow),
+            _ => Err(format!("unknown interpolation: {:?}", s)),
+        }
+    }
+}
+
+/// Quantization of the enhanced luminance.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Dither {
+    /// Truncates the fractional part.
+    #[default]
+    None,
+    /// 4x4 Bayer matrix.
+    Ordered,
+    /// Floyd-Steinberg error diffusion.
+    FloydSteinberg,
+}
+
+impl std::str::FromStr for Dither {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "ordered" | "bayer" => Ok(Self::Ordered),
+            "floyd-steinberg" | "floydsteinberg" | "error-diffusion" => Ok(Self::FloydSteinberg),
+            _ => Err(format!("unknown dither: {:?}", s)),
+        }
+    }
+}
+
+/// The color space in which RGB pixels are enhanced.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ColorSpace {
+    /// The HSV value `max(R, G, B)` with the integer HSV round trip of the paper.
+    #[default]
+    HsvInteger,
+    /// The HSV value with an `f32` round trip, which avoids the color shifts (up to 6 levels per
+    /// channel) of the integer conversion.
+    HsvFloat,
+    /// The HSL lightness `(max(R, G, B) + min(R, G, B)) / 2`; saturated colors are less prone to
+    /// blowing out than with the HSV value.
+    Hsl,
+    /// BT.709 YCbCr: Y is enhanced and Cb/Cr are kept (scaled by `saturation_factor`).
+    YCbCrBt709,
+    /// BT.601 YCbCr: Y is enhanced and Cb/Cr are kept (scaled by `saturation_factor`).
+    YCbCrBt601,
+    /// CIELAB `L*` (scaled to `[0, 255]`); preserves the perceived hue and chroma best.
+    Lab,
+    /// Oklab `L` (scaled to `[0, 255]`); keeps hues stable even for large luminance shifts.
+    Oklab,
+    /// BT.709 luma (`0.2126 R + 0.7152 G + 0.0722 B`), applied by scaling the channels.
+    Bt709,
+    /// BT.601 luma (`0.299 R + 0.587 G + 0.114 B`), applied by scaling the channels.
+    Bt601,
+    /// `(R + G + B) / 3`, applied by scaling the channels.
+    Average,
+    /// Enhances the R, G and B channels independently.
+    PerChannel,
+}
+
+impl ColorSpace {
+    /// Returns the working luminance of a pixel (its HSV value with `PerChannel`).
+    pub(crate) fn luminance(self, r: u8, g: u8, b: u8) -> u8 {
+        use crate::color_format::{
+            rgb_to_hsl, rgb_to_lab, rgb_to_luma, rgb_to_oklab, BT601, BT709,
+        };
+        match self {
+            Self::HsvInteger | Self::HsvFloat | Self::PerChannel => {
+                std::cmp::max(r, std::cmp::max(g, b))
+            }
+            Self::Bt709 | Self::YCbCrBt709 => rgb_to_luma(r, g, b, BT709),
+            Self::Bt601 | Self::YCbCrBt601 => rgb_to_luma(r, g, b, BT601),
+            Self::Average => rgb_to_luma(r, g, b, (1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0)),
+            Self::Hsl => rgb_to_hsl(r, g, b).2.round() as u8,
+            Self::Lab => (rgb_to_lab(r, g, b).0 * 2.55).round() as u8,
+            Self::Oklab => (rgb_to_oklab(r, g, b).0 * 255.0).round().min(255.0) as u8,
+        }
+    }
+
+    /// Returns the pixel with its luminance changed to `l`, keeping its hue, and its saturation
+    /// multiplied by `saturation_factor`.
+    pub(crate) fn with_luminance(
+        self,
+        (r, g, b): (u8, u8, u8),
+        l: u8,
+        saturation_factor: f32,
+    ) -> (u8, u8, u8) {
+        use crate::color_format::{
+            hsl_to_rgb, hsv_f32_to_rgb, hsv_to_rgb, lab_to_gray, lab_to_rgb, oklab_to_gray,
+            oklab_to_rgb, rgb_to_hsl, rgb_to_hsv, rgb_to_hsv_f32, rgb_to_lab, rgb_to_oklab,
+            scale_rgb, scale_saturation, ycbcr_with_luma, BT601, BT709,
+        };
+        // Neutral pixels must stay exactly neutral, whatever residual chroma the float
+        // conversions produce.
+        let gray = r == g && g == b;
+        match self {
+            Self::Lab => {
+                // Shifts the unquantized `L*` so that unchanged luminances keep the exact color.
+                let (l_star, a, b) = rgb_to_lab(r, g, b);
+                let l_star = l_star + (f32::from(l) - (l_star * 2.55).round()) / 2.55;
+                if gray {
+                    let v = lab_to_gray(l_star);
+                    (v, v, v)
+                } else {
+                    lab_to_rgb(l_star, a * saturation_factor, b * saturation_factor)
+                }
+            }
+            Self::Oklab => {
+                let (l_ok, a, b) = rgb_to_oklab(r, g, b);
+                let l_ok = l_ok + (f32::from(l) - (l_ok * 255.0).round()) / 255.0;
+                if gray {
+                    let v = oklab_to_gray(l_ok);
+                    (v, v, v)
+                } else {
+                    oklab_to_rgb(l_ok, a * saturation_factor, b * saturation_factor)
+                }
+            }
+            _ if gray => (l, l, l),
+            Self::Hsl => {
+                let (h, s, lightness) = rgb_to_hsl(r, g, b);
+                let lightness = (lightness + f32::from(l) - lightness.round()).clamp(0.0, 255.0);
+                hsl_to_rgb(h, (s * saturation_factor).min(1.0), lightness)
+            }
+            Self::HsvFloat => {
+                let (h, s, _) = rgb_to_hsv_f32(r, g, b);
+                hsv_f32_to_rgb(h, (s * saturation_factor).min(1.0), f32::from(l))
+            }
+            Self::HsvInteger | Self::PerChannel => {
+                let (h, s, _) = rgb_to_hsv(r, g, b);
+                let s = (f32::from(s) * saturation_factor).round().min(255.0) as u8;
+                hsv_to_rgb(h, s, l)
+            }
+            Self::YCbCrBt709 => ycbcr_with_luma(r, g, b, BT709, l, saturation_factor),
+            Self::YCbCrBt601 => ycbcr_with_luma(r, g, b, BT601, l, saturation_factor),
+            Self::Bt709 | Self::Bt601 | Self::Average => {
+                let (r, g, b) = scale_rgb(r, g, b, self.luminance(r, g, b), l);
+                if saturation_factor == 1.0 {
+                    (r, g, b)
+                } else {
+                    scale_saturation(r, g, b, self.luminance(r, g, b), saturation_factor)
+                }
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for ColorSpace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hsv" | "hsv-integer" => Ok(Self::HsvInteger),
+            "hsv-float" => Ok(Self::HsvFloat),
+            "hsl" => Ok(Self::Hsl),
+            "ycbcr" | "ycbcr709" => Ok(Self::YCbCrBt709),
+            "ycbcr601" => Ok(Self::YCbCrBt601),
+            "lab" | "cielab" => Ok(Self::Lab),
+            "oklab" => Ok(Self::Oklab),
+            "bt709" | "bt.709" => Ok(Self::Bt709),
+            "bt601" | "bt.601" => Ok(Self::Bt601),
+            "average" => Ok(Self::Average),
+            "per-channel" | "rgb" => Ok(Self::PerChannel),
+            _ => Err(format!("unknown color space: {:?}", s)),
+        }
+    }
+}
+
+/// Conversion of the enhanced luminance back to RGB.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WriteBack {
+    /// Round trip through the working color space.
+    #[default]
+    ColorSpace,
+    /// Multiplies R, G and B by `new_l / old_l`, which keeps the hue exactly; channels that
+    /// would overflow are desaturated towards white instead of being clipped.
+    Ratio,
+}
+
+impl std::str::FromStr for WriteBack {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "color-space" => Ok(Self::ColorSpace),
+            "ratio" => Ok(Self::Ratio),
+            _ => Err(format!("unknown write-back mode: {:?}", s)),
+        }
+    }
+}
+
+/// Tuned option sets for common scenarios.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Preset {
+    /// Large blocks and a low clip limit; only gently adds local contrast.
+    Subtle,
+    /// The parameters recommended by the paper (same as `AutomaticClaheOptions::default()`).
+    Balanced,
+    /// Small blocks and a high clip limit for flat, low-contrast images.
+    Aggressive,
+    /// Favors the dual gamma correction to lift dark regions of underexposed images.
+    LowLight,
+    /// Like `LowLight`, but only brightens the shadows (see [`ShadowLift`]).
+    ShadowLift,
+}
+
+impl std::str::FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "subtle" => Ok(Self::Subtle),
+            "balanced" => Ok(Self::Balanced),
+            "aggressive" => Ok(Self::Aggressive),
+            "lowlight" | "low-light" | "low_light" => Ok(Self::LowLight),
+            "shadowlift" | "shadow-lift" | "shadow_lift" => Ok(Self::ShadowLift),
+            _ => Err(format!("unknown preset: {:?}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct AutomaticClaheOptionsBuilder {
+    options: AutomaticClaheOptions,
+}
+
+impl AutomaticClaheOptionsBuilder {
+    /// Resets all options to `options`.
+    pub fn options(mut self, options: AutomaticClaheOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Resets all options to the values of `preset`.
+    pub fn preset(mut self, preset: Preset) -> Self {
+        self.options = AutomaticClaheOptions::preset(preset);
+        self
+    }
+
+    pub fn block_size(mut self, block_size: BlockSize) -> Self {
+        self.options.block_size = block_size;
+        self
+    }
+
+    pub fn block_width(mut self, block_width: usize) -> Self {
+        self.options.block_width = block_width;
+        self
+    }
+
+    pub fn block_height(mut self, block_height: usize) -> Self {
+        self.options.block_height = block_height;
+        self
+    }
+
+    pub fn block_depth(mut self, block_depth: usize) -> Self {
+        self.options.block_depth = block_depth;
+        self
+    }
+
+    pub fn block_overlap(mut self, block_overlap: f32) -> Self {
+        self.options.block_overlap = block_overlap;
+        self
+    }
+
+    pub fn block_scales(mut self, block_scales: Vec<f32>) -> Self {
+        self.options.block_scales = block_scales;
+        self
+    }
+
+    pub fn quadtree(mut self, split: QuadtreeSplit) -> Self {
+        self.options.quadtree = Some(split);
+        self
+    }
+
+    pub fn alpha(mut self, alpha: f32) -> Self {
+        self.options.alpha = alpha;
+        self
+    }
+
+    pub fn p(mut self, p: f32) -> Self {
+        self.options.p = p;
+        self
+    }
+
+    pub fn histogram_subsampling(mut self, histogram_subsampling: usize) -> Self {
+        self.options.histogram_subsampling = histogram_subsampling;
+        self
+    }
+
+    pub fn histogram_sample_budget(mut self, histogram_sample_budget: usize) -> Self {
+        self.options.histogram_sample_budget = Some(histogram_sample_budget);
+        self
+    }
+
+    pub fn noise_clip_factor(mut self, noise_clip_factor: f32) -> Self {
+        self.options.noise_clip_factor = noise_clip_factor;
+        self
+    }
+
+    pub fn denoise_strength(mut self, denoise_strength: f32) -> Self {
+        self.options.denoise_strength = denoise_strength;
+        self
+    }
+
+    pub fn log_luminance(mut self, log_luminance: bool) -> Self {
+        self.options.log_luminance = log_luminance;
+        self
+    }
+
+    pub fn d_threshold(mut self, d_threshold: u8) -> Self {
+        self.options.d_threshold = d_threshold;
+        self
+    }
+
+    pub fn min_sigma(mut self, min_sigma: f32) -> Self {
+        self.options.min_sigma = min_sigma;
+        self
+    }
+
+    pub fn gamma(mut self, gamma: GammaParams) -> Self {
+        self.options.gamma = gamma;
+        self
+    }
+
+    pub fn l_alpha_percentile(mut self, l_alpha_percentile: f32) -> Self {
+        self.options.l_alpha_percentile = l_alpha_percentile;
+        self
+    }
+
+    pub fn dual_gamma_correction(mut self, dual_gamma_correction: bool) -> Self {
+        self.options.dual_gamma_correction = dual_gamma_correction;
+        self
+    }
+
+    pub fn clip_limit(mut self, clip_limit: f32) -> Self {
+        self.options.clip_limit = Some(clip_limit);
+        self
+    }
+
+    pub fn max_block_divergence(mut self, max_block_divergence: f32) -> Self {
+        self.options.max_block_divergence = Some(max_block_divergence);
+        self
+    }
+
+    pub fn strength(mut self, strength: f32) -> Self {
+        self.options.strength = strength;
+        self
+    }
+
+    pub fn strength_gradient(mut self, strength_gradient: StrengthGradient) -> Self {
+        self.options.strength_gradient = Some(strength_gradient);
+        self
+    }
+
+    pub fn fusion_strengths(mut self, fusion_strengths: Vec<f32>) -> Self {
+        self.options.fusion_strengths = fusion_strengths;
+        self
+    }
+
+    pub fn saturation_factor(mut self, saturation_factor: f32) -> Self {
+        self.options.saturation_factor = saturation_factor;
+        self
+    }
+
+    pub fn chroma_noise_reduction(mut self, chroma_noise_reduction: f32) -> Self {
+        self.options.chroma_noise_reduction = chroma_noise_reduction;
+        self
+    }
+
+    pub fn protected_range(mut self, range: std::ops::RangeInclusive<u8>) -> Self {
+        self.options.protected_ranges.push(range);
+        self
+    }
+
+    pub fn max_gain(mut self, max_gain: f32) -> Self {
+        self.options.max_gain = Some(max_gain);
+        self
+    }
+
+    pub fn gain_smoothing(mut self, filter: GuidedFilter) -> Self {
+        self.options.gain_smoothing = Some(filter);
+        self
+    }
+
+    pub fn shadow_lift(mut self, shadow_lift: ShadowLift) -> Self {
+        self.options.shadow_lift = Some(shadow_lift);
+        self
+    }
+
+    pub fn highlight_knee(mut self, highlight_knee: u8) -> Self {
+        self.options.highlight_knee = Some(highlight_knee);
+        self
+    }
+
+    pub fn point_source_protection(mut self, protection: PointSourceProtection) -> Self {
+        self.options.point_source_protection = Some(protection);
+        self
+    }
+
+    pub fn output_range(mut self, black: u8, white: u8) -> Self {
+        self.options.output_black = black;
+        self.options.output_white = white;
+        self
+    }
+
+    pub fn clamp_mode(mut self, clamp_mode: ClampMode) -> Self {
+        self.options.clamp_mode = clamp_mode;
+        self
+    }
+
+    pub fn dither(mut self, dither: Dither) -> Self {
+        self.options.dither = dither;
+        self
+    }
+
+    pub fn interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.options.interpolation = interpolation;
+        self
+    }
+
+    pub fn color_space(mut self, color_space: ColorSpace) -> Self {
+        self.options.color_space = color_space;
+        self
+    }
+
+    pub fn write_back(mut self, write_back: WriteBack) -> Self {
+        self.options.write_back = write_back;
+        self
+    }
+
+    pub fn identity_luts(mut self, identity_luts: bool) -> Self {
+        self.options.identity_luts = identity_luts;
+        self
+    }
+
+    pub fn fixed_point_luts(mut self, fixed_point_luts: bool) -> Self {
+        self.options.fixed_point_luts = fixed_point_luts;
+        self
+    }
+
+    pub fn build(self) -> Result<AutomaticClaheOptions, OptionsError> {
+        self.options.validate()?;
+        Ok(self.options)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OptionsError {
+    ZeroBlockSize,
+    BlockSizeTooLarge { width: usize, height: usize },
+    ZeroTiles { x: usize, y: usize },
+    InvalidBlockOverlap(f32),
+    InvalidBlockScale(f32),
+    InvalidQuadtreeSplit(QuadtreeSplit),
+    InvalidAlpha(f32),
+    InvalidP(f32),
+    InvalidNoiseClipFactor(f32),
+    InvalidDenoiseStrength(f32),
+    InvalidMinSigma(f32),
+    InvalidGammaParams(GammaParams),
+    InvalidLAlphaPercentile(f32),
+    InvalidClipLimit(f32),
+    InvalidBlockDivergence(f32),
+    InvalidStrength(f32),
+    InvalidStrengthGradient(StrengthGradient),
+    InvalidSaturationFactor(f32),
+    InvalidChromaNoiseReduction(f32),
+    InvalidMaxGain(f32),
+    InvalidGainSmoothing(GuidedFilter),
+    ZeroShadowRolloff,
+    ZeroHistogramSubsampling,
+    ZeroHistogramSampleBudget,
+    ZeroPointSourceRadius,
+    InvalidRetinexSigma(f32),
+    InvalidRetinexClipPercentile(f32),
+    InvalidOutputRange { black: u8, white: u8 },
+    InvalidDehazeOmega(f32),
+    InvalidDehazeTransmission(f32),
+    InvalidTemporalSmoothing(f32),
+    InvalidSceneChangeThreshold(f32),
+    InvalidMaxLutDelta(f32),
+}
+
+impl std::fmt::Display for OptionsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ZeroBlockSize => write!(f, "block dimensions must be non-zero"),
+            Self::BlockSizeTooLarge { width, height } => write!(
+                f,
+                "block size {}x{} exceeds the limit of {}",
+                width,
+                height,
+                AutomaticClaheOptions::MAX_BLOCK_DIMENSION
+            ),
+            Self::ZeroTiles { x, y } => {
+                write!(f, "tile grid {}x{} must have at least one tile", x, y)
+            }
+            Self::InvalidAlpha(alpha) => {
+                write!(f, "alpha must be finite and non-negative: {}", alpha)
+            }
+            Self::InvalidBlockOverlap(overlap) => {
+                write!(f, "block overlap must be in [0.0, 1.0): {}", overlap)
+            }
+            Self::InvalidBlockScale(scale) => {
+                write!(f, "block scales must be finite and positive: {}", scale)
+            }
+            Self::InvalidQuadtreeSplit(split) => write!(
+                f,
+                "invalid quadtree split (sigma threshold {}, depth {}): the threshold must be \
+                 finite and non-negative and the depth at most {}",
+                split.sigma_threshold, split.max_depth, MAX_QUADTREE_DEPTH
+            ),
+            Self::InvalidP(p) => write!(f, "p must be finite and non-negative: {}", p),
+            Self::InvalidNoiseClipFactor(factor) => write!(
+                f,
+                "noise clip factor must be finite and non-negative: {}",
+                factor
+            ),
+            Self::InvalidDenoiseStrength(strength) => write!(
+                f,
+                "denoise strength must be finite and non-negative: {}",
+                strength
+            ),
+            Self::InvalidMinSigma(min_sigma) => {
+                write!(
+                    f,
+                    "min sigma must be finite and non-negative: {}",
+                    min_sigma
+                )
+            }
+            Self::InvalidGammaParams(gamma) => write!(
+                f,
+                "gamma parameters must be finite with non-zero divisors: {:?}",
+                gamma
+            ),
+            Self::InvalidLAlphaPercentile(percentile) => {
+                write!(
+                    f,
+                    "l_alpha percentile must be in (0.0, 1.0]: {}",
+                    percentile
+                )
+            }
+            Self::InvalidClipLimit(clip_limit) => {
+                write!(f, "clip limit must be finite and positive: {}", clip_limit)
+            }
+            Self::InvalidBlockDivergence(divergence) => {
+                write!(
+                    f,
+                    "block divergence must be finite and non-negative: {}",
+                    divergence
+                )
+            }
+            Self::InvalidStrength(strength) => {
+                write!(f, "strength must be between 0.0 and 1.0: {}", strength)
+            }
+            Self::InvalidStrengthGradient(gradient) => {
+                write!(f, "invalid strength gradient: {:?}", gradient)
+            }
+            Self::InvalidSaturationFactor(factor) => write!(
+                f,
+                "saturation factor must be finite and non-negative: {}",
+                factor
+            ),
+            Self::InvalidChromaNoiseReduction(v) => write!(
+                f,
+                "chroma noise reduction must be finite and non-negative: {}",
+                v
+            ),
+            Self::InvalidMaxGain(max_gain) => {
+                write!(f, "max gain must be finite and at least 1.0: {}", max_gain)
+            }
+            Self::InvalidGainSmoothing(filter) => write!(
+                f,
+                "gain smoothing epsilon must be finite and positive: {}",
+                filter.epsilon
+            ),
+            Self::ZeroShadowRolloff => write!(f, "shadow lift rolloff must be non-zero"),
+            Self::ZeroHistogramSubsampling => write!(f, "histogram subsampling must be non-zero"),
+            Self::ZeroHistogramSampleBudget => {
+                write!(f, "histogram sample budget must be non-zero")
+            }
+            Self::ZeroPointSourceRadius => {
+                write!(f, "point source protection radius must be non-zero")
+            }
+            Self::InvalidRetinexSigma(sigma) => {
+                write!(f, "retinex sigmas must be finite and positive: {}", sigma)
+            }
+            Self::InvalidRetinexClipPercentile(percentile) => write!(
+                f,
+                "retinex clip percentile must be in [0.0, 0.5): {}",
+                percentile
+            ),
+            Self::InvalidOutputRange { black, white } => write!(
+                f,
+                "output black point {} is greater than the white point {}",
+                black, white
+            ),
+            Self::InvalidDehazeOmega(omega) => {
+                write!(f, "dehaze omega must be in (0.0, 1.0]: {}", omega)
+            }
+            Self::InvalidDehazeTransmission(transmission) => write!(
+                f,
+                "dehaze minimum transmission must be in (0.0, 1.0]: {}",
+                transmission
+            ),
+            Self::InvalidTemporalSmoothing(smoothing) => {
+                write!(f, "temporal smoothing must be in [0.0, 1.0): {}", smoothing)
+            }
+            Self::InvalidSceneChangeThreshold(threshold) => write!(
+                f,
+                "scene change threshold must be in (0.0, 1.0]: {}",
+                threshold
+            ),
+            Self::InvalidMaxLutDelta(delta) => {
+                write!(f, "max LUT delta must be finite and positive: {}", delta)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OptionsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_works() {
+        let options: AutomaticClaheOptions =
+            serde_json::from_str(r#"{"block_width": 64, "alpha": 50.0}"#).expect("valid json");
+        assert_eq!(options.block_width, 64);
+        assert_eq!(options.block_height, 32);
+        assert_eq!(options.alpha, 50.0);
+
+        let json = serde_json::to_string(&options).expect("serializable");
+        let decoded: AutomaticClaheOptions = serde_json::from_str(&json).expect("valid json");
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", options));
+    }
+
+    #[test]
+    fn presets_are_valid() {
+        for preset in [
+            Preset::Subtle,
+            Preset::Balanced,
+            Preset::Aggressive,
+            Preset::LowLight,
+            Preset::ShadowLift,
+        ] {
+            assert!(AutomaticClaheOptions::preset(preset).validate().is_ok());
+            assert_eq!(format!("{:?}", preset).parse(), Ok(preset));
+        }
+    }
+
+    #[test]
+    fn grays_stay_gray() {
+        let color_spaces = [
+            ColorSpace::HsvInteger,
+            ColorSpace::HsvFloat,
+            ColorSpace::Hsl,
+            ColorSpace::YCbCrBt709,
+            ColorSpace::YCbCrBt601,
+            ColorSpace::Lab,
+            ColorSpace::Oklab,
+            ColorSpace::Bt709,
+            ColorSpace::Bt601,
+            ColorSpace::Average,
+            ColorSpace::PerChannel,
+        ];
+        for color_space in color_spaces {
+            for write_back in [WriteBack::ColorSpace, WriteBack::Ratio] {
+                let options = AutomaticClaheOptions {
+                    color_space,
+                    write_back,
+                    saturation_factor: 1.5,
+                    ..Default::default()
+                };
+                for v in 0..=255 {
+                    for l in 0..=255 {
+                        let (r, g, b) = options.with_luminance((v, v, v), l);
+                        assert!(
+                            r == g && g == b,
+                            "{:?}, {:?}: {} -> {}",
+                            color_space,
+                            write_back,
+                            v,
+                            l
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn builder_validates_options() {
+        assert!(AutomaticClaheOptions::builder().build().is_ok());
+
+        let options = AutomaticClaheOptions::builder()
+            .block_width(64)
+            .block_height(48)
+            .alpha(50.0)
+            .build()
+            .expect("valid options");
+        assert_eq!((options.block_width, options.block_height), (64, 48));
+
+        assert_eq!(
+            AutomaticClaheOptions::builder()
+                .block_width(0)
+                .build()
+                .err(),
+            Some(OptionsError::ZeroBlockSize)
+        );
+        assert!(matches!(
+            AutomaticClaheOptions::builder()
+                .block_height(1 << 20)
+                .build(),
+            Err(OptionsError::BlockSizeTooLarge { .. })
+        ));
+        assert_eq!(
+            AutomaticClaheOptions::builder()
+                .block_size(BlockSize::Tiles { x: 8, y: 0 })
+                .build()
+                .err(),
+            Some(OptionsError::ZeroTiles { x: 8, y: 0 })
+        );
+        assert!(matches!(
+            AutomaticClaheOptions::builder().alpha(f32::NAN).build(),
+            Err(OptionsError::InvalidAlpha(_))
+        ));
+        assert!(matches!(
+            AutomaticClaheOptions::builder().p(f32::INFINITY).build(),
+            Err(OptionsError::InvalidP(_))
+        ));
+        assert!(matches!(
+            AutomaticClaheOptions::builder()
+                .noise_clip_factor(-1.0)
+                .build(),
+            Err(OptionsError::InvalidNoiseClipFactor(_))
+        ));
+        assert!(matches!(
+            AutomaticClaheOptions::builder()
+                .denoise_strength(f32::NAN)
+                .build(),
+            Err(OptionsError::InvalidDenoiseStrength(_))
+        ));
+        assert!(matches!(
+            AutomaticClaheOptions::builder().block_overlap(1.0).build(),
+            Err(OptionsError::InvalidBlockOverlap(_))
+        ));
+        assert!(matches!(
+            AutomaticClaheOptions::builder()
+                .block_scales(vec![1.0, 0.0])
+                .build(),
+            Err(OptionsError::InvalidBlockScale(_))
+        ));
+        assert!(matches!(
+            AutomaticClaheOptions::builder()
+                .quadtree(QuadtreeSplit {
+                    sigma_threshold: f32::NAN,
+                    max_depth: 2,
+                })
+                .build(),
+            Err(OptionsError::InvalidQuadtreeSplit(_))
+        ));
+        assert!(matches!(
+            AutomaticClaheOptions::builder()
+                .quadtree(QuadtreeSplit {
+                    sigma_threshold: 32.0,
+                    max_depth: 70,
+                })
+                .build(),
+            Err(OptionsError::InvalidQuadtreeSplit(_))
+        ));
+        assert!(matches!(
+            AutomaticClaheOptions::builder()
+                .fusion_strengths(vec![0.5, 1.5])
+                .build(),
+            Err(OptionsError::InvalidStrength(_))
+        ));
+        assert!(matches!(
+            AutomaticClaheOptions::builder().min_sigma(-1.0).build(),
+            Err(OptionsError::InvalidMinSigma(_))
+        ));
+        assert!(matches!(
+            AutomaticClaheOptions::builder()
+                .gamma(GammaParams {
+                    gamma_1_divisor: 0.0,
+                    ..Default::default()
+                })
+                .build(),
+            Err(OptionsError::InvalidGammaParams(_))
+        ));
+        assert!(matches!(
+            AutomaticClaheOptions::builder()
+                .l_alpha_percentile(0.0)
+                .build(),
+            Err(OptionsError::InvalidLAlphaPercentile(_))
+        ));
+        assert!(matches!(
+            AutomaticClaheOptions::builder().clip_limit(0.0).build(),
+            Err(OptionsError::InvalidClipLimit(_))
+        ));
+        assert!(matches!(
+            AutomaticClaheOptions::builder()
+                .max_block_divergence(-1.0)
+                .build(),
+            Err(OptionsError::InvalidBlockDivergence(_))
+        ));
+        assert!(matches!(
+            AutomaticClaheOptions::builder().strength(1.5).build(),
+            Err(OptionsError::InvalidStrength(_))
+        ));
+        assert!(matches!(
+            AutomaticClaheOptions::builder()
+                .strength_gradient(StrengthGradient::Radial {
+                    center: (0.5, 0.5),
+                    inner_radius: 0.4,
+                    outer_radius: 0.2,
+                })
+                .build(),
+            Err(OptionsError::InvalidStrengthGradient(_))
+        ));
+        assert!(matches!(
+            AutomaticClaheOptions::builder()
+                .chroma_noise_reduction(-1.0)
+                .build(),
+            Err(OptionsError::InvalidChromaNoiseReduction(_))
+        ));
+        assert!(matches!(
+            AutomaticClaheOptions::builder().max_gain(0.5).build(),
+            Err(OptionsError::InvalidMaxGain(_))
+        ));
+        assert!(matches!(
+            AutomaticClaheOptions::builder()
+                .gain_smoothing(GuidedFilter {
+                    radius: 4,
+                    epsilon: 0.0,
+                })
+                .build(),
+            Err(OptionsError::InvalidGainSmoothing(_))
+        ));
+        assert!(matches!(
+            AutomaticClaheOptions::builder()
+                .shadow_lift(ShadowLift {
+                    threshold: 64,
+                    rolloff: 0,
+                })
+                .build(),
+            Err(OptionsError::ZeroShadowRolloff)
+        ));
+        assert!(matches!(
+            AutomaticClaheOptions::builder()
+                .histogram_subsampling(0)
+                .build(),
+            Err(OptionsError::ZeroHistogramSubsampling)
+        ));
+        assert!(matches!(
+            AutomaticClaheOptions::builder()
+                .histogram_sample_budget(0)
+                .build(),
+            Err(OptionsError::ZeroHistogramSampleBudget)
+        ));
+        assert!(matches!(
+            AutomaticClaheOptions::builder()
+                .point_source_protection(PointSourceProtection {
+                    prominence: 40,
+                    radius: 0,
+                })
+                .build(),
+            Err(OptionsError::ZeroPointSourceRadius)
+        ));
+        assert_eq!(
+            AutomaticClaheOptions::builder()
+                .output_range(200, 100)
+                .build()
+                .err(),
+            Some(OptionsError::InvalidOutputRange {
+                black: 200,
+                white: 100
+            })
+        );
+    }
+}
@@ -0,0 +1,432 @@
+use crate::{
+    quadtree::BlockQuadtree, AutomaticClahe, AutomaticClaheOptions, Block, BlockGrid, BlockLayout,
+    BlockRegions, ChannelLayout, ClaheError, ColorSpace, Interpolation, LutTexture, PlaneState,
+    Region, TransferFunction,
+};
+
+/// The block LUTs and geometry computed for an RGB image, which can be stored and applied to
+/// the image (or frames of the same size) later without analyzing it again.
+///
+/// Plans of [`Interpolation::SlidingWindow`] hold a bilinearly interpolated block grid instead,
+/// and the dehaze pre-pass is not part of them.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EnhancementPlan {
+    width: usize,
+    height: usize,
+    options: AutomaticClaheOptions,
+    transfer_function: Option<TransferFunction>,
+    block_size: (usize, usize),
+    // The layouts of every block scale of every plane.
+    planes: Vec<Vec<PlannedLayout>>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum PlannedLayout {
+    Grid {
+        block_width: usize,
+        block_height: usize,
+        blocks: Vec<PlannedBlock>,
+    },
+    Quadtree {
+        block_width: usize,
+        block_height: usize,
+        leaves: Vec<PlannedBlock>,
+    },
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(into = "PlannedBlockData", try_from = "PlannedBlockData")
+)]
+struct PlannedBlock {
+    region: Region,
+    table: [f32; 256],
+}
+
+impl PlannedBlock {
+    fn new(block: &Block) -> Self {
+        Self {
+            region: block.region,
+            table: block.table,
+        }
+    }
+
+    fn block(&self) -> Block {
+        Block {
+            table: self.table,
+            ..Block::identity(self.region)
+        }
+    }
+}
+
+// The serialized form of a block (serde only derives arrays of up to 32 elements).
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct PlannedBlockData {
+    region: Region,
+    table: Vec<f32>,
+}
+
+#[cfg(feature = "serde")]
+impl From<PlannedBlock> for PlannedBlockData {
+    fn from(block: PlannedBlock) -> Self {
+        Self {
+            region: block.region,
+            table: block.table.to_vec(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<PlannedBlockData> for PlannedBlock {
+    type Error = String;
+
+    fn try_from(block: PlannedBlockData) -> Result<Self, Self::Error> {
+        let table = <[f32; 256]>::try_from(block.table)
+            .map_err(|table| format!("expected 256 table entries, got {}", table.len()))?;
+        Ok(Self {
+            region: block.region,
+            table,
+        })
+    }
+}
+
+impl AutomaticClahe {
+    /// Analyzes an RGB image into a plan that [`EnhancementPlan::apply()`] turns into the same
+    /// output as [`enhance_rgb_image()`](Self::enhance_rgb_image).
+    pub fn compute_plan(&self, pixels: &[u8], width: usize) -> EnhancementPlan {
+        let planes = self.extract_planes(pixels, width, ChannelLayout::RGB, None);
+        let mut block_size = (0, 0);
+        let planned = planes
+            .images()
+            .iter()
+            .enumerate()
+            .map(|(i, image)| {
+                let (analyzed, _) = self.analyzed_image(image);
+                let (layouts, size) = self.block_layouts(analyzed.as_ref().unwrap_or(image), false);
+                if i == 0 {
+                    block_size = size;
+                }
+                layouts.iter().map(PlannedLayout::new).collect()
+            })
+            .collect();
+
+        let mut options = self.options.clone();
+        if options.interpolation == Interpolation::SlidingWindow {
+            options.interpolation = Interpolation::Bilinear;
+        }
+        EnhancementPlan {
+            width,
+            height: pixels.len() / ChannelLayout::RGB.channels() / width,
+            options,
+            transfer_function: self.transfer_function.clone(),
+            block_size,
+            planes: planned,
+        }
+    }
+}
+
+impl PlannedLayout {
+    fn new(layout: &BlockLayout) -> Self {
+        match layout {
+            BlockLayout::Grid(grid) => Self::Grid {
+                block_width: grid.block_width,
+                block_height: grid.block_height,
+                blocks: grid.blocks.iter().map(PlannedBlock::new).collect(),
+            },
+            BlockLayout::Quadtree(tree) => Self::Quadtree {
+                block_width: tree.block_size().0,
+                block_height: tree.block_size().1,
+                leaves: tree.leaves().iter().map(PlannedBlock::new).collect(),
+            },
+            BlockLayout::Exact { .. } => unreachable!("plans are built from blocks"),
+        }
+    }
+
+    fn validate(&self, plan: &EnhancementPlan) -> Result<(), ClaheError> {
+        let (width, height) = (plan.width, plan.height);
+        let (Self::Grid {
+            block_width,
+            block_height,
+            ..
+        }
+        | Self::Quadtree {
+            block_width,
+            block_height,
+            ..
+        }) = self;
+        if !(1..=width).contains(block_width) || !(1..=height).contains(block_height) {
+            return Err(ClaheError::InvalidPlan("block size"));
+        }
+        match self {
+            Self::Grid { blocks, .. } => {
+                if plan.options.quadtree.is_some() {
+                    return Err(ClaheError::InvalidPlan("grid layout of a quadtree plan"));
+                }
+                let regions = BlockRegions::new(width, height, *block_width, *block_height);
+                if !regions
+                    .map(|(region, _)| region)
+                    .eq(blocks.iter().map(|block| block.region))
+                {
+                    return Err(ClaheError::InvalidPlan("grid block regions"));
+                }
+            }
+            Self::Quadtree { leaves, .. } => {
+                if plan.options.quadtree.is_none() {
+                    return Err(ClaheError::InvalidPlan("quadtree layout without a split"));
+                }
+                // The leaves must cover every pixel exactly once.
+                let mut covered = vec![false; width * height];
+                for leaf in leaves {
+                    let Region { start, end } = leaf.region;
+                    if !(start.x < end.x && end.x <= width && start.y < end.y && end.y <= height) {
+                        return Err(ClaheError::InvalidPlan("quadtree leaf regions"));
+                    }
+                    for i in leaf.region.indices(width) {
+                        if std::mem::replace(&mut covered[i], true) {
+                            return Err(ClaheError::InvalidPlan("quadtree leaf regions"));
+                        }
+                    }
+                }
+                if covered.contains(&false) {
+                    return Err(ClaheError::InvalidPlan("quadtree leaf regions"));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn layout(&self, plan: &EnhancementPlan) -> BlockLayout {
+        let size = (plan.width, plan.height);
+        match self {
+            Self::Grid {
+                block_width,
+                block_height,
+                blocks,
+            } => BlockLayout::Grid(BlockGrid::from_blocks(
+                blocks.iter().map(PlannedBlock::block).collect(),
+                size,
+                (*block_width, *block_height),
+            )),
+            Self::Quadtree {
+                block_width,
+                block_height,
+                leaves,
+            } => BlockLayout::Quadtree(BlockQuadtree::from_leaves(
+                leaves.iter().map(PlannedBlock::block).collect(),
+                size,
+                (*block_width, *block_height),
+                plan.options
+                    .quadtree
+                    .as_ref()
+                    .expect("quadtree plans have a split"),
+            )),
+        }
+    }
+}
+
+impl EnhancementPlan {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Enhances an interleaved RGB image of the planned size in place.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of `pixels` does not match the plan, or if a deserialized plan is
+    /// inconsistent; see [`try_apply()`](Self::try_apply).
+    pub fn apply(&self, pixels: &mut [u8]) {
+        let layout = ChannelLayout::RGB;
+        assert_eq!(pixels.len(), self.width * self.height * layout.channels());
+
+        let mut clahe = AutomaticClahe::with_options(self.options.clone());
+        clahe.transfer_function = self.transfer_function.clone();
+        let mut planes = clahe.extract_planes(pixels, self.width, layout, None);
+        for (image, planned) in planes.images_mut().iter_mut().zip(&self.planes) {
+            let layouts = planned
+                .iter()
+                .map(|layout| layout.layout(self))
+                .collect::<Vec<_>>();
+            let state = PlaneState {
+                plan: Some((&layouts, self.block_size)),
+                ..Default::default()
+            };
+            clahe.enhance_luminances_with(image, state);
+        }
+        clahe.write_back_planes(pixels, layout, &planes);
+    }
+
+    /// Packs the block LUTs for [`LutTexture::shader()`], for plans of a single bilinearly
+    /// interpolated block grid of the luminance (`None` otherwise).
+    pub fn lut_texture(&self) -> Option<LutTexture> {
+        let bilinear = self.options.interpolation == Interpolation::Bilinear;
+        match &self.planes[..] {
+            [layouts] => match &layouts[..] {
+                [PlannedLayout::Grid {
+                    block_width,
+                    block_height,
+                    blocks,
+                }] if bilinear => Some(LutTexture::new(
+                    (self.width, self.height),
+                    (*block_width, *block_height),
+                    blocks.iter().map(|block| &block.table[..]),
+                )),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Validating variant of [`apply()`](Self::apply), which also checks that the block layouts
+    /// of the plan match its size and options.
+    pub fn try_apply(&self, pixels: &mut [u8]) -> Result<(), ClaheError> {
+        self.validate()?;
+        let channels = ChannelLayout::RGB.channels();
+        if pixels.len() != self.width * self.height * channels {
+            return Err(ClaheError::BufferSizeMismatch {
+                len: pixels.len(),
+                channels,
+                width: self.width,
+            });
+        }
+        self.apply(pixels);
+        Ok(())
+    }
+
+    // Checks what the enhancement assumes about the plan, which a deserialized plan may violate.
+    fn validate(&self) -> Result<(), ClaheError> {
+        let (width, height) = (self.width, self.height);
+        self.options.validate()?;
+        if width == 0 || height == 0 {
+            return Err(ClaheError::ZeroDimension { width, height });
+        }
+        if width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(ChannelLayout::RGB.channels()))
+            .is_none()
+        {
+            return Err(ClaheError::ImageTooLarge { width, height });
+        }
+        if self.options.interpolation == Interpolation::SlidingWindow {
+            return Err(ClaheError::InvalidPlan("sliding-window interpolation"));
+        }
+        let planes = if self.options.color_space == ColorSpace::PerChannel {
+            3
+        } else {
+            1
+        };
+        if self.planes.len() != planes {
+            return Err(ClaheError::InvalidPlan("plane count"));
+        }
+        let scales = self.options.block_scales.len().max(1);
+        for layouts in &self.planes {
+            if layouts.len() != scales {
+                return Err(ClaheError::InvalidPlan("block scale count"));
+            }
+            for layout in layouts {
+                layout.validate(self)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::QuadtreeSplit;
+
+    #[test]
+    fn applied_plans_match_the_enhancement() {
+        let width = 64;
+        let original = (0..width * 48)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                [(x * 3) as u8, (y * 5) as u8, ((x + y) * 2) as u8]
+            })
+            .collect::<Vec<_>>();
+        for options in [
+            AutomaticClaheOptions::default(),
+            AutomaticClaheOptions {
+                quadtree: Some(QuadtreeSplit::default()),
+                block_scales: vec![1.0, 2.0],
+                ..Default::default()
+            },
+        ] {
+            let clahe = AutomaticClahe::with_options(options);
+            let mut expected = original.clone();
+            clahe.enhance_rgb_image(&mut expected, width);
+
+            let plan = clahe.compute_plan(&original, width);
+            let mut pixels = original.clone();
+            plan.try_apply(&mut pixels).unwrap();
+            assert_eq!(pixels, expected);
+            assert!(plan.try_apply(&mut pixels[3..]).is_err());
+
+            #[cfg(feature = "serde")]
+            {
+                let json = serde_json::to_string(&plan).unwrap();
+                let plan: EnhancementPlan = serde_json::from_str(&json).unwrap();
+                let mut pixels = original.clone();
+                plan.apply(&mut pixels);
+                assert_eq!(pixels, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn inconsistent_plans_are_rejected() {
+        let width = 64;
+        let original = (0..width * 48)
+            .flat_map(|i| [(i % 251) as u8, (i / width * 5) as u8, 90])
+            .collect::<Vec<_>>();
+        let plan = |options| AutomaticClahe::with_options(options).compute_plan(&original, width);
+        let try_apply = |plan: &EnhancementPlan| plan.try_apply(&mut original.clone());
+
+        let mut quadtree = plan(AutomaticClaheOptions {
+            quadtree: Some(QuadtreeSplit::default()),
+            ..Default::default()
+        });
+        quadtree.options.quadtree = None;
+        assert_eq!(
+            try_apply(&quadtree),
+            Err(ClaheError::InvalidPlan("quadtree layout without a split"))
+        );
+
+        let mut grid = plan(Default::default());
+        grid.options.alpha = -1.0;
+        assert!(matches!(
+            try_apply(&grid),
+            Err(ClaheError::InvalidOptions(_))
+        ));
+        grid.options.alpha = 100.0;
+        grid.height = 40;
+        assert_eq!(
+            try_apply(&grid),
+            Err(ClaheError::InvalidPlan("grid block regions"))
+        );
+        grid.height = 48;
+        grid.planes.push(grid.planes[0].clone());
+        assert_eq!(
+            try_apply(&grid),
+            Err(ClaheError::InvalidPlan("plane count"))
+        );
+
+        #[cfg(feature = "serde")]
+        {
+            let mut json = serde_json::to_value(plan(Default::default())).unwrap();
+            let table = &mut json["planes"][0][0]["Grid"]["blocks"][0]["table"];
+            table.as_array_mut().unwrap().push(0.0.into());
+            assert!(serde_json::from_value::<EnhancementPlan>(json).is_err());
+        }
+    }
+}
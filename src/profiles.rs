@@ -0,0 +1,139 @@
+use crate::{AutomaticClaheOptions, OptionsError};
+use std::path::Path;
+
+/// A named, shareable set of options.
+///
+/// Profiles are stored as JSON or TOML; missing option fields take their default values.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Profile {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub options: AutomaticClaheOptions,
+}
+
+impl Profile {
+    pub fn new(name: impl Into<String>, options: AutomaticClaheOptions) -> Self {
+        Self {
+            name: name.into(),
+            description: None,
+            options,
+        }
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, ProfileError> {
+        let profile: Self = serde_json::from_str(s).map_err(ProfileError::Json)?;
+        profile.options.validate()?;
+        Ok(profile)
+    }
+
+    pub fn to_json(&self) -> Result<String, ProfileError> {
+        serde_json::to_string_pretty(self).map_err(ProfileError::Json)
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self, ProfileError> {
+        let profile: Self = toml::from_str(s).map_err(ProfileError::TomlDe)?;
+        profile.options.validate()?;
+        Ok(profile)
+    }
+
+    pub fn to_toml(&self) -> Result<String, ProfileError> {
+        toml::to_string_pretty(self).map_err(ProfileError::TomlSer)
+    }
+
+    /// Loads a profile from a `.toml` file, or a JSON file for any other extension.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProfileError> {
+        let path = path.as_ref();
+        let s = std::fs::read_to_string(path).map_err(ProfileError::Io)?;
+        if is_toml(path) {
+            Self::from_toml(&s)
+        } else {
+            Self::from_json(&s)
+        }
+    }
+
+    /// Saves the profile as TOML if `path` has the `.toml` extension, and as JSON otherwise.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ProfileError> {
+        let path = path.as_ref();
+        let s = if is_toml(path) {
+            self.to_toml()?
+        } else {
+            self.to_json()?
+        };
+        std::fs::write(path, s).map_err(ProfileError::Io)
+    }
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("toml"))
+}
+
+#[derive(Debug)]
+pub enum ProfileError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    TomlDe(toml::de::Error),
+    TomlSer(toml::ser::Error),
+    InvalidOptions(OptionsError),
+}
+
+impl std::fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {}", e),
+            Self::Json(e) => write!(f, "invalid JSON profile: {}", e),
+            Self::TomlDe(e) => write!(f, "invalid TOML profile: {}", e),
+            Self::TomlSer(e) => write!(f, "failed to serialize profile as TOML: {}", e),
+            Self::InvalidOptions(e) => write!(f, "invalid options: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Json(e) => Some(e),
+            Self::TomlDe(e) => Some(e),
+            Self::TomlSer(e) => Some(e),
+            Self::InvalidOptions(e) => Some(e),
+        }
+    }
+}
+
+impl From<OptionsError> for ProfileError {
+    fn from(e: OptionsError) -> Self {
+        Self::InvalidOptions(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockSize, Preset};
+
+    #[test]
+    fn round_trip_works() {
+        let mut options = AutomaticClaheOptions::preset(Preset::LowLight);
+        options.block_size = BlockSize::Tiles { x: 8, y: 6 };
+        options.max_gain = Some(3.0);
+        options.protected_ranges = vec![0..=2, 253..=255];
+        let mut profile = Profile::new("night", options);
+        profile.description = Some("Underexposed street photos".to_owned());
+
+        let json = Profile::from_json(&profile.to_json().expect("serializable")).expect("valid");
+        let toml = Profile::from_toml(&profile.to_toml().expect("serializable")).expect("valid");
+        for decoded in [json, toml] {
+            assert_eq!(format!("{:?}", decoded), format!("{:?}", profile));
+        }
+
+        let partial = Profile::from_toml("name = \"x\"\n[options]\nalpha = 20.0\n").expect("valid");
+        assert_eq!(partial.options.alpha, 20.0);
+        assert!(matches!(
+            Profile::from_json(r#"{"name": "x", "options": {"block_width": 0}}"#),
+            Err(ProfileError::InvalidOptions(OptionsError::ZeroBlockSize))
+        ));
+    }
+}
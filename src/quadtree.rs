@@ -0,0 +1,217 @@
+use crate::{
+    map_blocks, AutomaticClahe, Block, BlockRegions, GlobalStats, Histogram, Image, Interpolation,
+    Point, QuadtreeSplit, Region, ALL_LEVELS,
+};
+
+// Children narrower or shorter than this are not created.
+const MIN_BLOCK_DIMENSION: usize = 4;
+
+// Leaves are blended with `exp(-2 * (dx^2 + dy^2))`, where the distance to their center is
+// measured in their own size; beyond this distance the weight is negligible.
+const SUPPORT: usize = 2;
+
+// Blocks of a regular grid that are recursively split into quadrants where the content is
+// detailed.
+#[derive(Debug)]
+pub(crate) struct BlockQuadtree {
+    leaves: Vec<Block>,
+    block_width: usize,
+    block_height: usize,
+    cell_width: usize,
+    cell_height: usize,
+    line_cells: usize,
+    // For each cell of the finest possible leaf size, the leaves that may contribute to it.
+    candidates: Vec<Vec<usize>>,
+}
+
+impl BlockQuadtree {
+    pub(crate) fn new(
+        image: &Image,
+        stats: &GlobalStats,
+        clahe: &AutomaticClahe,
+        (block_width, block_height): (usize, usize),
+        split: &QuadtreeSplit,
+    ) -> Self {
+        let mut regions = Vec::new();
+        for (region, _) in BlockRegions::new(image.width, image.height, block_width, block_height) {
+            split_region(image, region, 0, split, &mut regions);
+        }
+        let leaves = map_blocks(Vec::new(), regions.into_iter(), |region| {
+            let (width, height) = region.size();
+            let margin = BlockRegions::overlap_margin(width, height, clahe.options.block_overlap);
+            let window = region.grown(margin, image.width, image.height);
+            Block::new(image, stats, clahe, (region, window), ALL_LEVELS)
+        });
+        Self::from_leaves(
+            leaves,
+            (image.width, image.height),
+            (block_width, block_height),
+            split,
+        )
+    }
+
+    pub(crate) fn from_leaves(
+        leaves: Vec<Block>,
+        (image_width, image_height): (usize, usize),
+        (block_width, block_height): (usize, usize),
+        split: &QuadtreeSplit,
+    ) -> Self {
+        // No leaf is smaller than these cells.
+        let cell = |size: usize| {
+            std::cmp::max(
+                size.checked_shr(split.max_depth).unwrap_or(0),
+                std::cmp::min(MIN_BLOCK_DIMENSION, size),
+            )
+        };
+        let (cell_width, cell_height) = (cell(block_width), cell(block_height));
+        let line_cells = image_width.div_ceil(cell_width);
+        let rows = image_height.div_ceil(cell_height);
+        let mut candidates = vec![Vec::new(); line_cells * rows];
+        for (i, leaf) in leaves.iter().enumerate() {
+            let (width, height) = leaf.region.size();
+            let (x, y) = (leaf.center_x(), leaf.center_y());
+            let x0 = x.saturating_sub(SUPPORT * width) / cell_width;
+            let y0 = y.saturating_sub(SUPPORT * height) / cell_height;
+            let x1 = std::cmp::min((x + SUPPORT * width) / cell_width + 1, line_cells);
+            let y1 = std::cmp::min((y + SUPPORT * height) / cell_height + 1, rows);
+            for cy in y0..y1 {
+                for cx in x0..x1 {
+                    candidates[cy * line_cells + cx].push(i);
+                }
+            }
+        }
+        Self {
+            leaves,
+            block_width,
+            block_height,
+            cell_width,
+            cell_height,
+            line_cells,
+            candidates,
+        }
+    }
+
+    pub(crate) fn block_size(&self) -> (usize, usize) {
+        (self.block_width, self.block_height)
+    }
+
+    pub(crate) fn leaves(&self) -> &[Block] {
+        &self.leaves
+    }
+
+    pub(crate) fn leaves_mut(&mut self) -> &mut [Block] {
+        &mut self.leaves
+    }
+
+    pub(crate) fn interpolate(
+        &self,
+        interpolation: Interpolation,
+        y: usize,
+        x: usize,
+        l0: u8,
+    ) -> f32 {
+        let cell = (y / self.cell_height) * self.line_cells + x / self.cell_width;
+        let leaves = self.candidates[cell].iter().map(|&i| &self.leaves[i]);
+        if interpolation == Interpolation::Nearest {
+            let leaf = leaves
+                .clone()
+                .find(|leaf| leaf.region.contains(x, y))
+                .expect("a leaf covers every pixel");
+            return leaf.enhance(l0);
+        }
+
+        let mut sum = 0.0;
+        let mut weights = 0.0;
+        for leaf in leaves {
+            let (width, height) = leaf.region.size();
+            let dy = (y as f32 - leaf.center_y() as f32) / height as f32;
+            let dx = (x as f32 - leaf.center_x() as f32) / width as f32;
+            let w = (-2.0 * (dx * dx + dy * dy)).exp();
+            sum += w * leaf.enhance(l0);
+            weights += w;
+        }
+        sum / weights
+    }
+}
+
+fn split_region(
+    image: &Image,
+    region: Region,
+    depth: u32,
+    split: &QuadtreeSplit,
+    leaves: &mut Vec<Region>,
+) {
+    let (width, height) = region.size();
+    let detailed = || {
+        let (_, sigma) = Histogram::new(image.items(region)).mean_and_sigma();
+        sigma > split.sigma_threshold
+    };
+    if depth >= split.max_depth
+        || width < 2 * MIN_BLOCK_DIMENSION
+        || height < 2 * MIN_BLOCK_DIMENSION
+        || !detailed()
+    {
+        leaves.push(region);
+        return;
+    }
+
+    let middle = Point::new(region.start.x + width / 2, region.start.y + height / 2);
+    for (start, end) in [
+        (region.start, middle),
+        (
+            Point::new(middle.x, region.start.y),
+            Point::new(region.end.x, middle.y),
+        ),
+        (
+            Point::new(region.start.x, middle.y),
+            Point::new(middle.x, region.end.y),
+        ),
+        (middle, region.end),
+    ] {
+        split_region(image, Region { start, end }, depth + 1, split, leaves);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AutomaticClaheOptions;
+
+    #[test]
+    fn only_detailed_blocks_are_split() {
+        let width = 128;
+        // Flat on the left, a fine checkerboard on the right.
+        let luminances = (0..width * 64)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                if x < 64 || (x / 2 + y / 2) % 2 == 0 {
+                    100
+                } else {
+                    200
+                }
+            })
+            .collect::<Vec<_>>();
+        let image = Image::new(luminances, width);
+        let clahe = AutomaticClahe::with_options(AutomaticClaheOptions {
+            quadtree: Some(QuadtreeSplit::default()),
+            ..Default::default()
+        });
+        let stats = GlobalStats::new(&image.histogram(), 0.75);
+        let tree = BlockQuadtree::new(&image, &stats, &clahe, (32, 32), &QuadtreeSplit::default());
+
+        // 2x2 unsplit blocks on the left and 2x2 blocks split twice on the right.
+        assert_eq!(tree.leaves.len(), 4 + 4 * 16);
+        for leaf in &tree.leaves {
+            let (width, height) = leaf.region.size();
+            let expected = if leaf.region.start.x < 64 { 32 } else { 8 };
+            assert_eq!((width, height), (expected, expected));
+        }
+
+        for y in 0..64 {
+            for x in 0..width {
+                let l = tree.interpolate(Interpolation::Bilinear, y, x, 150);
+                assert!(l.is_finite() && (0.0..=255.0).contains(&l));
+            }
+        }
+    }
+}
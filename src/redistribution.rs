@@ -0,0 +1,123 @@
+/// How the parts of a block histogram above the clip point are clipped and redistributed, see
+/// [`AutomaticClahe::with_redistribution()`](crate::AutomaticClahe::with_redistribution).
+pub trait RedistributionStrategy: std::fmt::Debug + Send + Sync {
+    /// Clips the normalized histogram `pdf` (which sums to one) at `clip_point`.
+    fn redistribute(&self, pdf: &mut [f32; 256], clip_point: f32);
+}
+
+/// Clips once and spreads the excess uniformly over all bins (the default), so that bins may
+/// end up slightly above the clip point.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SinglePassRedistribution;
+
+impl RedistributionStrategy for SinglePassRedistribution {
+    fn redistribute(&self, pdf: &mut [f32; 256], clip_point: f32) {
+        let exceeded = clip(pdf, clip_point);
+        if exceeded > 0.0 {
+            let offset = exceeded / 256.0;
+            for x in pdf {
+                *x += offset;
+            }
+        }
+    }
+}
+
+/// Re-clips the redistributed histogram until no bin exceeds the clip point, like the
+/// reference implementation of Zuiderveld.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IterativeRedistribution {
+    /// Upper bound of the clipping passes (clip points below `1 / 256` cannot be met).
+    pub max_iterations: usize,
+}
+
+impl Default for IterativeRedistribution {
+    fn default() -> Self {
+        Self { max_iterations: 32 }
+    }
+}
+
+impl RedistributionStrategy for IterativeRedistribution {
+    fn redistribute(&self, pdf: &mut [f32; 256], clip_point: f32) {
+        for _ in 0..self.max_iterations {
+            let exceeded = clip(pdf, clip_point);
+            if exceeded <= f32::EPSILON {
+                break;
+            }
+            let offset = exceeded / 256.0;
+            for x in pdf.iter_mut() {
+                *x += offset;
+            }
+        }
+    }
+}
+
+// Clips the bins at `clip_point` and returns the removed mass.
+fn clip(pdf: &mut [f32; 256], clip_point: f32) -> f32 {
+    let mut exceeded = 0.0;
+    for x in pdf {
+        if *x > clip_point {
+            exceeded += *x - clip_point;
+            *x = clip_point;
+        }
+    }
+    exceeded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AutomaticClahe, AutomaticClaheOptions};
+
+    #[test]
+    fn iterative_redistribution_respects_the_clip_point() {
+        let mut spike = [0.0; 256];
+        spike[10] = 0.5;
+        spike[200] = 0.5;
+        let clip_point = 0.02;
+
+        let mut single = spike;
+        SinglePassRedistribution.redistribute(&mut single, clip_point);
+        assert!(single[10] > clip_point);
+
+        let mut iterative = spike;
+        IterativeRedistribution::default().redistribute(&mut iterative, clip_point);
+        assert!(iterative.iter().all(|&x| x <= clip_point + 1e-6));
+        assert!((iterative.iter().sum::<f32>() - 1.0).abs() < 1e-4);
+
+        // A mostly flat image with a small gradient is clipped hard.
+        let width = 64;
+        let original = (0..width * 64)
+            .flat_map(|i| {
+                let l = if i % width < 8 {
+                    (i / width * 4) as u8
+                } else {
+                    120
+                };
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let enhance = |clahe: AutomaticClahe| {
+            let mut pixels = original.clone();
+            clahe.enhance_rgb_image(&mut pixels, width);
+            pixels
+        };
+        let options = || AutomaticClaheOptions {
+            clip_limit: Some(3.0),
+            ..Default::default()
+        };
+        assert_eq!(
+            enhance(AutomaticClahe::with_options(options())),
+            enhance(
+                AutomaticClahe::with_options(options())
+                    .with_redistribution(SinglePassRedistribution)
+            )
+        );
+        assert_ne!(
+            enhance(AutomaticClahe::with_options(options())),
+            enhance(
+                AutomaticClahe::with_options(options())
+                    .with_redistribution(IterativeRedistribution::default())
+            )
+        );
+    }
+}
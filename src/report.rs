@@ -0,0 +1,69 @@
+/// Information about a single enhancement call.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    /// Number of heap allocations performed by the calling thread during the call.
+    ///
+    /// `None` unless the `alloc-stats` feature is enabled and
+    /// `alloc_stats::CountingAllocator` is the global allocator.
+    pub allocations: Option<u64>,
+
+    /// Number of bytes requested by those allocations.
+    pub allocated_bytes: Option<u64>,
+
+    /// Size of the scratch buffers (luminance plane and blocks) used by the call.
+    pub scratch_bytes: usize,
+
+    pub warnings: Vec<Warning>,
+
+    /// The blocks of the first plane (all block scales and quadtree leaves); empty for
+    /// `Interpolation::SlidingWindow`.
+    pub blocks: Vec<BlockReport>,
+}
+
+/// What the enhancement did to a single block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockReport {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+
+    /// The clip point (as a fraction of the block's pixels per bin), `None` if the block was
+    /// left unenhanced (empty, flat or overridden).
+    pub clip_point: Option<f32>,
+
+    pub dual_gamma_correction: bool,
+
+    /// Standard deviation of the block's luminance.
+    pub sigma: f32,
+
+    /// Mean absolute difference between the LUT and the identity over the block's pixels.
+    pub lut_deviation: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// The blocks are smaller than the dominant image structures, which risks halos.
+    BlockSmallerThanStructures {
+        block_width: usize,
+        block_height: usize,
+        structure_scale: usize,
+    },
+}
+
+impl BlockReport {
+    // An unenhanced block.
+    pub(crate) fn new(region: crate::Region) -> Self {
+        let (width, height) = region.size();
+        Self {
+            x: region.start.x,
+            y: region.start.y,
+            width,
+            height,
+            clip_point: None,
+            dual_gamma_correction: false,
+            sigma: 0.0,
+            lut_deviation: 0.0,
+        }
+    }
+}
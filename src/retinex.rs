@@ -0,0 +1,175 @@
+use crate::guided::box_mean;
+use crate::{
+    AutomaticClahe, AutomaticClaheOptions, BlockSize, ChannelLayout, ClaheError, ColorSpace,
+    Enhancer, OptionsError,
+};
+
+/// Options of [`Retinex`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct RetinexOptions {
+    /// Standard deviations (in pixels) of the Gaussian surrounds, equally weighted.
+    pub sigmas: Vec<f32>,
+
+    /// Fraction of the darkest and of the brightest retinex outputs that is clipped when
+    /// stretching the result to the full luminance range.
+    pub clip_percentile: f32,
+
+    pub color_space: ColorSpace,
+}
+
+impl Default for RetinexOptions {
+    fn default() -> Self {
+        Self {
+            sigmas: vec![15.0, 80.0, 250.0],
+            clip_percentile: 0.01,
+            color_space: ColorSpace::HsvInteger,
+        }
+    }
+}
+
+impl RetinexOptions {
+    pub fn validate(&self) -> Result<(), OptionsError> {
+        if let Some(&sigma) = self.sigmas.iter().find(|s| !(s.is_finite() && **s > 0.0)) {
+            return Err(OptionsError::InvalidRetinexSigma(sigma));
+        }
+        if !(0.0..0.5).contains(&self.clip_percentile) {
+            return Err(OptionsError::InvalidRetinexClipPercentile(
+                self.clip_percentile,
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Multi-scale retinex (MSR): the log ratio of each pixel to its Gaussian surrounds, averaged
+/// over the scales and stretched to the luminance range, as a low-light baseline for
+/// [`AutomaticClahe`].
+#[derive(Debug)]
+pub struct Retinex {
+    options: RetinexOptions,
+    inner: AutomaticClahe,
+}
+
+impl Retinex {
+    pub fn with_options(options: RetinexOptions) -> Self {
+        let inner = AutomaticClahe::with_options(AutomaticClaheOptions {
+            block_size: BlockSize::Tiles { x: 1, y: 1 },
+            color_space: options.color_space,
+            ..Default::default()
+        });
+        Self { options, inner }
+    }
+
+    pub fn new() -> Self {
+        Self::with_options(Default::default())
+    }
+
+    fn enhance_luminances(&self, luminances: &mut [u8], width: usize) {
+        if self.options.sigmas.is_empty() {
+            return;
+        }
+        let log = luminances
+            .iter()
+            .map(|&l| (f32::from(l) + 1.0).ln())
+            .collect::<Vec<_>>();
+        let values = luminances.iter().map(|&l| f32::from(l)).collect::<Vec<_>>();
+        let mut retinex = vec![0.0; luminances.len()];
+        for &sigma in &self.options.sigmas {
+            let surround = gaussian_blur(&values, width, sigma);
+            for ((r, l), s) in retinex.iter_mut().zip(&log).zip(surround) {
+                *r += (l - (s + 1.0).ln()) / self.options.sigmas.len() as f32;
+            }
+        }
+
+        let mut sorted = retinex.clone();
+        sorted.sort_by(f32::total_cmp);
+        let rank = |q: f32| sorted[((sorted.len() - 1) as f32 * q).round() as usize];
+        let (low, high) = (
+            rank(self.options.clip_percentile),
+            rank(1.0 - self.options.clip_percentile),
+        );
+        let range = (high - low).max(f32::EPSILON);
+        for (l, r) in luminances.iter_mut().zip(retinex) {
+            *l = (255.0 * (r - low) / range).clamp(0.0, 255.0).round() as u8;
+        }
+    }
+}
+
+// Approximates a Gaussian blur by three box blurs of the same variance.
+fn gaussian_blur(values: &[f32], width: usize, sigma: f32) -> Vec<f32> {
+    let radius = (((4.0 * sigma * sigma + 1.0).sqrt() - 1.0) / 2.0).round() as usize;
+    let mut blurred = box_mean(values, width, radius);
+    for _ in 0..2 {
+        blurred = box_mean(&blurred, width, radius);
+    }
+    blurred
+}
+
+impl Default for Retinex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Enhancer for Retinex {
+    fn enhance_image(&self, pixels: &mut [u8], width: usize, layout: ChannelLayout) {
+        let mut planes = self.inner.extract_planes(pixels, width, layout, None);
+        for image in planes.images_mut() {
+            self.enhance_luminances(&mut image.luminances, width);
+        }
+        self.inner.write_back_planes(pixels, layout, &planes);
+    }
+
+    fn try_enhance_image(
+        &self,
+        pixels: &mut [u8],
+        width: usize,
+        layout: ChannelLayout,
+    ) -> Result<(), ClaheError> {
+        self.options.validate()?;
+        self.inner
+            .validate(pixels.len(), layout.channels(), width)?;
+        self.enhance_image(pixels, width, layout);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retinex_lifts_shadows_and_spans_the_range() {
+        let width = 96;
+        // A dark half with faint texture next to a bright half.
+        let original = (0..width * 64)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                let l = if x < 48 { 10 + (x + y) % 8 } else { 200 } as u8;
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let mut pixels = original.clone();
+        Retinex::new()
+            .try_enhance_rgb_image(&mut pixels, width)
+            .unwrap();
+
+        let dark = |pixels: &[u8]| {
+            pixels
+                .chunks(3 * width)
+                .flat_map(|row| row[..3 * 24].iter().map(|&l| u32::from(l)))
+                .sum::<u32>()
+        };
+        assert!(dark(&pixels) > 2 * dark(&original));
+        assert_eq!(pixels.iter().min(), Some(&0));
+        assert_eq!(pixels.iter().max(), Some(&255));
+
+        let invalid = Retinex::with_options(RetinexOptions {
+            sigmas: vec![0.0],
+            ..Default::default()
+        });
+        assert!(invalid.try_enhance_rgb_image(&mut pixels, width).is_err());
+    }
+}
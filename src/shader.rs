@@ -0,0 +1,243 @@
+/// The shading language of [`LutTexture::shader()`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShaderLanguage {
+    #[default]
+    Wgsl,
+    /// GLSL 3.30 / GLSL ES 3.00.
+    Glsl,
+}
+
+impl std::str::FromStr for ShaderLanguage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "wgsl" => Ok(Self::Wgsl),
+            "glsl" => Ok(Self::Glsl),
+            _ => Err(format!("unknown shader language: {:?}", s)),
+        }
+    }
+}
+
+/// The block LUTs of an [`EnhancementPlan`](crate::EnhancementPlan) packed into a
+/// single-channel `f32` texture (e.g. `R32Float`), so that the bilinear LUT blend can run in
+/// an existing shader pass.
+///
+/// Row `i` of the texture is the LUT of block `i` (in row-major block order), and its texel `l`
+/// is the enhanced luminance of the input level `l` divided by 255 (bright levels of the dual
+/// gamma correction may exceed `1.0`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct LutTexture {
+    data: Vec<f32>,
+    image_size: (usize, usize),
+    block_size: (usize, usize),
+}
+
+impl LutTexture {
+    pub(crate) fn new<'a>(
+        image_size: (usize, usize),
+        block_size: (usize, usize),
+        tables: impl Iterator<Item = &'a [f32]>,
+    ) -> Self {
+        Self {
+            data: tables.flatten().map(|&l| l / 255.0).collect(),
+            image_size,
+            block_size,
+        }
+    }
+
+    /// The texture width, one texel per input level.
+    pub fn width(&self) -> usize {
+        256
+    }
+
+    /// The texture height, one row per block.
+    pub fn height(&self) -> usize {
+        self.data.len() / 256
+    }
+
+    /// The texels in row-major order.
+    pub fn data(&self) -> &[f32] {
+        &self.data
+    }
+
+    /// Generates a snippet that declares the texture binding (group 0, binding 0 in WGSL;
+    /// `clahe_luts` in GLSL) and a `clahe_enhance(pixel, level)` function returning the
+    /// blended LUTs of the integer `level` of the `pixel` (both unsigned) like the CPU bilinear
+    /// interpolation, in the scale of the texels.
+    ///
+    /// The stages after the blend (strength, gain limits, clamping to the output range,
+    /// dithering and the write-back into the color space) are left to the calling shader.
+    pub fn shader(&self, language: ShaderLanguage) -> String {
+        let (width, height) = self.image_size;
+        let (block_width, block_height) = self.block_size;
+        let constants = [
+            ("CLAHE_WIDTH", width),
+            ("CLAHE_HEIGHT", height),
+            ("CLAHE_BLOCK_WIDTH", block_width),
+            ("CLAHE_BLOCK_HEIGHT", block_height),
+            ("CLAHE_COLUMNS", width / block_width),
+            ("CLAHE_ROWS", height / block_height),
+        ];
+        let mut shader = String::new();
+        for (name, value) in constants {
+            shader += &match language {
+                ShaderLanguage::Wgsl => format!("const {}: u32 = {}u;\n", name, value),
+                ShaderLanguage::Glsl => format!("const uint {} = {}u;\n", name, value),
+            };
+        }
+        shader
+            + match language {
+                ShaderLanguage::Wgsl => WGSL,
+                ShaderLanguage::Glsl => GLSL,
+            }
+    }
+}
+
+const WGSL: &str = r#"
+@group(0) @binding(0) var clahe_luts: texture_2d<f32>;
+
+// The center of a block, the last of which extends to the image edge.
+fn clahe_center(index: u32, size: u32, count: u32, extent: u32) -> i32 {
+    let start = index * size;
+    let end = select(start + size, extent, index + 1u == count);
+    return i32((end - start) / 2u + start);
+}
+
+fn clahe_lut(column: u32, row: u32, level: u32) -> f32 {
+    return textureLoad(clahe_luts, vec2<u32>(level, row * CLAHE_COLUMNS + column), 0).r;
+}
+
+fn clahe_enhance(pixel: vec2<u32>, level: u32) -> f32 {
+    let size = vec2<u32>(CLAHE_BLOCK_WIDTH, CLAHE_BLOCK_HEIGHT);
+    let half_size = size / 2u;
+    let aligned = vec2<u32>(CLAHE_COLUMNS, CLAHE_ROWS) * size;
+    let p = min(pixel, aligned - 1u);
+    let has_low = p >= half_size;
+    let has_high = p + half_size < aligned;
+    let low = select(vec2<u32>(0u), (p - half_size) / size, has_low);
+    let high = select(vec2<u32>(0u), (p + half_size) / size, has_high);
+    let a = has_low.x && has_low.y;
+    let b = has_high.x && has_low.y;
+    let c = has_low.x && has_high.y;
+    let d = has_high.x && has_high.y;
+
+    var m = select(0.0, 1.0, a || b);
+    if (a && c) {
+        let top = clahe_center(low.y, size.y, CLAHE_ROWS, CLAHE_HEIGHT);
+        let bottom = clahe_center(high.y, size.y, CLAHE_ROWS, CLAHE_HEIGHT);
+        if (top != bottom) {
+            m = f32(bottom - i32(pixel.y)) / f32(bottom - top);
+        }
+    }
+    var n = select(0.0, 1.0, a || c);
+    if (a && b) {
+        let left = clahe_center(low.x, size.x, CLAHE_COLUMNS, CLAHE_WIDTH);
+        let right = clahe_center(high.x, size.x, CLAHE_COLUMNS, CLAHE_WIDTH);
+        if (left != right) {
+            n = f32(right - i32(pixel.x)) / f32(right - left);
+        }
+    }
+
+    let la = select(0.0, clahe_lut(low.x, low.y, level), a);
+    let lb = select(0.0, clahe_lut(high.x, low.y, level), b);
+    let lc = select(0.0, clahe_lut(low.x, high.y, level), c);
+    let ld = select(0.0, clahe_lut(high.x, high.y, level), d);
+    return m * (n * la + (1.0 - n) * lb) + (1.0 - m) * (n * lc + (1.0 - n) * ld);
+}
+"#;
+
+const GLSL: &str = r#"
+uniform sampler2D clahe_luts;
+
+// The center of a block, the last of which extends to the image edge.
+int clahe_center(uint index, uint size, uint count, uint extent) {
+    uint start = index * size;
+    uint end = index + 1u == count ? extent : start + size;
+    return int((end - start) / 2u + start);
+}
+
+float clahe_lut(uint column, uint row, uint level) {
+    return texelFetch(clahe_luts, ivec2(int(level), int(row * CLAHE_COLUMNS + column)), 0).r;
+}
+
+float clahe_enhance(uvec2 pixel, uint level) {
+    uvec2 size = uvec2(CLAHE_BLOCK_WIDTH, CLAHE_BLOCK_HEIGHT);
+    uvec2 half_size = size / 2u;
+    uvec2 aligned = uvec2(CLAHE_COLUMNS, CLAHE_ROWS) * size;
+    uvec2 p = min(pixel, aligned - 1u);
+    bvec2 has_low = greaterThanEqual(p, half_size);
+    bvec2 has_high = lessThan(p + half_size, aligned);
+    uvec2 low = uvec2(has_low.x ? (p.x - half_size.x) / size.x : 0u,
+                      has_low.y ? (p.y - half_size.y) / size.y : 0u);
+    uvec2 high = (p + half_size) / size;
+    bool a = has_low.x && has_low.y;
+    bool b = has_high.x && has_low.y;
+    bool c = has_low.x && has_high.y;
+    bool d = has_high.x && has_high.y;
+
+    float m = a || b ? 1.0 : 0.0;
+    if (a && c) {
+        int top = clahe_center(low.y, size.y, CLAHE_ROWS, CLAHE_HEIGHT);
+        int bottom = clahe_center(high.y, size.y, CLAHE_ROWS, CLAHE_HEIGHT);
+        if (top != bottom) {
+            m = float(bottom - int(pixel.y)) / float(bottom - top);
+        }
+    }
+    float n = a || c ? 1.0 : 0.0;
+    if (a && b) {
+        int left = clahe_center(low.x, size.x, CLAHE_COLUMNS, CLAHE_WIDTH);
+        int right = clahe_center(high.x, size.x, CLAHE_COLUMNS, CLAHE_WIDTH);
+        if (left != right) {
+            n = float(right - int(pixel.x)) / float(right - left);
+        }
+    }
+
+    float la = a ? clahe_lut(low.x, low.y, level) : 0.0;
+    float lb = b ? clahe_lut(high.x, low.y, level) : 0.0;
+    float lc = c ? clahe_lut(low.x, high.y, level) : 0.0;
+    float ld = d ? clahe_lut(high.x, high.y, level) : 0.0;
+    return m * (n * la + (1.0 - n) * lb) + (1.0 - m) * (n * lc + (1.0 - n) * ld);
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AutomaticClahe, AutomaticClaheOptions, QuadtreeSplit};
+
+    #[test]
+    fn lut_textures_hold_the_planned_luts() {
+        let width = 64;
+        let original = (0..width * 48)
+            .flat_map(|i| {
+                let l = (i % width * 3 + i / width) as u8;
+                [l, l / 2, l / 3]
+            })
+            .collect::<Vec<_>>();
+        let clahe = AutomaticClahe::new();
+        let texture = clahe.compute_plan(&original, width).lut_texture().unwrap();
+        assert_eq!((texture.width(), texture.height()), (256, 2));
+        assert!(texture.data().iter().all(|&l| l >= 0.0));
+        assert!(texture.data()[256..].windows(2).any(|w| w[0] != w[1]));
+
+        let wgsl = texture.shader(ShaderLanguage::Wgsl);
+        assert!(wgsl.starts_with("const CLAHE_WIDTH: u32 = 64u;\n"));
+        assert!(
+            wgsl.contains("const CLAHE_COLUMNS: u32 = 2u;\n") && wgsl.contains("fn clahe_enhance(")
+        );
+        let glsl = texture.shader("glsl".parse().unwrap());
+        assert!(
+            glsl.contains("const uint CLAHE_ROWS = 1u;\n") && glsl.contains("float clahe_enhance(")
+        );
+
+        let quadtree = AutomaticClahe::with_options(AutomaticClaheOptions {
+            quadtree: Some(QuadtreeSplit::default()),
+            ..Default::default()
+        });
+        assert!(quadtree
+            .compute_plan(&original, width)
+            .lut_texture()
+            .is_none());
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{ChannelLayout, ChannelRole};
+use std::arch::x86_64::*;
+
+/// Appends the `max(R, G, B)` of every pixel of a 4-channel layout to `maxima`, computed 16
+/// pixels at a time with SSE2 (which every x86-64 CPU has).
+pub(crate) fn max_rgb(pixels: &[u8], layout: ChannelLayout, maxima: &mut Vec<u8>) {
+    debug_assert_eq!(layout.channels(), 4);
+    let mut mask = [0xff_u8; 16];
+    for (i, m) in mask.iter_mut().enumerate() {
+        if layout.role(i % 4) == Some(ChannelRole::Ignored) {
+            *m = 0;
+        }
+    }
+
+    let chunks = pixels.chunks_exact(64);
+    let rest = chunks.remainder();
+    maxima.reserve(pixels.len() / 4);
+    // SAFETY: SSE2 is part of the x86-64 baseline and every load and store is unaligned and
+    // within a 64-byte chunk or a local 16-byte array.
+    unsafe {
+        let mask = _mm_loadu_si128(mask.as_ptr().cast());
+        let low = _mm_set1_epi32(0xff);
+        for chunk in chunks {
+            // The maximum of the 4 (masked) bytes of each pixel ends up in its low byte.
+            let max4 = |offset: usize| {
+                let v = _mm_and_si128(_mm_loadu_si128(chunk.as_ptr().add(offset).cast()), mask);
+                let v = _mm_max_epu8(v, _mm_srli_epi32::<8>(v));
+                let v = _mm_max_epu8(v, _mm_srli_epi32::<16>(v));
+                _mm_and_si128(v, low)
+            };
+            let bytes = _mm_packus_epi16(
+                _mm_packs_epi32(max4(0), max4(16)),
+                _mm_packs_epi32(max4(32), max4(48)),
+            );
+            let mut lanes = [0; 16];
+            _mm_storeu_si128(lanes.as_mut_ptr().cast(), bytes);
+            maxima.extend_from_slice(&lanes);
+        }
+    }
+    maxima.extend(rest.chunks_exact(4).map(|p| {
+        let (r, g, b) = layout.rgb(p);
+        r.max(g).max(b)
+    }));
+}
+
+/// Blends the bilinear terms `[m, n, a, b, c, d]` of 8 pixels (stored term by term) like
+/// `blend_bilinear()`, as two 4-lane SSE vectors per term.
+pub(crate) fn blend_bilinear(lanes: &[[f32; 8]; 6]) -> [f32; 8] {
+    let mut blended = [0.0; 8];
+    // SAFETY: SSE is part of the x86-64 baseline and every load and store is unaligned and
+    // within a local 8-element array.
+    unsafe {
+        let one = _mm_set1_ps(1.0);
+        for half in [0, 4] {
+            let lane = |i: usize| _mm_loadu_ps(lanes[i].as_ptr().add(half));
+            let (m, n) = (lane(0), lane(1));
+            let not_n = _mm_sub_ps(one, n);
+            let top = _mm_add_ps(_mm_mul_ps(n, lane(2)), _mm_mul_ps(not_n, lane(3)));
+            let bottom = _mm_add_ps(_mm_mul_ps(n, lane(4)), _mm_mul_ps(not_n, lane(5)));
+            let l = _mm_add_ps(_mm_mul_ps(m, top), _mm_mul_ps(_mm_sub_ps(one, m), bottom));
+            _mm_storeu_ps(blended.as_mut_ptr().add(half), l);
+        }
+    }
+    blended
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_rgb_matches_the_scalar_extraction() {
+        let pixels = (0..4 * 1003)
+            .map(|i: usize| (i.wrapping_mul(2_654_435_761) >> 7) as u8)
+            .collect::<Vec<_>>();
+        for layout in [
+            ChannelLayout::RGBA,
+            ChannelLayout::BGRA,
+            ChannelLayout::ARGB,
+            ChannelLayout::ABGR,
+        ] {
+            let expected = pixels
+                .chunks_exact(4)
+                .map(|p| {
+                    let (r, g, b) = layout.rgb(p);
+                    r.max(g).max(b)
+                })
+                .collect::<Vec<_>>();
+            let mut maxima = vec![7];
+            max_rgb(&pixels, layout, &mut maxima);
+            assert_eq!(maxima[1..], expected);
+        }
+    }
+
+    #[test]
+    fn blend_bilinear_matches_the_scalar_blend() {
+        let lanes: [[f32; 8]; 6] = std::array::from_fn(|t| {
+            std::array::from_fn(|i| {
+                let v = ((t * 8 + i).wrapping_mul(2_654_435_761) >> 9) as u8;
+                if t < 2 {
+                    f32::from(v) / 255.0
+                } else {
+                    f32::from(v)
+                }
+            })
+        });
+        let expected: [f32; 8] =
+            std::array::from_fn(|i| crate::blend_bilinear(lanes.map(|term| term[i])));
+        assert_eq!(blend_bilinear(&lanes), expected);
+    }
+}
@@ -0,0 +1,93 @@
+use crate::{AutomaticClahe, Block, GlobalStats, Histogram, Image, Point, Region};
+
+impl Histogram {
+    fn add(&mut self, pixels: impl Iterator<Item = (u8, u32)>) {
+        for (l, w) in pixels {
+            self.0[usize::from(l)] += u64::from(w);
+        }
+    }
+
+    fn remove(&mut self, pixels: impl Iterator<Item = (u8, u32)>) {
+        for (l, w) in pixels {
+            self.0[usize::from(l)] -= u64::from(w);
+        }
+    }
+}
+
+// Enhances every pixel with the LUT of the `block_width x block_height` window centered on it
+// (clipped to the image). The window histogram of a row is updated column by column.
+pub(crate) fn enhance_exact(
+    image: &Image,
+    stats: &GlobalStats,
+    clahe: &AutomaticClahe,
+    (block_width, block_height): (usize, usize),
+) -> Vec<f32> {
+    let centered = |c: usize, size: usize, len: usize| {
+        (
+            c.saturating_sub(size / 2),
+            std::cmp::min(c + size - size / 2, len),
+        )
+    };
+    let mut values = Vec::with_capacity(image.luminances.len());
+    for y in 0..image.height {
+        let (y0, y1) = centered(y, block_height, image.height);
+        let column = |x: usize| Region {
+            start: Point::new(x, y0),
+            end: Point::new(x + 1, y1),
+        };
+
+        let mut histogram = Histogram([0; 256]);
+        let (mut x0, mut x1) = (0, 0);
+        for x in 0..image.width {
+            let (next_x0, next_x1) = centered(x, block_width, image.width);
+            for c in x1..next_x1 {
+                histogram.add(image.items(column(c)));
+            }
+            for c in x0..next_x0 {
+                histogram.remove(image.items(column(c)));
+            }
+            (x0, x1) = (next_x0, next_x1);
+
+            let window = Region {
+                start: Point::new(x0, y0),
+                end: Point::new(x1, y1),
+            };
+            let l0 = image.luminances[y * image.width + x];
+            let value = if clahe.options.identity_luts {
+                f32::from(l0)
+            } else {
+                let noise = Block::noise(image, clahe, window);
+                Block::from_histogram(&histogram, stats, clahe, window, noise, l0..=l0).enhance(l0)
+            };
+            values.push(value);
+        }
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_match_direct_histograms() {
+        let width = 24;
+        let luminances = (0..width * 20)
+            .map(|i| ((i * 7919) % 251) as u8)
+            .collect::<Vec<_>>();
+        let image = Image::new(luminances, width);
+        let stats = GlobalStats::new(&image.histogram(), 0.75);
+        let clahe = AutomaticClahe::new();
+        let values = enhance_exact(&image, &stats, &clahe, (8, 6));
+
+        for (i, value) in values.iter().enumerate() {
+            let (x, y) = (i % width, i / width);
+            let window = Region {
+                start: Point::new(x.saturating_sub(4), y.saturating_sub(3)),
+                end: Point::new((x + 4).min(width), (y + 3).min(20)),
+            };
+            let block = Block::new(&image, &stats, &clahe, (window, window), crate::ALL_LEVELS);
+            assert_eq!(*value, block.enhance(image.luminances[i]));
+        }
+    }
+}
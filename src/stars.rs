@@ -0,0 +1,62 @@
+use crate::guided::box_mean;
+use crate::PointSourceProtection;
+
+impl PointSourceProtection {
+    /// Returns the local background luminance of the pixels that belong to point sources.
+    ///
+    /// A pixel is a point source if it exceeds the mean of the window around it by `prominence`;
+    /// the detections are grown by one pixel to cover the fainter rims of the sources.
+    pub(crate) fn backgrounds(&self, luminances: &[u8], width: usize) -> Vec<Option<u8>> {
+        let height = luminances.len() / width;
+        let values = luminances.iter().map(|&l| f32::from(l)).collect::<Vec<_>>();
+        let means = box_mean(&values, width, self.radius);
+        let detected = values
+            .iter()
+            .zip(&means)
+            .map(|(l, mean)| *l >= mean + f32::from(self.prominence))
+            .collect::<Vec<_>>();
+
+        let mut backgrounds = vec![None; luminances.len()];
+        for y in 0..height {
+            for x in 0..width {
+                let near = (y.saturating_sub(1)..std::cmp::min(y + 2, height)).any(|ny| {
+                    (x.saturating_sub(1)..std::cmp::min(x + 2, width))
+                        .any(|nx| detected[ny * width + nx])
+                });
+                if near {
+                    let i = y * width + x;
+                    backgrounds[i] = Some(means[i].round() as u8);
+                }
+            }
+        }
+        backgrounds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_stars_but_not_extended_regions() {
+        let width = 64;
+        let mut luminances = vec![20; width * 32];
+        // A star and a large bright region.
+        luminances[10 * width + 10] = 250;
+        for y in 0..32 {
+            for x in 40..64 {
+                luminances[y * width + x] = 200;
+            }
+        }
+
+        let protection = PointSourceProtection::default();
+        let backgrounds = protection.backgrounds(&luminances, width);
+        for y in 9..12 {
+            for x in 9..12 {
+                assert!(backgrounds[y * width + x].is_some_and(|b| b < 40));
+            }
+        }
+        assert!(backgrounds[10 * width + 14].is_none());
+        assert!(backgrounds[16 * width + 50].is_none());
+    }
+}
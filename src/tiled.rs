@@ -0,0 +1,71 @@
+use crate::{Image, Region};
+
+// The luminances of an image rearranged block by block (row-major within each block), so that
+// the histogram of a block region is built from a contiguous slice instead of a strided walk
+// over the rows of the image.
+#[derive(Debug)]
+pub(crate) struct TiledLuminances {
+    values: Vec<u8>,
+    // The start of every tile in `values`, followed by the end of the last one.
+    offsets: Vec<usize>,
+}
+
+impl TiledLuminances {
+    // `regions` are the blocks of a grid of `columns` blocks per row, in row-major order.
+    pub(crate) fn new(image: &Image, regions: &[Region], columns: usize) -> Self {
+        let mut offsets = Vec::with_capacity(regions.len() + 1);
+        offsets.push(0);
+        for region in regions {
+            let (width, height) = region.size();
+            offsets.push(offsets[offsets.len() - 1] + width * height);
+        }
+
+        // The rows of the image are read in order, each appending a row to every tile of its
+        // block row.
+        let mut values = vec![0; offsets[regions.len()]];
+        let mut cursors = Vec::with_capacity(columns);
+        for (i, blocks) in regions.chunks(columns).enumerate() {
+            cursors.clear();
+            cursors.extend_from_slice(&offsets[i * columns..][..blocks.len()]);
+            for y in blocks[0].start.y..blocks[0].end.y {
+                let row = &image.luminances[y * image.width..][..image.width];
+                for (region, cursor) in blocks.iter().zip(&mut cursors) {
+                    let span = &row[region.start.x..region.end.x];
+                    values[*cursor..][..span.len()].copy_from_slice(span);
+                    *cursor += span.len();
+                }
+            }
+        }
+        Self { values, offsets }
+    }
+
+    // The luminances of every block, in the order of the regions.
+    pub(crate) fn tiles(&self) -> impl '_ + Iterator<Item = &[u8]> {
+        self.offsets.windows(2).map(|w| &self.values[w[0]..w[1]])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BlockRegions;
+
+    #[test]
+    fn tiles_hold_the_block_luminances() {
+        let width = 37;
+        let luminances = (0..width * 29)
+            .map(|i| ((i * 7919) % 251) as u8)
+            .collect::<Vec<_>>();
+        let image = Image::new(luminances, width);
+        let regions = BlockRegions::new(width, 29, 8, 6)
+            .map(|(region, _)| region)
+            .collect::<Vec<_>>();
+        let tiled = TiledLuminances::new(&image, &regions, width / 8);
+
+        assert_eq!(tiled.tiles().count(), regions.len());
+        for (tile, region) in tiled.tiles().zip(&regions) {
+            let expected = image.items(*region).map(|(l, _)| l).collect::<Vec<_>>();
+            assert_eq!(tile, expected);
+        }
+    }
+}
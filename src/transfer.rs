@@ -0,0 +1,175 @@
+/// A pair of transfer functions between the encoded pixel values and the light domain in which
+/// the enhancement operates (e.g. from an ICC profile or a camera log curve).
+///
+/// Both functions map normalized values in `[0, 1]` and are tabulated for the 256 8-bit levels.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(into = "TransferTables", try_from = "TransferTables")
+)]
+pub struct TransferFunction {
+    decode: [u8; 256],
+    encode: [u8; 256],
+}
+
+impl TransferFunction {
+    /// `decode` converts encoded values into the working domain and `encode` is its inverse.
+    pub fn new<D, E>(decode: D, encode: E) -> Self
+    where
+        D: Fn(f32) -> f32,
+        E: Fn(f32) -> f32,
+    {
+        let table = |f: &dyn Fn(f32) -> f32| {
+            std::array::from_fn(|v| (f(v as f32 / 255.0) * 255.0).round().clamp(0.0, 255.0) as u8)
+        };
+        Self {
+            decode: table(&decode),
+            encode: table(&encode),
+        }
+    }
+
+    /// PQ (SMPTE ST 2084) encoded content, enhanced as luminance relative to `peak_nits`.
+    ///
+    /// The relative luminance is stored with a 2.4 gamma so that the 8-bit working values keep
+    /// an SDR-like distribution.
+    pub fn pq(peak_nits: f32) -> Self {
+        let scale = 10000.0 / peak_nits;
+        Self::new(
+            move |e| (pq_eotf(e) * scale).min(1.0).powf(1.0 / 2.4),
+            move |v| pq_inverse_eotf(v.powf(2.4) / scale),
+        )
+    }
+
+    /// HLG (ARIB STD-B67) encoded content, enhanced as relative scene luminance (stored with a
+    /// 2.4 gamma like `pq()`).
+    pub fn hlg() -> Self {
+        Self::new(
+            |e| hlg_inverse_oetf(e).powf(1.0 / 2.4),
+            |v| hlg_oetf(v.powf(2.4)),
+        )
+    }
+
+    pub(crate) fn decode(&self, (r, g, b): (u8, u8, u8)) -> (u8, u8, u8) {
+        let t = &self.decode;
+        (t[usize::from(r)], t[usize::from(g)], t[usize::from(b)])
+    }
+
+    pub(crate) fn encode(&self, (r, g, b): (u8, u8, u8)) -> (u8, u8, u8) {
+        let t = &self.encode;
+        (t[usize::from(r)], t[usize::from(g)], t[usize::from(b)])
+    }
+}
+
+const PQ_M1: f32 = 2610.0 / 16384.0;
+const PQ_M2: f32 = 2523.0 / 4096.0 * 128.0;
+const PQ_C1: f32 = 3424.0 / 4096.0;
+const PQ_C2: f32 = 2413.0 / 4096.0 * 32.0;
+const PQ_C3: f32 = 2392.0 / 4096.0 * 32.0;
+
+// Returns the luminance relative to 10000 cd/m^2.
+fn pq_eotf(e: f32) -> f32 {
+    let p = e.powf(1.0 / PQ_M2);
+    ((p - PQ_C1).max(0.0) / (PQ_C2 - PQ_C3 * p)).powf(1.0 / PQ_M1)
+}
+
+fn pq_inverse_eotf(y: f32) -> f32 {
+    let p = y.clamp(0.0, 1.0).powf(PQ_M1);
+    ((PQ_C1 + PQ_C2 * p) / (1.0 + PQ_C3 * p)).powf(PQ_M2)
+}
+
+const HLG_A: f32 = 0.178_832_77;
+const HLG_B: f32 = 0.284_668_92;
+const HLG_C: f32 = 0.559_910_7;
+
+fn hlg_oetf(e: f32) -> f32 {
+    let e = e.clamp(0.0, 1.0);
+    if e <= 1.0 / 12.0 {
+        (3.0 * e).sqrt()
+    } else {
+        HLG_A * (12.0 * e - HLG_B).ln() + HLG_C
+    }
+}
+
+fn hlg_inverse_oetf(e: f32) -> f32 {
+    if e <= 0.5 {
+        e * e / 3.0
+    } else {
+        (((e - HLG_C) / HLG_A).exp() + HLG_B) / 12.0
+    }
+}
+
+// The serialized form of the tables (serde only derives arrays of up to 32 elements).
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct TransferTables {
+    decode: Vec<u8>,
+    encode: Vec<u8>,
+}
+
+#[cfg(feature = "serde")]
+impl From<TransferFunction> for TransferTables {
+    fn from(t: TransferFunction) -> Self {
+        Self {
+            decode: t.decode.to_vec(),
+            encode: t.encode.to_vec(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<TransferTables> for TransferFunction {
+    type Error = String;
+
+    fn try_from(t: TransferTables) -> Result<Self, Self::Error> {
+        let table = |values: Vec<u8>| {
+            <[u8; 256]>::try_from(values)
+                .map_err(|values| format!("expected 256 table entries, got {}", values.len()))
+        };
+        Ok(Self {
+            decode: table(t.decode)?,
+            encode: table(t.encode)?,
+        })
+    }
+}
+
+impl std::fmt::Debug for TransferFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TransferFunction").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gamma_transfer_function_works() {
+        let tf = TransferFunction::new(|v| v.powf(2.2), |v| v.powf(1.0 / 2.2));
+        assert_eq!(tf.decode((0, 128, 255)), (0, 56, 255));
+        assert_eq!(tf.encode((0, 56, 255)), (0, 128, 255));
+
+        let identity = TransferFunction::new(|v| v, |v| v);
+        assert_eq!(identity.decode((1, 2, 3)), (1, 2, 3));
+    }
+
+    #[test]
+    fn hdr_transfer_functions_round_trip() {
+        assert!((pq_eotf(1.0) - 1.0).abs() < 1e-4);
+        // 100 cd/m^2 is encoded at about 51% of the PQ range.
+        assert!((pq_inverse_eotf(0.01) - 0.508).abs() < 1e-3);
+        assert!((hlg_oetf(1.0) - 1.0).abs() < 1e-4);
+        assert!((hlg_oetf(hlg_inverse_oetf(0.7)) - 0.7).abs() < 1e-4);
+
+        for tf in [TransferFunction::pq(1000.0), TransferFunction::hlg()] {
+            for v in (0..=255).step_by(5) {
+                let (d, _, _) = tf.decode((v, v, v));
+                let (e, _, _) = tf.encode((d, d, d));
+                // The PQ levels above the 1000 cd/m^2 peak are clipped.
+                if d < 255 {
+                    assert!(e.abs_diff(v) <= 2, "{} -> {} -> {}", v, d, e);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use crate::analysis::estimate_noise;
+use crate::{AutomaticClahe, AutomaticClaheOptions, BlockSize, ChannelLayout};
+
+/// Option values evaluated by [`AutomaticClahe::tune()`].
+///
+/// Every combination is tried; the options of the enhancer are used for everything else.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParameterGrid {
+    pub block_sizes: Vec<usize>,
+    pub alphas: Vec<f32>,
+    pub ps: Vec<f32>,
+    pub d_thresholds: Vec<u8>,
+}
+
+impl Default for ParameterGrid {
+    fn default() -> Self {
+        Self {
+            block_sizes: vec![16, 32, 64],
+            alphas: vec![40.0, 100.0, 200.0],
+            ps: vec![1.0, 1.5, 3.0],
+            d_thresholds: vec![30, 50, 80],
+        }
+    }
+}
+
+/// `alpha` values evaluated by [`AutomaticClahe::select_alpha()`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlphaSearch {
+    pub alphas: Vec<f32>,
+
+    /// Entropy (in bits) subtracted per gray level of noise sigma the enhancement adds.
+    pub noise_penalty: f32,
+}
+
+impl Default for AlphaSearch {
+    fn default() -> Self {
+        Self {
+            alphas: vec![25.0, 50.0, 100.0, 150.0, 200.0, 300.0],
+            noise_penalty: 0.25,
+        }
+    }
+}
+
+/// An evaluated option set.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub options: AutomaticClaheOptions,
+
+    /// Shannon entropy (in bits) of the enhanced luminance histogram.
+    pub entropy: f32,
+
+    /// Standard deviation of the enhanced luminance.
+    pub contrast: f32,
+
+    /// `entropy / 8 + contrast / 128`; higher is better.
+    pub score: f32,
+}
+
+impl AutomaticClahe {
+    /// Longest side of the downscaled image that [`tune()`](Self::tune) evaluates.
+    pub const TUNING_SIZE: usize = 256;
+
+    /// Enhances a downscale of the image with every option combination of `grid` and returns the
+    /// results ordered from the best to the worst score.
+    ///
+    /// Block sizes are scaled down along with the image.
+    pub fn tune(
+        &self,
+        pixels: &[u8],
+        width: usize,
+        layout: ChannelLayout,
+        grid: &ParameterGrid,
+    ) -> Vec<Candidate> {
+        let height = pixels.len() / layout.channels() / width;
+        let factor = std::cmp::max(width, height)
+            .div_ceil(Self::TUNING_SIZE)
+            .max(1);
+        let (small, small_width) = downscale(pixels, width, layout, factor);
+
+        let mut candidates = Vec::new();
+        for &block_size in &grid.block_sizes {
+            for &alpha in &grid.alphas {
+                for &p in &grid.ps {
+                    for &d_threshold in &grid.d_thresholds {
+                        let options = AutomaticClaheOptions {
+                            block_size: BlockSize::Fixed,
+                            block_width: block_size,
+                            block_height: block_size,
+                            alpha,
+                            p,
+                            d_threshold,
+                            ..self.options.clone()
+                        };
+                        let mut scaled = options.clone();
+                        scaled.block_width = (block_size / factor).max(1);
+                        scaled.block_height = scaled.block_width;
+
+                        let clahe = AutomaticClahe::with_options(scaled);
+                        let mut enhanced = small.clone();
+                        if clahe
+                            .try_enhance_image(&mut enhanced, small_width, ChannelLayout::RGB)
+                            .is_err()
+                        {
+                            continue;
+                        }
+                        let luminances = clahe.extract_luminances(&enhanced, ChannelLayout::RGB);
+                        let (entropy, contrast) = metrics(&luminances);
+                        candidates.push(Candidate {
+                            options,
+                            entropy,
+                            contrast,
+                            score: entropy / 8.0 + contrast / 128.0,
+                        });
+                    }
+                }
+            }
+        }
+        candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+        candidates
+    }
+
+    /// Picks the `alpha` of `search` that maximizes the entropy of the enhanced luminance minus
+    /// the noise penalty, evaluated on a downscale of the image like [`tune()`](Self::tune).
+    ///
+    /// Returns the configured `alpha` if no candidate could be evaluated.
+    pub fn select_alpha(
+        &self,
+        pixels: &[u8],
+        width: usize,
+        layout: ChannelLayout,
+        search: &AlphaSearch,
+    ) -> f32 {
+        let height = pixels.len() / layout.channels() / width;
+        let factor = std::cmp::max(width, height)
+            .div_ceil(Self::TUNING_SIZE)
+            .max(1);
+        let (small, small_width) = downscale(pixels, width, layout, factor);
+        let noise = estimate_noise(
+            &self.extract_luminances(&small, ChannelLayout::RGB),
+            small_width,
+        );
+
+        let mut best = (f32::NEG_INFINITY, self.options.alpha);
+        for &alpha in &search.alphas {
+            let mut options = AutomaticClaheOptions {
+                alpha,
+                ..self.options.clone()
+            };
+            if options.block_size == BlockSize::Fixed {
+                options.block_width = (options.block_width / factor).max(1);
+                options.block_height = (options.block_height / factor).max(1);
+            }
+            let clahe = AutomaticClahe::with_options(options);
+            let mut enhanced = small.clone();
+            if clahe
+                .try_enhance_image(&mut enhanced, small_width, ChannelLayout::RGB)
+                .is_err()
+            {
+                continue;
+            }
+            let luminances = clahe.extract_luminances(&enhanced, ChannelLayout::RGB);
+            let (entropy, _) = metrics(&luminances);
+            let added_noise = (estimate_noise(&luminances, small_width) - noise).max(0.0);
+            let score = entropy - search.noise_penalty * added_noise;
+            if score > best.0 {
+                best = (score, alpha);
+            }
+        }
+        best.1
+    }
+}
+
+// Box-filters the image by `factor` into an RGB buffer.
+fn downscale(
+    pixels: &[u8],
+    width: usize,
+    layout: ChannelLayout,
+    factor: usize,
+) -> (Vec<u8>, usize) {
+    let channels = layout.channels();
+    let height = pixels.len() / channels / width;
+    let (small_width, small_height) = ((width / factor).max(1), (height / factor).max(1));
+
+    let mut small = Vec::with_capacity(small_width * small_height * 3);
+    for sy in 0..small_height {
+        for sx in 0..small_width {
+            let mut sum = [0usize; 3];
+            let mut n = 0;
+            for y in sy * factor..((sy + 1) * factor).min(height) {
+                for x in sx * factor..((sx + 1) * factor).min(width) {
+                    let i = (y * width + x) * channels;
+                    let (r, g, b) = layout.rgb(&pixels[i..i + channels]);
+                    sum[0] += usize::from(r);
+                    sum[1] += usize::from(g);
+                    sum[2] += usize::from(b);
+                    n += 1;
+                }
+            }
+            small.extend(sum.map(|s| (s / n.max(1)) as u8));
+        }
+    }
+    (small, small_width)
+}
+
+fn metrics(luminances: &[u8]) -> (f32, f32) {
+    let mut histogram = [0usize; 256];
+    for &l in luminances {
+        histogram[usize::from(l)] += 1;
+    }
+    let n = luminances.len() as f32;
+    let entropy = histogram
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f32 / n;
+            -p * p.log2()
+        })
+        .sum();
+    let mean = luminances.iter().map(|&l| f32::from(l)).sum::<f32>() / n;
+    let variance = luminances
+        .iter()
+        .map(|&l| (f32::from(l) - mean).powi(2))
+        .sum::<f32>()
+        / n;
+    (entropy, variance.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tune_ranks_candidates() {
+        let width = 600;
+        let pixels = (0..width * 400)
+            .flat_map(|i| {
+                let l = (60 + (i % width) / 20 + i / width / 40) as u8;
+                [l, l, l / 2, 255]
+            })
+            .collect::<Vec<_>>();
+        let grid = ParameterGrid {
+            block_sizes: vec![32, 64],
+            alphas: vec![40.0, 200.0],
+            ps: vec![1.5],
+            d_thresholds: vec![50],
+        };
+
+        let candidates = AutomaticClahe::new().tune(&pixels, width, ChannelLayout::RGBA, &grid);
+        assert_eq!(candidates.len(), 4);
+        assert!(candidates.windows(2).all(|w| w[0].score >= w[1].score));
+        assert!(candidates
+            .iter()
+            .all(|c| c.entropy > 0.0 && c.contrast > 0.0));
+        assert!([32, 64].contains(&candidates[0].options.block_width));
+    }
+
+    #[test]
+    fn select_alpha_picks_a_candidate() {
+        let width = 128;
+        let pixels = (0..width * 96)
+            .flat_map(|i| {
+                let l = ((i % width) + (i * 7919 % 9)) as u8;
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let clahe = AutomaticClahe::new();
+        let select = |search| clahe.select_alpha(&pixels, width, ChannelLayout::RGB, &search);
+
+        let search = AlphaSearch::default();
+        assert!(search.alphas.contains(&select(search.clone())));
+        let alphas = vec![40.0];
+        assert_eq!(
+            select(AlphaSearch {
+                alphas,
+                ..search.clone()
+            }),
+            40.0
+        );
+        // Nothing to evaluate keeps the configured alpha.
+        let alphas = Vec::new();
+        assert_eq!(
+            select(AlphaSearch { alphas, ..search }),
+            clahe.options.alpha
+        );
+    }
+}
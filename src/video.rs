@@ -0,0 +1,294 @@
+use crate::{
+    AutomaticClahe, Block, BlockLayout, ChannelLayout, ClaheError, Histogram, OptionsError, Region,
+};
+
+/// Enhances the frames of a video with [`AutomaticClahe`], blending the block LUTs of every
+/// frame with those of the previous frames to remove the flicker of enhancing each frame
+/// independently.
+///
+/// The history is dropped at scene cuts, which are detected by the distance between the
+/// luminance histograms of consecutive frames.
+#[derive(Debug)]
+pub struct VideoClahe {
+    clahe: AutomaticClahe,
+    smoothing: f32,
+    scene_change_threshold: f32,
+    max_lut_delta: Option<f32>,
+    histories: Vec<LutHistory>,
+    previous_histogram: Option<Histogram>,
+}
+
+impl VideoClahe {
+    /// `smoothing` is the weight of the previous LUTs in `[0.0, 1.0)`: `0.0` enhances every
+    /// frame independently, and with `0.9` a new scene is fully adopted after about 30 frames.
+    pub fn new(clahe: AutomaticClahe, smoothing: f32) -> Self {
+        Self {
+            clahe,
+            smoothing,
+            scene_change_threshold: 0.5,
+            max_lut_delta: None,
+            histories: Vec::new(),
+            previous_histogram: None,
+        }
+    }
+
+    /// Sets the total variation distance between the normalized histograms of consecutive
+    /// frames (in `(0.0, 1.0]`) above which a frame starts a new scene; `1.0` disables the
+    /// detection (default: `0.5`).
+    pub fn with_scene_change_threshold(mut self, threshold: f32) -> Self {
+        self.scene_change_threshold = threshold;
+        self
+    }
+
+    /// Limits the change of every LUT entry between consecutive frames to `max_delta`
+    /// luminance levels (after the smoothing), so that no transition within a scene flickers.
+    pub fn with_max_lut_delta(mut self, max_delta: f32) -> Self {
+        self.max_lut_delta = Some(max_delta);
+        self
+    }
+
+    /// Enhances the next frame of the video (see [`AutomaticClahe::enhance_image()`]).
+    pub fn enhance_frame(&mut self, pixels: &mut [u8], width: usize, layout: ChannelLayout) {
+        if let Some(dehazed) = self.clahe.dehazed(pixels, width, layout) {
+            pixels.copy_from_slice(&dehazed);
+        }
+        let mut planes = self.clahe.extract_planes(pixels, width, layout, None);
+        let mut histogram = Histogram([0; 256]);
+        for image in planes.images() {
+            for (bin, (_, count)) in histogram.0.iter_mut().zip(image.histogram().iter()) {
+                *bin += count;
+            }
+        }
+        if let Some(previous) = &self.previous_histogram {
+            if distance(previous, &histogram) > self.scene_change_threshold {
+                self.histories.clear();
+            }
+        }
+        self.previous_histogram = Some(histogram);
+
+        let (smoothing, max_delta) = (self.smoothing, self.max_lut_delta);
+        self.histories.resize_with(planes.images().len(), || {
+            LutHistory::new(smoothing, max_delta)
+        });
+        self.clahe
+            .enhance_planes_with(&mut planes, Some(&mut self.histories), None, None);
+        self.clahe.write_back_planes(pixels, layout, &planes);
+    }
+
+    pub fn enhance_rgba_frame(&mut self, pixels: &mut [u8], width: usize) {
+        self.enhance_frame(pixels, width, ChannelLayout::RGBA);
+    }
+
+    /// Validating variant of [`enhance_frame()`](Self::enhance_frame).
+    pub fn try_enhance_frame(
+        &mut self,
+        pixels: &mut [u8],
+        width: usize,
+        layout: ChannelLayout,
+    ) -> Result<(), ClaheError> {
+        if !(0.0..1.0).contains(&self.smoothing) {
+            return Err(OptionsError::InvalidTemporalSmoothing(self.smoothing).into());
+        }
+        if !(self.scene_change_threshold > 0.0 && self.scene_change_threshold <= 1.0) {
+            return Err(
+                OptionsError::InvalidSceneChangeThreshold(self.scene_change_threshold).into(),
+            );
+        }
+        if let Some(max_delta) = self.max_lut_delta {
+            if !(max_delta.is_finite() && max_delta > 0.0) {
+                return Err(OptionsError::InvalidMaxLutDelta(max_delta).into());
+            }
+        }
+        self.clahe
+            .validate(pixels.len(), layout.channels(), width)?;
+        self.enhance_frame(pixels, width, layout);
+        Ok(())
+    }
+
+    /// Forgets the previous frames, e.g. at a known scene cut or seek.
+    pub fn reset(&mut self) {
+        self.histories.clear();
+        self.previous_histogram = None;
+    }
+}
+
+// Total variation distance between the normalized histograms, in `[0.0, 1.0]`.
+fn distance(a: &Histogram, b: &Histogram) -> f32 {
+    let (count_a, count_b) = (a.count().max(1) as f32, b.count().max(1) as f32);
+    a.0.iter()
+        .zip(&b.0)
+        .map(|(&a, &b)| (a as f32 / count_a - b as f32 / count_b).abs())
+        .sum::<f32>()
+        / 2.0
+}
+
+// The smoothed LUTs of one plane.
+#[derive(Debug)]
+pub(crate) struct LutHistory {
+    smoothing: f32,
+    max_delta: Option<f32>,
+    tables: Vec<(Region, [f32; 256])>,
+}
+
+impl LutHistory {
+    fn new(smoothing: f32, max_delta: Option<f32>) -> Self {
+        Self {
+            smoothing,
+            max_delta,
+            tables: Vec::new(),
+        }
+    }
+
+    // Blends the LUTs of `layouts` with the history and records the result; the history starts
+    // over when the blocks changed (resolution, quadtree splits, ...).
+    pub(crate) fn smooth(&mut self, layouts: &mut [BlockLayout]) {
+        let mut blocks = layouts
+            .iter_mut()
+            .flat_map(|layout| layout.blocks_mut())
+            .collect::<Vec<&mut Block>>();
+        let matches = blocks.len() == self.tables.len()
+            && blocks
+                .iter()
+                .zip(&self.tables)
+                .all(|(block, (region, _))| block.region == *region);
+        if !matches {
+            self.tables = blocks
+                .iter()
+                .map(|block| (block.region, block.table))
+                .collect();
+            return;
+        }
+        for (block, (_, table)) in blocks.iter_mut().zip(&mut self.tables) {
+            for (l, previous) in block.table.iter_mut().zip(table.iter_mut()) {
+                *l = self.smoothing * *previous + (1.0 - self.smoothing) * *l;
+                if let Some(max_delta) = self.max_delta {
+                    *l = l.clamp(*previous - max_delta, *previous + max_delta);
+                }
+                *previous = *l;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AutomaticClaheOptions, BlockGrid, Point};
+
+    #[test]
+    fn previous_frames_damp_lut_changes() {
+        let width = 64;
+        let frame = |offset: usize| {
+            (0..width * 64)
+                .flat_map(|i| {
+                    let l = ((i % width + i / width) / 2 + offset) as u8;
+                    [l, l, l, 255]
+                })
+                .collect::<Vec<_>>()
+        };
+        let clahe = || {
+            AutomaticClahe::with_options(AutomaticClaheOptions {
+                block_width: 32,
+                block_height: 32,
+                ..Default::default()
+            })
+        };
+        let mut independent = frame(100);
+        clahe().enhance_rgba_image(&mut independent, width);
+        let mut first = frame(10);
+        clahe().enhance_rgba_image(&mut first, width);
+
+        let mut video = VideoClahe::new(clahe(), 0.9).with_scene_change_threshold(1.0);
+        video.enhance_rgba_frame(&mut frame(10), width);
+        let mut smoothed = frame(100);
+        video.enhance_rgba_frame(&mut smoothed, width);
+        let distance = |a: &[u8], b: &[u8]| {
+            a.iter()
+                .zip(b)
+                .map(|(&a, &b)| u32::from(a.abs_diff(b)))
+                .sum::<u32>()
+        };
+        assert_ne!(smoothed, independent);
+        assert!(distance(&smoothed, &first) < distance(&independent, &first));
+
+        video.reset();
+        let mut reset = frame(100);
+        video.enhance_rgba_frame(&mut reset, width);
+        assert_eq!(reset, independent);
+
+        let mut unsmoothed = VideoClahe::new(clahe(), 0.0);
+        unsmoothed.enhance_rgba_frame(&mut frame(10), width);
+        let mut pixels = frame(100);
+        unsmoothed.enhance_rgba_frame(&mut pixels, width);
+        assert_eq!(pixels, independent);
+
+        let mut invalid = VideoClahe::new(clahe(), 1.0);
+        assert!(invalid
+            .try_enhance_frame(&mut pixels, width, ChannelLayout::RGBA)
+            .is_err());
+    }
+
+    #[test]
+    fn scene_cuts_reset_the_history() {
+        let width = 64;
+        let frame = |offset: usize, step: usize| {
+            (0..width * 64)
+                .flat_map(|i| {
+                    let l = ((i % width + i / width) / step + offset) as u8;
+                    [l, l, l]
+                })
+                .collect::<Vec<_>>()
+        };
+        let clahe = AutomaticClahe::new;
+        let independent = |pixels: &[u8]| {
+            let mut pixels = pixels.to_vec();
+            clahe().enhance_rgb_image(&mut pixels, width);
+            pixels
+        };
+
+        let mut video = VideoClahe::new(clahe(), 0.9);
+        video.enhance_frame(&mut frame(10, 2), width, ChannelLayout::RGB);
+        // A slight change keeps the history, a cut drops it.
+        let mut pixels = frame(14, 2);
+        video.enhance_frame(&mut pixels, width, ChannelLayout::RGB);
+        assert_ne!(pixels, independent(&frame(14, 2)));
+        let mut pixels = frame(120, 1);
+        video.enhance_frame(&mut pixels, width, ChannelLayout::RGB);
+        assert_eq!(pixels, independent(&frame(120, 1)));
+
+        let mut invalid = VideoClahe::new(clahe(), 0.5).with_scene_change_threshold(0.0);
+        assert!(invalid
+            .try_enhance_frame(&mut pixels, width, ChannelLayout::RGB)
+            .is_err());
+    }
+
+    #[test]
+    fn lut_changes_are_bounded() {
+        let region = Region {
+            start: Point::new(0, 0),
+            end: Point::new(8, 8),
+        };
+        let layout = |l: f32| {
+            BlockLayout::Grid(BlockGrid {
+                blocks: vec![Block {
+                    table: [l; 256],
+                    ..Block::identity(region)
+                }],
+                block_width: 8,
+                block_height: 8,
+                line_blocks: 1,
+                aligned_width: 8,
+                aligned_height: 8,
+            })
+        };
+        let mut history = LutHistory::new(0.0, Some(4.0));
+        for (l, expected) in [(100.0, 100.0), (200.0, 104.0), (200.0, 108.0), (0.0, 104.0)] {
+            let mut layouts = [layout(l)];
+            history.smooth(&mut layouts);
+            assert!(layouts[0].blocks_mut()[0]
+                .table
+                .iter()
+                .all(|&t| t == expected));
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use crate::{AutomaticClahe, Block, GlobalStats, Histogram, Point, Region, ALL_LEVELS};
+
+// The per-axis block extents: the remainder is merged into the last block.
+fn axis_blocks(len: usize, size: usize) -> Vec<(usize, usize)> {
+    let n = std::cmp::max(len / size, 1);
+    (0..n)
+        .map(|i| (i * size, if i + 1 == n { len } else { (i + 1) * size }))
+        .collect()
+}
+
+// For each coordinate, the two nearest block indices and the weight of the second one.
+fn axis_weights(len: usize, blocks: &[(usize, usize)]) -> Vec<(usize, usize, f32)> {
+    let centers = blocks
+        .iter()
+        .map(|&(start, end)| (end - start) as f32 / 2.0 + start as f32)
+        .collect::<Vec<_>>();
+    (0..len)
+        .map(|c| {
+            let c = c as f32;
+            match centers.iter().rposition(|&center| center <= c) {
+                None => (0, 0, 0.0),
+                Some(i) if i + 1 == centers.len() => (i, i, 0.0),
+                Some(i) => (i, i + 1, (c - centers[i]) / (centers[i + 1] - centers[i])),
+            }
+        })
+        .collect()
+}
+
+impl AutomaticClahe {
+    /// Enhances an 8-bit volume (e.g. a CT or MRI scan) stored slice by slice.
+    ///
+    /// The blocks are `block_width x block_height x block_depth` voxels and their LUTs are
+    /// interpolated trilinearly.
+    pub fn enhance_volume(&self, voxels: &mut [u8], width: usize, height: usize, depth: usize) {
+        assert_eq!(voxels.len(), width * height * depth);
+        if voxels.is_empty() {
+            return;
+        }
+        let index = |x: usize, y: usize, z: usize| (z * height + y) * width + x;
+
+        let xs = axis_blocks(width, self.options.block_width);
+        let ys = axis_blocks(height, self.options.block_height);
+        let zs = axis_blocks(depth, self.options.block_depth);
+        let stats = GlobalStats::new(
+            &Histogram::new(voxels.iter().map(|&l| (l, 1))),
+            self.options.l_alpha_percentile,
+        );
+        let source: &[u8] = voxels;
+        let mut blocks = Vec::with_capacity(xs.len() * ys.len() * zs.len());
+        for &(z0, z1) in &zs {
+            for &(y0, y1) in &ys {
+                for &(x0, x1) in &xs {
+                    let region = Region {
+                        start: Point::new(x0, y0),
+                        end: Point::new(x1, y1),
+                    };
+                    let block = if self.options.identity_luts {
+                        Block::identity(region)
+                    } else {
+                        let histogram = Histogram::new((z0..z1).flat_map(|z| {
+                            (y0..y1).flat_map(move |y| {
+                                source[index(x0, y, z)..index(x1, y, z)]
+                                    .iter()
+                                    .map(|&l| (l, 1))
+                            })
+                        }));
+                        Block::from_histogram(&histogram, &stats, self, region, 0.0, ALL_LEVELS)
+                    };
+                    blocks.push(block);
+                }
+            }
+        }
+        let block = |bx: usize, by: usize, bz: usize| &blocks[(bz * ys.len() + by) * xs.len() + bx];
+
+        let wx = axis_weights(width, &xs);
+        let wy = axis_weights(height, &ys);
+        let wz = axis_weights(depth, &zs);
+        for (z, &(z0, z1, tz)) in wz.iter().enumerate() {
+            for (y, &(y0, y1, ty)) in wy.iter().enumerate() {
+                for (x, &(x0, x1, tx)) in wx.iter().enumerate() {
+                    let i = index(x, y, z);
+                    let l0 = voxels[i];
+                    if self.options.is_protected(l0) {
+                        continue;
+                    }
+                    let lerp_x = |by, bz| {
+                        let a = block(x0, by, bz).enhance(l0);
+                        let b = block(x1, by, bz).enhance(l0);
+                        a + (b - a) * tx
+                    };
+                    let lerp_y = |bz| {
+                        let a = lerp_x(y0, bz);
+                        a + (lerp_x(y1, bz) - a) * ty
+                    };
+                    let a = lerp_y(z0);
+                    let l = a + (lerp_y(z1) - a) * tz;
+                    voxels[i] = self.output_luminance(l0, l);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AutomaticClahe, AutomaticClaheOptions};
+
+    #[test]
+    fn enhance_volume_works() {
+        let (width, height, depth) = (40, 30, 20);
+        let original = (0..width * height * depth)
+            .map(|i| (20 + i % width + (i / (width * height)) * 3) as u8)
+            .collect::<Vec<_>>();
+        let clahe = AutomaticClahe::with_options(AutomaticClaheOptions {
+            block_width: 16,
+            block_height: 16,
+            block_depth: 8,
+            ..Default::default()
+        });
+        let mut voxels = original.clone();
+        clahe.enhance_volume(&mut voxels, width, height, depth);
+        assert_ne!(voxels, original);
+
+        // A volume of a single slice matches the 2D enhancement of a single-block image.
+        let mut slice = original[..16 * 16].to_vec();
+        let mut pixels = slice.iter().flat_map(|&l| [l, l, l]).collect::<Vec<_>>();
+        clahe.enhance_volume(&mut slice, 16, 16, 1);
+        clahe.enhance_rgb_image(&mut pixels, 16);
+        let enhanced = pixels.chunks(3).map(|p| p[0]).collect::<Vec<_>>();
+        assert_eq!(slice, enhanced);
+    }
+}
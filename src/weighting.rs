@@ -0,0 +1,87 @@
+/// How the clipped block histogram is transformed into the weighting distribution whose CDF
+/// drives the gamma correction, see
+/// [`AutomaticClahe::with_weighting()`](crate::AutomaticClahe::with_weighting).
+pub trait WeightingStrategy: std::fmt::Debug + Send + Sync {
+    /// Returns the (unnormalized) weighting distribution of the normalized histogram `pdf`.
+    fn weight(&self, pdf: &[f32; 256]) -> [f32; 256];
+}
+
+/// Rescales the histogram linearly onto `[0, max]` (the default).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinearWeighting;
+
+impl WeightingStrategy for LinearWeighting {
+    fn weight(&self, pdf: &[f32; 256]) -> [f32; 256] {
+        AlphaPowerWeighting { alpha: 1.0 }.weight(pdf)
+    }
+}
+
+/// The weighting distribution `max * ((pdf - min) / (max - min))^alpha` of the original
+/// AGCWD paper (Huang et al.), where `alpha < 1.0` raises the weight of rare luminances.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AlphaPowerWeighting {
+    pub alpha: f32,
+}
+
+impl Default for AlphaPowerWeighting {
+    fn default() -> Self {
+        Self { alpha: 0.5 }
+    }
+}
+
+impl WeightingStrategy for AlphaPowerWeighting {
+    fn weight(&self, pdf: &[f32; 256]) -> [f32; 256] {
+        let mut max = pdf[0];
+        let mut min = pdf[0];
+        for &x in &pdf[1..] {
+            max = max.max(x);
+            min = min.min(x);
+        }
+
+        let mut pdf_w = *pdf;
+        let range = max - min + f32::EPSILON;
+        for x in &mut pdf_w {
+            *x = max * ((*x - min) / range).powf(self.alpha);
+        }
+        pdf_w
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AutomaticClahe;
+
+    #[test]
+    fn weighting_strategies_are_applied() {
+        let mut pdf = [0.0; 256];
+        pdf[50] = 0.75;
+        pdf[100] = 0.25;
+        let linear = LinearWeighting.weight(&pdf);
+        let power = AlphaPowerWeighting::default().weight(&pdf);
+        assert!((linear[50] - 0.75).abs() < 1e-6 && (linear[100] - 0.25).abs() < 1e-6);
+        assert!((power[50] - 0.75).abs() < 1e-6 && power[100] > linear[100]);
+        assert_eq!(power[0], 0.0);
+
+        let width = 64;
+        let original = (0..width * 32)
+            .flat_map(|i| {
+                let l = (30 + (i % width) * 2 + i / width) as u8;
+                [l, l, l]
+            })
+            .collect::<Vec<_>>();
+        let enhance = |clahe: AutomaticClahe| {
+            let mut pixels = original.clone();
+            clahe.enhance_rgb_image(&mut pixels, width);
+            pixels
+        };
+        assert_eq!(
+            enhance(AutomaticClahe::new().with_weighting(LinearWeighting)),
+            enhance(AutomaticClahe::new())
+        );
+        assert_ne!(
+            enhance(AutomaticClahe::new().with_weighting(AlphaPowerWeighting::default())),
+            enhance(AutomaticClahe::new())
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use crate::AutomaticClahe;
+use zune_core::bit_depth::BitDepth;
+use zune_core::colorspace::ColorSpace;
+use zune_image::channel::ChannelErrors;
+use zune_image::image::Image;
+
+#[derive(Debug)]
+pub enum ZuneImageError {
+    UnsupportedDepth(BitDepth),
+    UnsupportedColorSpace(ColorSpace),
+    /// The named option (or stage) has no planar implementation.
+    UnsupportedOption(&'static str),
+    Channel(ChannelErrors),
+}
+
+impl std::fmt::Display for ZuneImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnsupportedDepth(depth) => write!(f, "unsupported bit depth: {:?}", depth),
+            Self::UnsupportedColorSpace(colorspace) => {
+                write!(f, "unsupported color space: {:?}", colorspace)
+            }
+            Self::UnsupportedOption(option) => {
+                write!(f, "{} is not supported for zune-image images", option)
+            }
+            Self::Channel(e) => write!(f, "channel error: {:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for ZuneImageError {}
+
+impl From<ChannelErrors> for ZuneImageError {
+    fn from(e: ChannelErrors) -> Self {
+        Self::Channel(e)
+    }
+}
+
+impl AutomaticClahe {
+    /// Enhances every frame of a `zune_image` image in place.
+    ///
+    /// Only 8-bit images in the RGB, BGR (with or without alpha), ARGB and Luma(A) color spaces
+    /// are supported. Alpha channels are left untouched, and the dehaze pre-pass is not
+    /// supported.
+    pub fn enhance_zune_image(&self, image: &mut Image) -> Result<(), ZuneImageError> {
+        #[cfg(feature = "dehaze")]
+        if self.dehaze.is_some() {
+            return Err(ZuneImageError::UnsupportedOption("dehaze"));
+        }
+        let depth = image.depth();
+        if depth != BitDepth::Eight {
+            return Err(ZuneImageError::UnsupportedDepth(depth));
+        }
+
+        let colorspace = image.colorspace();
+        let (width, _) = image.dimensions();
+        for frame in image.frames_mut() {
+            match colorspace {
+                ColorSpace::RGB | ColorSpace::RGBA | ColorSpace::ARGB => {
+                    let [r, g, b] = frame.channels_mut(colorspace, true) else {
+                        unreachable!()
+                    };
+                    self.enhance_rgb_planes(
+                        r.reinterpret_as_mut()?,
+                        g.reinterpret_as_mut()?,
+                        b.reinterpret_as_mut()?,
+                        width,
+                    );
+                }
+                ColorSpace::BGR | ColorSpace::BGRA => {
+                    let [b, g, r] = frame.channels_mut(colorspace, true) else {
+                        unreachable!()
+                    };
+                    self.enhance_rgb_planes(
+                        r.reinterpret_as_mut()?,
+                        g.reinterpret_as_mut()?,
+                        b.reinterpret_as_mut()?,
+                        width,
+                    );
+                }
+                ColorSpace::Luma | ColorSpace::LumaA => {
+                    let [luma] = frame.channels_mut(colorspace, true) else {
+                        unreachable!()
+                    };
+                    self.enhance_luma_plane(luma.reinterpret_as_mut()?, width);
+                }
+                _ => return Err(ZuneImageError::UnsupportedColorSpace(colorspace)),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AutomaticClaheOptions, ChannelLayout};
+
+    #[test]
+    fn zune_images_match_the_interleaved_enhancement() {
+        let (width, height) = (48, 40);
+        let rgb = (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                [(x * 5) as u8, (y * 6) as u8, ((x * y) % 251) as u8]
+            })
+            .collect::<Vec<_>>();
+        let rgba = rgb
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 128])
+            .collect::<Vec<_>>();
+        let gray = rgb.iter().step_by(3).copied().collect::<Vec<_>>();
+        for options in [
+            AutomaticClaheOptions::default(),
+            AutomaticClaheOptions {
+                color_space: crate::ColorSpace::PerChannel,
+                protected_ranges: vec![200..=255],
+                ..Default::default()
+            },
+        ] {
+            let clahe = AutomaticClahe::with_options(options)
+                .with_transfer_function(crate::TransferFunction::hlg());
+            for (pixels, colorspace, layout) in [
+                (&rgb, ColorSpace::RGB, ChannelLayout::RGB),
+                (&rgba, ColorSpace::RGBA, ChannelLayout::RGBA),
+                (&rgb, ColorSpace::BGR, ChannelLayout::BGR),
+            ] {
+                let mut expected = pixels.clone();
+                clahe.enhance_image(&mut expected, width, layout);
+                let mut image = Image::from_u8(pixels, width, height, colorspace);
+                clahe.enhance_zune_image(&mut image).unwrap();
+                assert_eq!(image.flatten_to_u8()[0], expected, "{:?}", colorspace);
+            }
+
+            let mut expected = gray.iter().flat_map(|&l| [l; 3]).collect::<Vec<_>>();
+            clahe.enhance_image(&mut expected, width, ChannelLayout::RGB);
+            let mut image = Image::from_u8(&gray, width, height, ColorSpace::Luma);
+            clahe.enhance_zune_image(&mut image).unwrap();
+            assert_eq!(
+                image.flatten_to_u8()[0],
+                expected.iter().step_by(3).copied().collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn unsupported_zune_images_are_rejected() {
+        let clahe = AutomaticClahe::new();
+        let mut image = Image::from_u16(&[0; 32 * 32 * 3], 32, 32, ColorSpace::RGB);
+        assert!(matches!(
+            clahe.enhance_zune_image(&mut image),
+            Err(ZuneImageError::UnsupportedDepth(BitDepth::Sixteen))
+        ));
+        let mut image = Image::from_u8(&[0; 32 * 32 * 4], 32, 32, ColorSpace::CMYK);
+        assert!(matches!(
+            clahe.enhance_zune_image(&mut image),
+            Err(ZuneImageError::UnsupportedColorSpace(ColorSpace::CMYK))
+        ));
+
+        #[cfg(feature = "dehaze")]
+        {
+            let clahe = AutomaticClahe::new().with_dehaze(Default::default());
+            let mut image = Image::from_u8(&[0; 32 * 32 * 3], 32, 32, ColorSpace::RGB);
+            assert!(matches!(
+                clahe.enhance_zune_image(&mut image),
+                Err(ZuneImageError::UnsupportedOption("dehaze"))
+            ));
+        }
+    }
+}